@@ -0,0 +1,103 @@
+//! Overflow, division-by-zero and constant-condition warnings
+//!
+//! Every expression is run through the constant evaluator, which reports the 16-bit overflows and
+//! divisions by zero of its constant parts. An `if` or `while` condition comparing constants is
+//! reported as always true or always false. Plain `true`/`false` conditions are left alone, since
+//! `while (true)` is a common idiom.
+
+use crate::ast;
+use crate::const_eval::{self, Evaluator, IssueKind};
+use crate::diagnostics::Diagnostic;
+use crate::visit::{self, Visitor};
+
+pub const CONSTANT_OVERFLOW: &str = "J0401";
+pub const DIVISION_BY_ZERO: &str = "J0402";
+pub const CONSTANT_CONDITION: &str = "J0403";
+
+pub fn check_class(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    let mut checker = Checker { diagnostics };
+    checker.visit_class(class);
+}
+
+struct Checker<'a> {
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'source> Visitor<'source> for Checker<'a> {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'source>) {
+        let (keyword, condition) = match stmt {
+            ast::Stmt::If(if_stmt) => ("if", &if_stmt.condition),
+            ast::Stmt::While(while_stmt) => ("while", &while_stmt.condition),
+            _ => return visit::walk_stmt(self, stmt),
+        };
+        if has_comparison(condition)
+            && let Some(value) = const_eval::eval(condition)
+        {
+            let always = if value == const_eval::FALSE {
+                "false"
+            } else {
+                "true"
+            };
+            self.diagnostics.push(
+                Diagnostic::warning(
+                    format!("this `{}` condition is always {}", keyword, always),
+                    condition.span,
+                )
+                .with_code(CONSTANT_CONDITION),
+            );
+        }
+        visit::walk_stmt(self, stmt);
+    }
+
+    /// Evaluates a whole expression. The evaluator walks the subexpressions itself, so they
+    /// aren't visited again.
+    fn visit_expression(&mut self, expr: &ast::Expression<'source>) {
+        let mut evaluator = Evaluator::new();
+        evaluator.expression(expr);
+        for issue in evaluator.issues {
+            let diagnostic = match issue.kind {
+                IssueKind::LiteralOutOfRange(n) => Diagnostic::warning(
+                    format!("integer constant {} is out of range", n),
+                    issue.span,
+                )
+                .with_code(CONSTANT_OVERFLOW)
+                .with_note("Jack integer constants range from 0 to 32767"),
+                IssueKind::Overflow {
+                    expr,
+                    exact,
+                    wrapped,
+                } => Diagnostic::warning(
+                    format!("constant expression overflows: `{}` is {}", expr, exact),
+                    issue.span,
+                )
+                .with_code(CONSTANT_OVERFLOW)
+                .with_note(format!(
+                    "16-bit arithmetic wraps around, so the result is {}",
+                    wrapped
+                )),
+                IssueKind::DivisionByZero => Diagnostic::warning("division by zero", issue.span)
+                    .with_code(DIVISION_BY_ZERO)
+                    .with_note("`Math.divide` stops the program with an error"),
+            };
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+/// Returns whether a `<`, `>` or `=` appears anywhere in the expression.
+fn has_comparison(expr: &ast::Expression) -> bool {
+    expr.following_terms
+        .iter()
+        .any(|(op, _)| matches!(op, ast::Op::Lt | ast::Op::Gt | ast::Op::Eq))
+        || std::iter::once(&*expr.leading_term)
+            .chain(expr.following_terms.iter().map(|(_, term)| &**term))
+            .any(term_has_comparison)
+}
+
+fn term_has_comparison(term: &ast::Term) -> bool {
+    match &term.kind {
+        ast::TermKind::Expr(expr) => has_comparison(expr),
+        ast::TermKind::UnaryOperation(_, term) => term_has_comparison(term),
+        _ => false,
+    }
+}
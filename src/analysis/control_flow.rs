@@ -0,0 +1,89 @@
+//! Return-path and reachability analysis
+//!
+//! The VM requires every subroutine to end in an explicit `return`, so a body that can fall off
+//! its closing brace is an error. Statements following an unconditional `return` are reported as
+//! unreachable.
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+
+pub const MISSING_RETURN: &str = "J0001";
+pub const UNREACHABLE_CODE: &str = "J0002";
+
+pub fn check_class(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    for subroutine in &class.subroutines {
+        check_subroutine(subroutine, diagnostics);
+    }
+}
+
+fn check_subroutine(subroutine: &ast::SubroutineDec, diagnostics: &mut Vec<Diagnostic>) {
+    if check_stmts(&subroutine.body.stmts, diagnostics).is_none() {
+        let body = subroutine.body.span;
+        let closing_brace = Span::new(body.end - 1, body.end);
+        let hint = match subroutine.return_ty {
+            ast::SubroutineReturnTy::Void => "add `return;` before the closing brace",
+            ast::SubroutineReturnTy::Type(_) => "add a `return` statement with a value",
+        };
+        diagnostics.push(
+            Diagnostic::error(
+                format!(
+                    "subroutine `{}` may reach its end without returning",
+                    subroutine.name
+                ),
+                closing_brace,
+            )
+            .with_code(MISSING_RETURN)
+            .with_label(subroutine.span, "in this subroutine")
+            .with_note("the VM requires an explicit `return` on every path")
+            .with_note(hint),
+        );
+    }
+}
+
+/// Checks a statement sequence and returns the span of the statement that makes it return on
+/// every path, if any.
+fn check_stmts(stmts: &ast::Stmts, diagnostics: &mut Vec<Diagnostic>) -> Option<Span> {
+    let mut terminator = None;
+    for stmt in &stmts.0 {
+        if let Some(terminator) = terminator {
+            let last = stmts.0.last().unwrap().span();
+            diagnostics.push(
+                Diagnostic::warning("unreachable statement", stmt.span().to(last))
+                    .with_code(UNREACHABLE_CODE)
+                    .with_label(
+                        terminator,
+                        "any code following this statement is unreachable",
+                    ),
+            );
+            break;
+        }
+        if check_stmt(stmt, diagnostics) {
+            terminator = Some(stmt.span());
+        }
+    }
+    terminator
+}
+
+/// Checks a single statement and returns whether it returns on every path.
+fn check_stmt(stmt: &ast::Stmt, diagnostics: &mut Vec<Diagnostic>) -> bool {
+    use ast::Stmt::*;
+    match stmt {
+        Return(_) => true,
+        If(if_stmt) => {
+            let then_returns = check_stmts(&if_stmt.stmts, diagnostics).is_some();
+            let else_returns = if_stmt
+                .else_stmts
+                .as_ref()
+                .is_some_and(|stmts| check_stmts(stmts, diagnostics).is_some());
+            then_returns && else_returns
+        }
+        // The loop body may never run, and we don't evaluate the condition, so a loop never
+        // counts as returning on every path.
+        While(while_stmt) => {
+            check_stmts(&while_stmt.stmts, diagnostics);
+            false
+        }
+        Let(_) | Do(_) => false,
+    }
+}
@@ -0,0 +1,180 @@
+//! Definite-assignment analysis for local variables
+//!
+//! The VM initialises locals to 0, so reading one before it is assigned is not undefined
+//! behaviour, but relying on that is almost always a mistake. This check follows `if` and `while`
+//! control flow and warns about the first read of each local that may happen before any `let`
+//! assigns it.
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use std::collections::HashSet;
+
+pub const UNASSIGNED_READ: &str = "J0003";
+
+pub fn check_class(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    for subroutine in &class.subroutines {
+        check_subroutine(subroutine, diagnostics);
+    }
+}
+
+fn check_subroutine(subroutine: &ast::SubroutineDec, diagnostics: &mut Vec<Diagnostic>) {
+    let locals = subroutine
+        .body
+        .variables
+        .iter()
+        .flat_map(|var_dec| var_dec.names.iter().copied())
+        .collect();
+    let mut checker = Checker {
+        locals,
+        reported: HashSet::new(),
+        diagnostics,
+    };
+    checker.stmts(&subroutine.body.stmts, State::default());
+}
+
+/// The set of locals assigned on every path reaching a program point.
+#[derive(Debug, Default, Clone)]
+struct State<'source> {
+    assigned: HashSet<&'source str>,
+    /// Set after a `return`. An unreachable state is the identity of `join`, and reads in
+    /// unreachable code are not reported.
+    unreachable: bool,
+}
+
+impl<'source> State<'source> {
+    fn join(self, other: Self) -> Self {
+        if self.unreachable {
+            return other;
+        }
+        if other.unreachable {
+            return self;
+        }
+        State {
+            assigned: self
+                .assigned
+                .intersection(&other.assigned)
+                .copied()
+                .collect(),
+            unreachable: false,
+        }
+    }
+}
+
+struct Checker<'source, 'a> {
+    locals: Vec<ast::VarName<'source>>,
+    reported: HashSet<&'source str>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'source, 'a> Checker<'source, 'a> {
+    fn stmts(&mut self, stmts: &ast::Stmts<'source>, mut state: State<'source>) -> State<'source> {
+        for stmt in &stmts.0 {
+            state = self.stmt(stmt, state);
+        }
+        state
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt<'source>, mut state: State<'source>) -> State<'source> {
+        use ast::Stmt::*;
+        match stmt {
+            Let(let_stmt) => {
+                if let Some(ref idx) = let_stmt.idx_expr {
+                    // `let a[i] = ...` writes through `a`, so it reads the array reference
+                    self.read(&let_stmt.var_name, &state);
+                    self.expr(idx, &state);
+                }
+                self.expr(&let_stmt.assign_expr, &state);
+                if let_stmt.idx_expr.is_none() {
+                    state.assigned.insert(let_stmt.var_name.name);
+                }
+                state
+            }
+            If(if_stmt) => {
+                self.expr(&if_stmt.condition, &state);
+                let then_state = self.stmts(&if_stmt.stmts, state.clone());
+                let else_state = match if_stmt.else_stmts {
+                    Some(ref stmts) => self.stmts(stmts, state),
+                    None => state,
+                };
+                then_state.join(else_state)
+            }
+            While(while_stmt) => {
+                // Assignments only ever grow the state, so the state at the end of the body is a
+                // superset of the entry state, and a single pass reaches the fixed point.
+                self.expr(&while_stmt.condition, &state);
+                let body_state = self.stmts(&while_stmt.stmts, state.clone());
+                state.join(body_state)
+            }
+            Do(do_stmt) => {
+                self.call(&do_stmt.call, &state);
+                state
+            }
+            Return(return_stmt) => {
+                if let Some(ref expr) = return_stmt.return_val {
+                    self.expr(expr, &state);
+                }
+                State {
+                    assigned: HashSet::new(),
+                    unreachable: true,
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression<'source>, state: &State<'source>) {
+        self.term(&expr.leading_term, state);
+        for (_, term) in &expr.following_terms {
+            self.term(term, state);
+        }
+    }
+
+    fn term(&mut self, term: &ast::Term<'source>, state: &State<'source>) {
+        use ast::TermKind::*;
+        match &term.kind {
+            IntegerConst(_) | StringConst(_) | KeywordConst(_) => {}
+            VarRef(var_name) => self.read(var_name, state),
+            VarRefWithIdx(var_name, expr) => {
+                self.read(var_name, state);
+                self.expr(expr, state);
+            }
+            SubroutineCall(call) => self.call(call, state),
+            Expr(expr) => self.expr(expr, state),
+            UnaryOperation(_, term) => self.term(term, state),
+        }
+    }
+
+    fn call(&mut self, call: &ast::SubroutineCall<'source>, state: &State<'source>) {
+        // A prefix naming a local is a method call through that variable
+        if let Some(ref prefix) = call.prefix {
+            self.read(prefix, state);
+        }
+        for arg in &call.args.0 {
+            self.expr(arg, state);
+        }
+    }
+
+    fn read(&mut self, var_name: &ast::VarName<'source>, state: &State<'source>) {
+        if state.unreachable
+            || state.assigned.contains(var_name.name)
+            || self.reported.contains(var_name.name)
+        {
+            return;
+        }
+        let Some(decl) = self.locals.iter().find(|local| local.name == var_name.name) else {
+            return;
+        };
+        self.reported.insert(var_name.name);
+        self.diagnostics.push(
+            Diagnostic::warning(
+                format!(
+                    "local variable `{}` may be read before it is assigned",
+                    var_name
+                ),
+                var_name.span,
+            )
+            .with_code(UNASSIGNED_READ)
+            .with_label(decl.span, format!("`{}` declared here", decl))
+            .with_note("the VM initialises locals to 0, but relying on this is usually a mistake"),
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! Semantic checks run over the parsed AST
+//!
+//! Each check stamps its diagnostics with the code of the lint rule they belong to. The checks are
+//! normally run through [`crate::lint::Linter`], which applies the configured rule levels.
+
+pub mod constant;
+pub mod control_flow;
+pub mod definite_assignment;
+pub mod precedence;
+pub mod shadowing;
+pub mod style;
+pub mod unused;
+
+/// The classes of the Jack OS, which every program can call into.
+pub const OS_CLASSES: [&str; 8] = [
+    "Math", "String", "Array", "Output", "Screen", "Keyboard", "Memory", "Sys",
+];
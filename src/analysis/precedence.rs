@@ -0,0 +1,159 @@
+//! Ambiguous-precedence warnings for flat expressions
+//!
+//! Jack has no operator precedence: the operators of an `Expression` are applied strictly left to
+//! right, so `1 + 2 * 3` means `(1 + 2) * 3`. An expression where an operator follows one that
+//! would conventionally bind looser is reported, with two suggestions: a machine-applicable one
+//! making the actual left-to-right order explicit, and one regrouping the terms the way
+//! conventional precedence would, which changes the result.
+
+use crate::ast;
+use crate::diagnostics::{Applicability, Diagnostic, Edit, Suggestion};
+use crate::span::Span;
+use crate::visit::{self, Visitor};
+use std::collections::BTreeMap;
+
+pub const AMBIGUOUS_PRECEDENCE: &str = "J0404";
+
+pub fn check_class(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    let mut checker = Checker { diagnostics };
+    checker.visit_class(class);
+}
+
+/// The conventional (Java-like) precedence of an operator; higher binds tighter.
+fn precedence(op: ast::Op) -> u8 {
+    use ast::Op::*;
+    match op {
+        Mul | Div => 5,
+        Add | Sub => 4,
+        Lt | Gt => 3,
+        Eq => 2,
+        And => 1,
+        Or => 0,
+    }
+}
+
+struct Checker<'a> {
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a, 'source> Visitor<'source> for Checker<'a> {
+    fn visit_expression(&mut self, expr: &ast::Expression<'source>) {
+        check_expression(expr, self.diagnostics);
+        visit::walk_expression(self, expr);
+    }
+}
+
+fn check_expression(expr: &ast::Expression, diagnostics: &mut Vec<Diagnostic>) {
+    let ops: Vec<ast::Op> = expr.following_terms.iter().map(|(op, _)| *op).collect();
+    let terms: Vec<&ast::Term> = std::iter::once(&*expr.leading_term)
+        .chain(expr.following_terms.iter().map(|(_, term)| &**term))
+        .collect();
+
+    // `ops[i]` sits between `terms[i]` and `terms[i + 1]`. When `ops[i + 1]` binds tighter than
+    // `ops[i]`, everything up to `terms[i + 1]` is still evaluated first.
+    let prefix_ends: Vec<usize> = (1..ops.len())
+        .filter(|&i| precedence(ops[i]) > precedence(ops[i - 1]))
+        .collect();
+    let Some(&first_end) = prefix_ends.first() else {
+        return;
+    };
+
+    let explicit = Suggestion {
+        message: "to make the left-to-right evaluation explicit, write".to_string(),
+        edits: paren_edits(&terms, prefix_ends.iter().map(|&end| (0, end))),
+        applicability: Applicability::MachineApplicable,
+    };
+    let mut groups = Vec::new();
+    conventional_groups(&ops, &mut 0, Group::Term(0), 0).collect_parens(&mut groups);
+    let conventional = Suggestion {
+        message: "to apply conventional precedence instead, which changes the result, write"
+            .to_string(),
+        edits: paren_edits(&terms, groups.into_iter()),
+        applicability: Applicability::MaybeIncorrect,
+    };
+
+    diagnostics.push(
+        Diagnostic::warning(
+            "operators of different precedence are evaluated strictly left to right",
+            expr.span,
+        )
+        .with_code(AMBIGUOUS_PRECEDENCE)
+        .with_label(
+            terms[0].span.to(terms[first_end].span),
+            "this part is evaluated first",
+        )
+        .with_note("Jack has no operator precedence, so `1 + 2 * 3` means `(1 + 2) * 3`")
+        .with_suggestion(explicit)
+        .with_suggestion(conventional),
+    );
+}
+
+/// Turns `(first, last)` term ranges into edits wrapping each range in parentheses.
+fn paren_edits(terms: &[&ast::Term], ranges: impl Iterator<Item = (usize, usize)>) -> Vec<Edit> {
+    // Parentheses inserted at the same position are merged into one edit. An opening position is
+    // never a closing position, since an operator sits between two terms.
+    let mut inserts: BTreeMap<usize, String> = BTreeMap::new();
+    for (first, last) in ranges {
+        inserts
+            .entry(terms[first].span.start)
+            .or_default()
+            .push('(');
+        inserts.entry(terms[last].span.end).or_default().push(')');
+    }
+    inserts
+        .into_iter()
+        .map(|(pos, replacement)| Edit {
+            span: Span::new(pos, pos),
+            replacement,
+        })
+        .collect()
+}
+
+/// A chain of terms grouped by conventional precedence.
+enum Group {
+    Term(usize),
+    Binary(Box<Group>, Box<Group>),
+}
+
+impl Group {
+    fn first(&self) -> usize {
+        match self {
+            Group::Term(i) => *i,
+            Group::Binary(lhs, _) => lhs.first(),
+        }
+    }
+
+    fn last(&self) -> usize {
+        match self {
+            Group::Term(i) => *i,
+            Group::Binary(_, rhs) => rhs.last(),
+        }
+    }
+
+    /// Collects the term ranges that need parentheses under left-to-right evaluation for the
+    /// grouping to hold. Only right operands spanning several terms need them.
+    fn collect_parens(&self, ranges: &mut Vec<(usize, usize)>) {
+        if let Group::Binary(lhs, rhs) = self {
+            if let Group::Binary(_, _) = **rhs {
+                ranges.push((rhs.first(), rhs.last()));
+            }
+            lhs.collect_parens(ranges);
+            rhs.collect_parens(ranges);
+        }
+    }
+}
+
+/// Groups the chain starting at `lhs` by precedence climbing. `next` is the index of the next
+/// operator to consume.
+fn conventional_groups(ops: &[ast::Op], next: &mut usize, mut lhs: Group, min_prec: u8) -> Group {
+    while *next < ops.len() && precedence(ops[*next]) >= min_prec {
+        let op = ops[*next];
+        *next += 1;
+        let mut rhs = Group::Term(*next);
+        while *next < ops.len() && precedence(ops[*next]) > precedence(op) {
+            rhs = conventional_groups(ops, next, rhs, precedence(op) + 1);
+        }
+        lhs = Group::Binary(Box::new(lhs), Box::new(rhs));
+    }
+    lhs
+}
@@ -0,0 +1,112 @@
+//! Shadowing diagnostics
+//!
+//! A parameter or local with the same name as a field or static silently changes which VM segment
+//! the name refers to within the subroutine. Likewise, a parameter or local named like a class
+//! turns `Foo.bar()` from a function call on the class into a method call on the variable.
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use crate::symbol_table::SymbolTable;
+use crate::visit::{self, Visitor};
+use std::collections::HashSet;
+
+pub const SHADOWED_CLASS_VARIABLE: &str = "J0201";
+pub const SHADOWED_CLASS_NAME: &str = "J0202";
+
+/// `class_names` holds every class the program can refer to, including the Jack OS classes.
+pub fn check_class(
+    class: &ast::Class,
+    class_names: &HashSet<&str>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut table = SymbolTable::new(class);
+
+    for subroutine in &class.subroutines {
+        table.enter_subroutine(subroutine);
+
+        for var in table.subroutine_scope() {
+            let shadowed = table
+                .class_scope()
+                .iter()
+                .find(|class_var| class_var.name.name == var.name.name);
+            if let Some(shadowed) = shadowed {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        format!(
+                            "{} `{}` shadows {} `{}`",
+                            var.kind.description(),
+                            var.name,
+                            shadowed.kind.description(),
+                            shadowed.name
+                        ),
+                        var.name.span,
+                    )
+                    .with_code(SHADOWED_CLASS_VARIABLE)
+                    .with_label(
+                        shadowed.name.span,
+                        format!(
+                            "{} `{}` declared here",
+                            shadowed.kind.description(),
+                            shadowed.name
+                        ),
+                    )
+                    .with_note(format!(
+                        "within `{}`, `{}` refers to the {}",
+                        subroutine.name,
+                        var.name,
+                        var.kind.description()
+                    )),
+                );
+            }
+        }
+
+        let mut prefixes = CallPrefixes::default();
+        prefixes.visit_subroutine_dec(subroutine);
+        for prefix in prefixes.prefixes {
+            let Some(var) = table
+                .subroutine_scope()
+                .iter()
+                .find(|var| var.name.name == prefix.name)
+            else {
+                continue;
+            };
+            if class_names.contains(prefix.name) {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        format!(
+                            "{} `{}` shadows the class `{}` in this call",
+                            var.kind.description(),
+                            var.name,
+                            prefix
+                        ),
+                        prefix.span,
+                    )
+                    .with_code(SHADOWED_CLASS_NAME)
+                    .with_label(var.name.span, format!("`{}` declared here", var.name))
+                    .with_note(format!(
+                        "this calls a method on the variable `{}`, not a function of the class",
+                        var.name
+                    )),
+                );
+            }
+        }
+    }
+}
+
+/// Collects the first occurrence of each distinct call prefix.
+#[derive(Default)]
+struct CallPrefixes<'source> {
+    seen: HashSet<&'source str>,
+    prefixes: Vec<ast::Identifier<'source>>,
+}
+
+impl<'source> Visitor<'source> for CallPrefixes<'source> {
+    fn visit_subroutine_call(&mut self, call: &ast::SubroutineCall<'source>) {
+        if let Some(prefix) = call.prefix
+            && self.seen.insert(prefix.name)
+        {
+            self.prefixes.push(prefix);
+        }
+        visit::walk_subroutine_call(self, call);
+    }
+}
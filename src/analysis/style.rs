@@ -0,0 +1,194 @@
+//! Naming-convention and style checks
+//!
+//! Class names should be UpperCamelCase, and subroutine and variable names lowerCamelCase.
+//! Subroutines with too many statements or too deeply nested `if`/`while` statements are reported
+//! against configurable thresholds.
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+
+pub const CLASS_NAME_CASE: &str = "J0301";
+pub const SUBROUTINE_NAME_CASE: &str = "J0302";
+pub const VARIABLE_NAME_CASE: &str = "J0303";
+pub const LONG_SUBROUTINE: &str = "J0311";
+pub const DEEP_NESTING: &str = "J0312";
+
+pub fn check_names(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    if !is_upper_camel_case(class.name.name) {
+        diagnostics.push(
+            Diagnostic::warning(
+                format!("class name `{}` should be UpperCamelCase", class.name),
+                class.name.span,
+            )
+            .with_code(CLASS_NAME_CASE)
+            .with_note(format!(
+                "rename it to `{}`",
+                to_camel_case(class.name.name, true)
+            )),
+        );
+    }
+
+    let class_vars = class.variables.iter().flat_map(|var_dec| &var_dec.names);
+    for name in class_vars {
+        check_lower_camel_case(name, "variable", VARIABLE_NAME_CASE, diagnostics);
+    }
+    for subroutine in &class.subroutines {
+        check_lower_camel_case(
+            &subroutine.name,
+            "subroutine",
+            SUBROUTINE_NAME_CASE,
+            diagnostics,
+        );
+        let params = subroutine.params.0.iter().map(|param| &param.name);
+        let locals = subroutine
+            .body
+            .variables
+            .iter()
+            .flat_map(|var_dec| &var_dec.names);
+        for name in params.chain(locals) {
+            check_lower_camel_case(name, "variable", VARIABLE_NAME_CASE, diagnostics);
+        }
+    }
+}
+
+fn check_lower_camel_case(
+    name: &ast::Identifier,
+    what: &str,
+    code: &'static str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if !is_lower_camel_case(name.name) {
+        diagnostics.push(
+            Diagnostic::warning(
+                format!("{} name `{}` should be lowerCamelCase", what, name),
+                name.span,
+            )
+            .with_code(code)
+            .with_note(format!(
+                "rename it to `{}`",
+                to_camel_case(name.name, false)
+            )),
+        );
+    }
+}
+
+/// Names of a single letter are allowed, but longer names written entirely in uppercase aren't.
+fn is_upper_camel_case(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_uppercase())
+        && (name.len() == 1 || chars.any(|c| c.is_ascii_lowercase()))
+        && !name.contains('_')
+}
+
+/// Leading underscores are allowed, since they mark intentionally unused parameters.
+fn is_lower_camel_case(name: &str) -> bool {
+    let name = name.trim_start_matches('_');
+    name.is_empty() || (name.starts_with(|c: char| c.is_ascii_lowercase()) && !name.contains('_'))
+}
+
+/// Converts a name to UpperCamelCase or lowerCamelCase, splitting words at underscores. Names
+/// written entirely in uppercase are treated as a single word.
+fn to_camel_case(name: &str, upper: bool) -> String {
+    let prefix_len = name.len() - name.trim_start_matches('_').len();
+    let (prefix, name) = name.split_at(if upper { 0 } else { prefix_len });
+    let all_caps = !name.chars().any(|c| c.is_ascii_lowercase());
+
+    let mut result = String::from(prefix);
+    for word in name.split('_').filter(|word| !word.is_empty()) {
+        let mut chars = word.chars();
+        let first = chars.next().unwrap();
+        if result.len() == prefix.len() && !upper {
+            result.push(first.to_ascii_lowercase());
+        } else {
+            result.push(first.to_ascii_uppercase());
+        }
+        if all_caps {
+            result.extend(chars.map(|c| c.to_ascii_lowercase()));
+        } else {
+            result.extend(chars);
+        }
+    }
+    result
+}
+
+pub fn check_length(class: &ast::Class, max_stmts: usize, diagnostics: &mut Vec<Diagnostic>) {
+    for subroutine in &class.subroutines {
+        let count = count_stmts(&subroutine.body.stmts);
+        if count > max_stmts {
+            diagnostics.push(
+                Diagnostic::warning(
+                    format!(
+                        "subroutine `{}` has {} statements, more than the limit of {}",
+                        subroutine.name, count, max_stmts
+                    ),
+                    subroutine.name.span,
+                )
+                .with_code(LONG_SUBROUTINE)
+                .with_note("consider splitting it into smaller subroutines"),
+            );
+        }
+    }
+}
+
+/// Counts the statements of a sequence, including the ones nested in `if` and `while` bodies.
+fn count_stmts(stmts: &ast::Stmts) -> usize {
+    stmts
+        .0
+        .iter()
+        .map(|stmt| {
+            1 + match stmt {
+                ast::Stmt::If(if_stmt) => {
+                    count_stmts(&if_stmt.stmts) + if_stmt.else_stmts.as_ref().map_or(0, count_stmts)
+                }
+                ast::Stmt::While(while_stmt) => count_stmts(&while_stmt.stmts),
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+pub fn check_nesting(class: &ast::Class, max_depth: usize, diagnostics: &mut Vec<Diagnostic>) {
+    for subroutine in &class.subroutines {
+        // Only the first statement nested too deeply is reported for each subroutine
+        if let Some(span) = too_deep(&subroutine.body.stmts, 1, max_depth) {
+            diagnostics.push(
+                Diagnostic::warning(
+                    format!(
+                        "statements in `{}` are nested more than {} levels deep",
+                        subroutine.name, max_depth
+                    ),
+                    span,
+                )
+                .with_code(DEEP_NESTING)
+                .with_label(subroutine.name.span, "in this subroutine")
+                .with_note("consider extracting the inner statements into a subroutine"),
+            );
+        }
+    }
+}
+
+/// Returns the span of the first `if` or `while` statement whose nesting depth exceeds
+/// `max_depth`. Statements directly in a subroutine body are at depth 1.
+fn too_deep(stmts: &ast::Stmts, depth: usize, max_depth: usize) -> Option<Span> {
+    for stmt in &stmts.0 {
+        let bodies: Vec<&ast::Stmts> = match stmt {
+            ast::Stmt::If(if_stmt) => [Some(&if_stmt.stmts), if_stmt.else_stmts.as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ast::Stmt::While(while_stmt) => vec![&while_stmt.stmts],
+            _ => continue,
+        };
+        if depth > max_depth {
+            return Some(stmt.span());
+        }
+        if let Some(span) = bodies
+            .into_iter()
+            .find_map(|body| too_deep(body, depth + 1, max_depth))
+        {
+            return Some(span);
+        }
+    }
+    None
+}
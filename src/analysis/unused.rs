@@ -0,0 +1,103 @@
+//! Unused variable, parameter and class variable warnings
+//!
+//! Parameters and locals are reported when they're never referenced in their subroutine.
+//! Fields and statics are reported when they're never referenced in their class. Jack has no
+//! syntax for accessing another class's variables, so a class variable unused in its own class is
+//! unused in the whole program.
+//!
+//! Parameters whose name starts with an underscore are never reported, so that intentionally
+//! unused parameters can be silenced.
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use crate::symbol_table::{SymbolTable, VarKind, Variable};
+use crate::visit::{self, Visitor};
+use std::collections::HashSet;
+
+pub const UNUSED_LOCAL: &str = "J0101";
+pub const UNUSED_PARAMETER: &str = "J0102";
+pub const UNUSED_FIELD: &str = "J0103";
+pub const UNUSED_STATIC: &str = "J0104";
+
+pub fn check_class(class: &ast::Class, diagnostics: &mut Vec<Diagnostic>) {
+    let mut table = SymbolTable::new(class);
+    let mut used_class_vars = HashSet::new();
+
+    for subroutine in &class.subroutines {
+        table.enter_subroutine(subroutine);
+        let mut references = References::default();
+        references.visit_subroutine_dec(subroutine);
+
+        for var in table.subroutine_scope() {
+            let silenced = var.kind == VarKind::Argument && var.name.name.starts_with('_');
+            if !silenced && !references.names.contains(var.name.name) {
+                diagnostics.push(unused(var));
+            }
+        }
+        for name in &references.names {
+            if let Some(var) = table.lookup(name)
+                && matches!(var.kind, VarKind::Static | VarKind::Field)
+            {
+                used_class_vars.insert(*name);
+            }
+        }
+    }
+
+    for var in table.class_scope() {
+        if !used_class_vars.contains(var.name.name) {
+            diagnostics.push(unused(var));
+        }
+    }
+}
+
+fn unused(var: &Variable) -> Diagnostic {
+    let code = match var.kind {
+        VarKind::Static => UNUSED_STATIC,
+        VarKind::Field => UNUSED_FIELD,
+        VarKind::Argument => UNUSED_PARAMETER,
+        VarKind::Local => UNUSED_LOCAL,
+    };
+    let diagnostic = Diagnostic::warning(
+        format!("unused {} `{}`", var.kind.description(), var.name),
+        var.name.span,
+    )
+    .with_code(code);
+    if var.kind == VarKind::Argument {
+        diagnostic.with_note(format!(
+            "if this is intentional, rename it to `_{}`",
+            var.name
+        ))
+    } else {
+        diagnostic
+    }
+}
+
+/// Collects every variable name referenced in a subroutine, whether read or written.
+#[derive(Default)]
+struct References<'source> {
+    names: HashSet<&'source str>,
+}
+
+impl<'source> Visitor<'source> for References<'source> {
+    fn visit_let_stmt(&mut self, let_stmt: &ast::LetStmt<'source>) {
+        self.names.insert(let_stmt.var_name.name);
+        visit::walk_let_stmt(self, let_stmt);
+    }
+
+    fn visit_term(&mut self, term: &ast::Term<'source>) {
+        if let ast::TermKind::VarRef(var_name) | ast::TermKind::VarRefWithIdx(var_name, _) =
+            &term.kind
+        {
+            self.names.insert(var_name.name);
+        }
+        visit::walk_term(self, term);
+    }
+
+    fn visit_subroutine_call(&mut self, call: &ast::SubroutineCall<'source>) {
+        // The prefix is either a class name or a variable holding the receiver
+        if let Some(ref prefix) = call.prefix {
+            self.names.insert(prefix.name);
+        }
+        visit::walk_subroutine_call(self, call);
+    }
+}
@@ -1,4 +1,17 @@
-pub type Identifier<'source> = &'source str;
+use crate::span::Span;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Identifier<'source> {
+    pub name: &'source str,
+    pub span: Span,
+}
+
+impl<'source> std::fmt::Display for Identifier<'source> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
 pub type ClassName<'source> = Identifier<'source>;
 pub type SubroutineName<'source> = Identifier<'source>;
 pub type VarName<'source> = Identifier<'source>;
@@ -38,6 +51,7 @@ pub struct SubroutineDec<'source> {
     pub name: SubroutineName<'source>,
     pub params: ParameterList<'source>,
     pub body: SubroutineBody<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -66,6 +80,7 @@ pub struct Parameter<'source> {
 pub struct SubroutineBody<'source> {
     pub variables: Vec<VarDec<'source>>,
     pub stmts: Stmts<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,11 +101,25 @@ pub enum Stmt<'source> {
     Return(ReturnStmt<'source>),
 }
 
+impl<'source> Stmt<'source> {
+    pub fn span(&self) -> Span {
+        use Stmt::*;
+        match self {
+            Let(stmt) => stmt.span,
+            If(stmt) => stmt.span,
+            While(stmt) => stmt.span,
+            Do(stmt) => stmt.span,
+            Return(stmt) => stmt.span,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct LetStmt<'source> {
     pub var_name: VarName<'source>,
     pub idx_expr: Option<Expression<'source>>,
     pub assign_expr: Expression<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -98,32 +127,43 @@ pub struct IfStmt<'source> {
     pub condition: Expression<'source>,
     pub stmts: Stmts<'source>,
     pub else_stmts: Option<Stmts<'source>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct WhileStmt<'source> {
     pub condition: Expression<'source>,
     pub stmts: Stmts<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DoStmt<'source> {
     pub call: SubroutineCall<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReturnStmt<'source> {
     pub return_val: Option<Expression<'source>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Expression<'source> {
     pub leading_term: Box<Term<'source>>,
     pub following_terms: Vec<(Op, Box<Term<'source>>)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term<'source> {
+    pub kind: TermKind<'source>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Term<'source> {
+pub enum TermKind<'source> {
     IntegerConst(u16),
     StringConst(&'source str),
     KeywordConst(KeywordConst),
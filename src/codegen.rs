@@ -0,0 +1,586 @@
+//! VM code generation
+//!
+//! Each class compiles to the instructions of its own `.vm` file. Subroutines become VM functions
+//! named `Class.subroutine`, and variables live in the VM segment given by the symbol table:
+//! statics in `static`, fields in `this`, parameters in `argument` and locals in `local`.
+//!
+//! Constructors allocate the new object with `Memory.alloc`, and methods receive it as their
+//! first argument. Either way, it's then moved to `pointer 0` so that `this` refers to it.
+//!
+//! Expressions are evaluated strictly left to right, like the language defines them. `*` and `/`
+//! call `Math.multiply` and `Math.divide`, and string constants are built at run time through
+//! `String.new` and `String.appendChar`. Array elements are accessed through the `that` segment,
+//! after moving their address to `pointer 1`.
+//!
+//! The code is generated from the IR by [`ir::to_vm`], which the optimisations work on too, and
+//! which keeps the span of the Jack code every instruction comes from for annotations and source
+//! maps. The `Compiler` walking the AST only remains for what the IR can't reproduce: the labels
+//! and the order of operations of [`Compat::Official`].
+
+use crate::ast;
+use crate::diagnostics::Diagnostic;
+use crate::ir;
+use crate::resolve::{Receiver, Resolver};
+use crate::span::{self, Span};
+use crate::symbol_table::VarKind;
+use crate::vm::{self, ArithmeticOp, Instruction, Segment};
+use std::io::{self, Write};
+
+/// A compiler whose output can be reproduced exactly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compat {
+    /// The JackCompiler of the nand2tetris software suite. Its `if` statements jump to
+    /// `IF_TRUE<n>` when the condition holds and to `IF_FALSE<n>` otherwise, loops use
+    /// `WHILE_EXP<n>` and `WHILE_END<n>` labels, and both counters restart in every subroutine.
+    /// It computes the address of an array element by pushing the index before the array.
+    Official,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Creates each distinct string constant of a class only once, the first time it's evaluated,
+    /// and keeps it in a static variable added after the ones the class declares. Every
+    /// evaluation then yields the same `String` object, so the program must not modify or
+    /// dispose of it.
+    pub pool_strings: bool,
+    /// Produces the same instructions and labels as another compiler, for programs it accepts.
+    pub compat: Option<Compat>,
+    /// Optimises the IR with [`ir::optimize`] before translating it. `compat` is ignored.
+    pub optimize: bool,
+    /// With `optimize`, inlines getters with [`ir::inline`] across the classes compiled together
+    /// by [`compile_program`].
+    pub inline: bool,
+}
+
+/// The VM code of a class.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassCode {
+    pub instructions: Vec<Instruction>,
+    /// The span of every statement, in order, with the index of the first instruction generated
+    /// for it. Statements left without instructions by the optimisations aren't listed.
+    pub statements: Vec<(usize, Span)>,
+    /// The span of the Jack code every instruction was generated from: the innermost term,
+    /// operation or statement, or the subroutine for its `function` instruction and prologue.
+    /// Variables and constants are pushed by the operation or statement reading them.
+    pub origins: Vec<Span>,
+    /// The span of every subroutine, in order, with the index of its `function` instruction.
+    pub subroutines: Vec<(usize, Span)>,
+}
+
+impl ClassCode {
+    /// Writes the instructions in the textual `.vm` format.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        for instruction in &self.instructions {
+            writeln!(writer, "{}", instruction)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the instructions like [`ClassCode::write`], preceding the ones of every statement
+    /// with a `// <file name>:<line>: <statement>` comment. Only the first line of statements
+    /// spanning several lines is shown.
+    pub fn write_annotated(
+        &self,
+        writer: &mut impl Write,
+        file_name: &str,
+        source: &str,
+    ) -> io::Result<()> {
+        let mut statements = self.statements.iter().peekable();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            while let Some((_, span)) = statements.next_if(|(start, _)| *start == index) {
+                let (line, _) = span::line_col(source, span.start);
+                let text = source[span.start..span.end].lines().next().unwrap_or("");
+                writeln!(writer, "// {}:{}: {}", file_name, line, text.trim_end())?;
+            }
+            writeln!(writer, "{}", instruction)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the [`vm::peephole`] optimiser on the instructions, keeping track of the Jack code
+    /// they come from. Returns the name of every function with the number of instructions
+    /// removed from it.
+    pub fn peephole(&mut self) -> Vec<(String, usize)> {
+        let optimized = vm::peephole(&self.instructions);
+        let sources = &optimized.sources;
+        if !self.origins.is_empty() {
+            self.origins = sources.iter().map(|&source| self.origins[source]).collect();
+        }
+        // Code removed from the start of a statement is counted from the next instruction kept
+        for (start, _) in self.statements.iter_mut().chain(&mut self.subroutines) {
+            *start = sources.partition_point(|&source| source < *start);
+        }
+        self.instructions = optimized.instructions;
+        optimized.removed
+    }
+}
+
+/// Compiles a class into VM code, or returns the errors preventing it.
+///
+/// The code is generated from the IR, except with `compat` and without `optimize`: the IR doesn't
+/// keep the labels and the order of operations of another compiler, so the code is then
+/// generated by walking the AST.
+pub fn compile_class(class: &ast::Class, options: &Options) -> Result<ClassCode, Vec<Diagnostic>> {
+    if options.optimize || options.compat.is_none() {
+        return ir::lower_class(class, options).map(|functions| ir_code(class, functions, options));
+    }
+    let mut compiler = Compiler {
+        class,
+        options,
+        resolver: Resolver::new(class),
+        instructions: Vec::new(),
+        statements: Vec::new(),
+        origins: Vec::new(),
+        subroutines: Vec::new(),
+        span: Span::default(),
+        label_count: 0,
+        if_count: 0,
+        while_count: 0,
+        strings: Vec::new(),
+    };
+    for subroutine in &class.subroutines {
+        compiler.subroutine(subroutine);
+    }
+    if compiler.resolver.diagnostics.is_empty() {
+        Ok(ClassCode {
+            instructions: compiler.instructions,
+            statements: compiler.statements,
+            origins: compiler.origins,
+            subroutines: compiler.subroutines,
+        })
+    } else {
+        Err(compiler.resolver.diagnostics)
+    }
+}
+
+/// Compiles the classes of a program, returning the code of every class or the errors
+/// preventing it. Unlike [`compile_class`], this can inline getters across classes.
+pub fn compile_program(
+    classes: &[ast::Class],
+    options: &Options,
+) -> Vec<Result<ClassCode, Vec<Diagnostic>>> {
+    if !(options.optimize && options.inline) {
+        return classes
+            .iter()
+            .map(|class| compile_class(class, options))
+            .collect();
+    }
+    ir::lower_program(classes, options)
+        .into_iter()
+        .zip(classes)
+        .map(|(result, class)| result.map(|functions| ir_code(class, functions, options)))
+        .collect()
+}
+
+/// Translates the IR of the subroutines of a class to VM code, optimising it first with
+/// `optimize`.
+fn ir_code(class: &ast::Class, functions: Vec<ir::Function>, options: &Options) -> ClassCode {
+    let mut code = ClassCode::default();
+    for (mut function, subroutine) in functions.into_iter().zip(&class.subroutines) {
+        if options.optimize {
+            ir::optimize(&mut function);
+            debug_assert_eq!(ir::verify(&function), Ok(()), "optimised IR:\n{}", function);
+        }
+        let start = code.instructions.len();
+        code.subroutines.push((start, function.span));
+        let (instructions, origins) = ir::to_vm_with_origins(&function);
+        code.instructions.extend(instructions);
+        code.origins.extend(origins);
+        code.statements.extend(statement_starts(
+            &subroutine.body.stmts,
+            &code.origins[start..],
+            start,
+        ));
+    }
+    code
+}
+
+/// A statement of a subroutine, with the index of the statement it's nested in.
+struct Statement {
+    span: Span,
+    parent: Option<usize>,
+}
+
+fn statements(stmts: &ast::Stmts, parent: Option<usize>, list: &mut Vec<Statement>) {
+    for stmt in &stmts.0 {
+        list.push(Statement {
+            span: stmt.span(),
+            parent,
+        });
+        let index = Some(list.len() - 1);
+        match stmt {
+            ast::Stmt::If(if_stmt) => {
+                statements(&if_stmt.stmts, index, list);
+                if let Some(else_stmts) = &if_stmt.else_stmts {
+                    statements(else_stmts, index, list);
+                }
+            }
+            ast::Stmt::While(while_stmt) => statements(&while_stmt.stmts, index, list),
+            ast::Stmt::Let(_) | ast::Stmt::Do(_) | ast::Stmt::Return(_) => {}
+        }
+    }
+}
+
+/// Returns the statements of a subroutine, given its instructions' origins starting at `offset`,
+/// with the index of the first instruction generated for each. Every instruction belongs to the
+/// innermost statement its origin is part of, and a statement starts with the first instruction
+/// of the statement or of one nested in it.
+fn statement_starts(stmts: &ast::Stmts, origins: &[Span], offset: usize) -> Vec<(usize, Span)> {
+    let mut list = Vec::new();
+    statements(stmts, None, &mut list);
+    let mut started = vec![false; list.len()];
+    let mut starts = Vec::new();
+    for (index, origin) in origins.iter().enumerate() {
+        // Statements come before the ones nested in them, which are within their span
+        let mut statement = list.iter().rposition(|statement| {
+            statement.span.start <= origin.start && origin.end <= statement.span.end
+        });
+        let mut new = Vec::new();
+        while let Some(id) = statement
+            && !started[id]
+        {
+            started[id] = true;
+            new.push((offset + index, list[id].span));
+            statement = list[id].parent;
+        }
+        starts.extend(new.into_iter().rev());
+    }
+    starts
+}
+
+/// Returns whether `c` is a printable character of the Hack character set, whose codes are the
+/// same as in ASCII.
+pub fn is_hack_char(c: char) -> bool {
+    (' '..='~').contains(&c)
+}
+
+/// Generates the code of [`Compat::Official`] by walking the AST.
+struct Compiler<'a, 'source> {
+    class: &'a ast::Class<'source>,
+    options: &'a Options,
+    resolver: Resolver<'a, 'source>,
+    instructions: Vec<Instruction>,
+    statements: Vec<(usize, Span)>,
+    origins: Vec<Span>,
+    subroutines: Vec<(usize, Span)>,
+    /// The span the next instructions are generated from.
+    span: Span,
+    /// The number of label suffixes generated so far in the current subroutine.
+    label_count: usize,
+    /// The number of `if` and `while` statements compiled so far in the current subroutine, which
+    /// number their labels.
+    if_count: usize,
+    while_count: usize,
+    /// The pooled string constants, without quotes, in the order of their static variables.
+    strings: Vec<&'source str>,
+}
+
+impl<'a, 'source> Compiler<'a, 'source> {
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+        self.origins.push(self.span);
+    }
+
+    fn push(&mut self, segment: Segment, index: u16) {
+        self.emit(Instruction::Push(segment, index));
+    }
+
+    fn pop(&mut self, segment: Segment, index: u16) {
+        self.emit(Instruction::Pop(segment, index));
+    }
+
+    fn arithmetic(&mut self, op: ArithmeticOp) {
+        self.emit(Instruction::Arithmetic(op));
+    }
+
+    fn call(&mut self, name: String, args: u16) {
+        self.emit(Instruction::Call { name, args });
+    }
+
+    /// Returns a number unique within the current subroutine, to suffix the labels of a
+    /// statement with.
+    fn new_label_id(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count - 1
+    }
+
+    fn subroutine(&mut self, subroutine: &'a ast::SubroutineDec<'source>) {
+        self.resolver.enter_subroutine(subroutine);
+        self.label_count = 0;
+        self.if_count = 0;
+        self.while_count = 0;
+        self.subroutines
+            .push((self.instructions.len(), subroutine.span));
+        self.span = subroutine.span;
+        self.emit(Instruction::Function {
+            name: format!("{}.{}", self.class.name, subroutine.name),
+            locals: self.resolver.symbols.count(VarKind::Local),
+        });
+        match subroutine.kind {
+            ast::SubroutineKind::Constructor => {
+                // The new object holds one word per field
+                self.push(
+                    Segment::Constant,
+                    self.resolver.symbols.count(VarKind::Field),
+                );
+                self.call("Memory.alloc".to_string(), 1);
+                self.pop(Segment::Pointer, 0);
+            }
+            ast::SubroutineKind::Method => {
+                self.push(Segment::Argument, 0);
+                self.pop(Segment::Pointer, 0);
+            }
+            ast::SubroutineKind::Function => {}
+        }
+        self.stmts(&subroutine.body.stmts);
+    }
+
+    fn stmts(&mut self, stmts: &ast::Stmts<'source>) {
+        for stmt in &stmts.0 {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt<'source>) {
+        self.statements.push((self.instructions.len(), stmt.span()));
+        let outer = std::mem::replace(&mut self.span, stmt.span());
+        self.stmt_instructions(stmt);
+        self.span = outer;
+    }
+
+    fn stmt_instructions(&mut self, stmt: &ast::Stmt<'source>) {
+        match stmt {
+            ast::Stmt::Let(let_stmt) => {
+                let Some(idx_expr) = &let_stmt.idx_expr else {
+                    self.expression(&let_stmt.assign_expr);
+                    if let Some((segment, index)) = self.variable(&let_stmt.var_name) {
+                        self.pop(segment, index);
+                    }
+                    return;
+                };
+                self.element_address(&let_stmt.var_name, idx_expr);
+                // The right-hand side may access an array itself, overwriting `pointer 1`, so it's
+                // evaluated before the address is moved there
+                self.expression(&let_stmt.assign_expr);
+                self.pop(Segment::Temp, 0);
+                self.pop(Segment::Pointer, 1);
+                self.push(Segment::Temp, 0);
+                self.pop(Segment::That, 0);
+            }
+            ast::Stmt::If(if_stmt) => self.if_stmt(if_stmt),
+            ast::Stmt::While(while_stmt) => {
+                self.while_count += 1;
+                let id = self.while_count - 1;
+                let (start_label, end_label) =
+                    (format!("WHILE_EXP{}", id), format!("WHILE_END{}", id));
+                self.emit(Instruction::Label(start_label.clone()));
+                self.expression(&while_stmt.condition);
+                self.arithmetic(ArithmeticOp::Not);
+                self.emit(Instruction::IfGoto(end_label.clone()));
+                self.stmts(&while_stmt.stmts);
+                self.emit(Instruction::Goto(start_label));
+                self.emit(Instruction::Label(end_label));
+            }
+            ast::Stmt::Do(do_stmt) => {
+                self.subroutine_call(&do_stmt.call);
+                // Every subroutine returns a value, which `do` discards
+                self.pop(Segment::Temp, 0);
+            }
+            ast::Stmt::Return(return_stmt) => {
+                match &return_stmt.return_val {
+                    Some(expr) => self.expression(expr),
+                    None => self.push(Segment::Constant, 0),
+                }
+                self.emit(Instruction::Return);
+            }
+        }
+    }
+
+    /// Compiles an `if` statement the way the official compiler does, jumping over a `goto` to
+    /// the `else` branch when the condition holds.
+    fn if_stmt(&mut self, if_stmt: &ast::IfStmt<'source>) {
+        let id = self.if_count;
+        self.if_count += 1;
+        let true_label = format!("IF_TRUE{}", id);
+        let false_label = format!("IF_FALSE{}", id);
+        self.expression(&if_stmt.condition);
+        self.emit(Instruction::IfGoto(true_label.clone()));
+        self.emit(Instruction::Goto(false_label.clone()));
+        self.emit(Instruction::Label(true_label));
+        self.stmts(&if_stmt.stmts);
+        match &if_stmt.else_stmts {
+            Some(else_stmts) => {
+                let end_label = format!("IF_END{}", id);
+                self.emit(Instruction::Goto(end_label.clone()));
+                self.emit(Instruction::Label(false_label));
+                self.stmts(else_stmts);
+                self.emit(Instruction::Label(end_label));
+            }
+            None => self.emit(Instruction::Label(false_label)),
+        }
+    }
+
+    fn expression(&mut self, expr: &ast::Expression<'source>) {
+        self.term(&expr.leading_term);
+        let outer = self.span;
+        for (op, term) in &expr.following_terms {
+            self.term(term);
+            self.span = Span::new(expr.span.start, term.span.end);
+            match op {
+                ast::Op::Add => self.arithmetic(ArithmeticOp::Add),
+                ast::Op::Sub => self.arithmetic(ArithmeticOp::Sub),
+                ast::Op::Mul => self.call("Math.multiply".to_string(), 2),
+                ast::Op::Div => self.call("Math.divide".to_string(), 2),
+                ast::Op::And => self.arithmetic(ArithmeticOp::And),
+                ast::Op::Or => self.arithmetic(ArithmeticOp::Or),
+                ast::Op::Lt => self.arithmetic(ArithmeticOp::Lt),
+                ast::Op::Gt => self.arithmetic(ArithmeticOp::Gt),
+                ast::Op::Eq => self.arithmetic(ArithmeticOp::Eq),
+            }
+            self.span = outer;
+        }
+    }
+
+    fn term(&mut self, term: &ast::Term<'source>) {
+        let outer = std::mem::replace(&mut self.span, term.span);
+        self.term_instructions(term);
+        self.span = outer;
+    }
+
+    fn term_instructions(&mut self, term: &ast::Term<'source>) {
+        use ast::TermKind::*;
+        match &term.kind {
+            IntegerConst(n) => self.integer(*n),
+            StringConst(literal) => self.string(literal, term.span),
+            KeywordConst(kw) => match kw {
+                ast::KeywordConst::True => {
+                    self.push(Segment::Constant, 0);
+                    self.arithmetic(ArithmeticOp::Not);
+                }
+                ast::KeywordConst::False | ast::KeywordConst::Null => {
+                    self.push(Segment::Constant, 0)
+                }
+                ast::KeywordConst::This => {
+                    if self.resolver.check_this(term.span, "`this`") {
+                        self.push(Segment::Pointer, 0);
+                    }
+                }
+            },
+            VarRef(name) => {
+                if let Some((segment, index)) = self.variable(name) {
+                    self.push(segment, index);
+                }
+            }
+            VarRefWithIdx(name, idx_expr) => {
+                self.element_address(name, idx_expr);
+                self.pop(Segment::Pointer, 1);
+                self.push(Segment::That, 0);
+            }
+            SubroutineCall(call) => self.subroutine_call(call),
+            Expr(expr) => self.expression(expr),
+            UnaryOperation(op, operand) => {
+                self.term(operand);
+                self.arithmetic(match op {
+                    ast::UnaryOp::Negative => ArithmeticOp::Neg,
+                    ast::UnaryOp::Neg => ArithmeticOp::Not,
+                });
+            }
+        }
+    }
+
+    /// Pushes an integer constant. `push constant` only takes 15-bit values, so constants above
+    /// 32767 are pushed as the negative number they wrap around to.
+    fn integer(&mut self, n: u16) {
+        match n as i16 {
+            i16::MIN => {
+                self.push(Segment::Constant, i16::MAX as u16);
+                self.arithmetic(ArithmeticOp::Not);
+            }
+            value if value < 0 => {
+                self.push(Segment::Constant, value.unsigned_abs());
+                self.arithmetic(ArithmeticOp::Neg);
+            }
+            _ => self.push(Segment::Constant, n),
+        }
+    }
+
+    fn string(&mut self, literal: &'source str, span: Span) {
+        let Some(content) = self.resolver.string(literal, span) else {
+            return;
+        };
+        if !self.options.pool_strings {
+            self.new_string(content);
+            return;
+        }
+        let position = match self.strings.iter().position(|s| *s == content) {
+            Some(position) => position,
+            None => {
+                self.strings.push(content);
+                self.strings.len() - 1
+            }
+        };
+        let index = self.resolver.symbols.count(VarKind::Static) + position as u16;
+        // The static variable is 0 until the string is created
+        let label = format!("STRING_READY_{}", self.new_label_id());
+        self.push(Segment::Static, index);
+        self.emit(Instruction::IfGoto(label.clone()));
+        self.new_string(content);
+        self.pop(Segment::Static, index);
+        self.emit(Instruction::Label(label));
+        self.push(Segment::Static, index);
+    }
+
+    fn new_string(&mut self, content: &str) {
+        self.push(Segment::Constant, content.chars().count() as u16);
+        self.call("String.new".to_string(), 1);
+        for c in content.chars() {
+            self.push(Segment::Constant, c as u16);
+            self.call("String.appendChar".to_string(), 2);
+        }
+    }
+
+    fn subroutine_call(&mut self, call: &ast::SubroutineCall<'source>) {
+        let Some(callee) = self.resolver.callee(call) else {
+            return;
+        };
+        let mut args = call.args.0.len() as u16;
+        if let Some(receiver) = callee.receiver {
+            match receiver {
+                Receiver::This => self.push(Segment::Pointer, 0),
+                Receiver::Variable(kind, index) => self.push(segment(kind), index),
+            }
+            args += 1;
+        }
+        for arg in &call.args.0 {
+            self.expression(arg);
+        }
+        self.call(callee.name, args);
+    }
+
+    /// Pushes the address of the element `idx_expr` of the array `name`, pushing the index
+    /// before the array like the official compiler.
+    fn element_address(&mut self, name: &ast::VarName, idx_expr: &ast::Expression<'source>) {
+        self.expression(idx_expr);
+        if let Some((segment, index)) = self.variable(name) {
+            self.push(segment, index);
+        }
+        self.arithmetic(ArithmeticOp::Add);
+    }
+
+    /// Resolves a variable to its VM segment and index, reporting an error if it can't be used
+    /// here.
+    fn variable(&mut self, name: &ast::VarName) -> Option<(Segment, u16)> {
+        let (kind, index) = self.resolver.variable(name)?;
+        Some((segment(kind), index))
+    }
+}
+
+/// Returns the VM segment holding variables of the given kind.
+pub fn segment(kind: VarKind) -> Segment {
+    match kind {
+        VarKind::Static => Segment::Static,
+        VarKind::Field => Segment::This,
+        VarKind::Argument => Segment::Argument,
+        VarKind::Local => Segment::Local,
+    }
+}
@@ -0,0 +1,160 @@
+//! Constant-expression evaluation
+//!
+//! Jack integers are 16-bit two's-complement values, and an `Expression` is evaluated strictly
+//! left to right: `1 + 2 * 3` is `(1 + 2) * 3`. Arithmetic wraps around like the Hack ALU, and
+//! `true` is -1 (all bits set). Division truncates towards zero, like `Math.divide`.
+//!
+//! Evaluation records an [`Issue`] for every overflow and division by zero it runs into, so that
+//! they can be reported even when the expression as a whole isn't constant.
+
+use crate::ast;
+use crate::span::Span;
+
+pub const TRUE: i16 = -1;
+pub const FALSE: i16 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    /// An integer constant greater than 32767.
+    LiteralOutOfRange(u16),
+    /// An operation whose exact result doesn't fit in 16 bits. `exact` is the mathematical result
+    /// and `wrapped` the value the program actually computes.
+    Overflow {
+        expr: String,
+        exact: i32,
+        wrapped: i16,
+    },
+    DivisionByZero,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Default)]
+pub struct Evaluator {
+    pub issues: Vec<Issue>,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluates an expression, returning its value if it's constant. Non-constant parts are still
+    /// walked so that issues in their constant subexpressions are recorded.
+    pub fn expression(&mut self, expr: &ast::Expression) -> Option<i16> {
+        let mut acc = self.term(&expr.leading_term);
+        for (op, term) in &expr.following_terms {
+            let rhs = self.term(term);
+            if *op == ast::Op::Div && rhs == Some(0) {
+                self.issues.push(Issue {
+                    kind: IssueKind::DivisionByZero,
+                    span: term.span,
+                });
+                acc = None;
+                continue;
+            }
+            acc = match (acc, rhs) {
+                (Some(lhs), Some(rhs)) => {
+                    let span = Span::new(expr.span.start, term.span.end);
+                    Some(self.binary(*op, lhs, rhs, span))
+                }
+                _ => None,
+            };
+        }
+        acc
+    }
+
+    pub fn term(&mut self, term: &ast::Term) -> Option<i16> {
+        use ast::TermKind::*;
+        match &term.kind {
+            IntegerConst(n) => match i16::try_from(*n) {
+                Ok(n) => Some(n),
+                Err(_) => {
+                    self.issues.push(Issue {
+                        kind: IssueKind::LiteralOutOfRange(*n),
+                        span: term.span,
+                    });
+                    Some(*n as i16)
+                }
+            },
+            KeywordConst(kw) => match kw {
+                ast::KeywordConst::True => Some(TRUE),
+                ast::KeywordConst::False | ast::KeywordConst::Null => Some(FALSE),
+                ast::KeywordConst::This => None,
+            },
+            StringConst(_) | VarRef(_) => None,
+            VarRefWithIdx(_, idx) => {
+                self.expression(idx);
+                None
+            }
+            SubroutineCall(call) => {
+                for arg in &call.args.0 {
+                    self.expression(arg);
+                }
+                None
+            }
+            Expr(expr) => self.expression(expr),
+            UnaryOperation(op, operand) => {
+                let value = self.term(operand)?;
+                match op {
+                    ast::UnaryOp::Neg => Some(!value),
+                    ast::UnaryOp::Negative => {
+                        let (wrapped, overflow) = value.overflowing_neg();
+                        if overflow {
+                            self.issues.push(Issue {
+                                kind: IssueKind::Overflow {
+                                    expr: format!("-({})", value),
+                                    exact: -i32::from(value),
+                                    wrapped,
+                                },
+                                span: term.span,
+                            });
+                        }
+                        Some(wrapped)
+                    }
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, op: ast::Op, lhs: i16, rhs: i16, span: Span) -> i16 {
+        use ast::Op::*;
+        let (exact, symbol) = match op {
+            Add => (i32::from(lhs) + i32::from(rhs), "+"),
+            Sub => (i32::from(lhs) - i32::from(rhs), "-"),
+            Mul => (i32::from(lhs) * i32::from(rhs), "*"),
+            // Division by zero is handled by the caller
+            Div => (i32::from(lhs) / i32::from(rhs), "/"),
+            And => return lhs & rhs,
+            Or => return lhs | rhs,
+            Lt => return bool_value(lhs < rhs),
+            Gt => return bool_value(lhs > rhs),
+            Eq => return bool_value(lhs == rhs),
+        };
+        let wrapped = exact as i16;
+        if i32::from(wrapped) != exact {
+            self.issues.push(Issue {
+                kind: IssueKind::Overflow {
+                    expr: format!("{} {} {}", lhs, symbol, rhs),
+                    exact,
+                    wrapped,
+                },
+                span,
+            });
+        }
+        wrapped
+    }
+}
+
+pub fn bool_value(b: bool) -> i16 {
+    if b { TRUE } else { FALSE }
+}
+
+/// Evaluates an expression, ignoring any issue found along the way.
+pub fn eval(expr: &ast::Expression) -> Option<i16> {
+    Evaluator::new().expression(expr)
+}
@@ -0,0 +1,119 @@
+//! Diagnostics reported by the semantic checks
+
+use crate::span::Span;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A secondary location attached to a diagnostic, e.g. "declared here".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Applicability {
+    /// The suggestion keeps the meaning of the program and can be applied automatically.
+    MachineApplicable,
+    /// The suggestion may change the meaning of the program, so it needs a human to review it.
+    MaybeIncorrect,
+}
+
+/// Replaces the source text in `span` with `replacement`. An empty span is an insertion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    /// Non-overlapping edits, sorted by position.
+    pub edits: Vec<Edit>,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    /// Returns the source text in `span` with the edits falling inside it applied.
+    pub fn apply(&self, source: &str, span: Span) -> String {
+        let mut result = String::new();
+        let mut pos = span.start;
+        for edit in &self.edits {
+            if edit.span.start < span.start || edit.span.end > span.end {
+                continue;
+            }
+            result.push_str(&source[pos..edit.span.start]);
+            result.push_str(&edit.replacement);
+            pos = edit.span.end;
+        }
+        result.push_str(&source[pos..span.end]);
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The code of the lint rule that produced the diagnostic, e.g. `J0101`.
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// The location the diagnostic is primarily about.
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, message, span)
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, message, span)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Suggestion) -> Self {
+        self.suggestions.push(suggestion);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
@@ -0,0 +1,382 @@
+//! Diagnostics rendered for a terminal
+//!
+//! A diagnostic is shown with the source lines it refers to. Its span is underlined with `^` and
+//! each label with `-`; spans covering several lines are drawn as a bar in a gutter left of the
+//! source, from the first line to the last one. Lines far from any span are elided.
+
+use super::{Emitter, SourceFile, severity_str};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::span;
+use std::fmt::Display;
+use std::io::{self, Write};
+
+/// The number of columns a tab is rendered as.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Style {
+    Plain,
+    Error,
+    Warning,
+    /// Line numbers, the gutter and secondary labels.
+    Accent,
+    Bold,
+    Help,
+}
+
+impl Style {
+    fn ansi(self) -> &'static str {
+        match self {
+            Style::Plain => "",
+            Style::Error => "\x1b[1;31m",
+            Style::Warning => "\x1b[1;33m",
+            Style::Accent => "\x1b[1;34m",
+            Style::Bold => "\x1b[1m",
+            Style::Help => "\x1b[1;36m",
+        }
+    }
+
+    fn severity(severity: Severity) -> Style {
+        match severity {
+            Severity::Error => Style::Error,
+            Severity::Warning => Style::Warning,
+        }
+    }
+}
+
+/// Plain-text diagnostics meant to be read by humans, optionally coloured with ANSI escape codes.
+pub struct HumanEmitter<W: Write> {
+    writer: W,
+    color: bool,
+}
+
+impl<W: Write> HumanEmitter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            color: false,
+        }
+    }
+
+    /// Enables or disables colours. They are disabled by default.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Writes an error that doesn't refer to any source file.
+    pub fn error(&mut self, message: impl Display) -> io::Result<()> {
+        let header = self.paint(Style::Error, "error");
+        let message = self.paint(Style::Bold, &message.to_string());
+        writeln!(self.writer, "{}: {}", header, message)
+    }
+
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.color && style != Style::Plain && !text.is_empty() {
+            format!("{}{}\x1b[0m", style.ansi(), text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// A position in the rendered source: a 0-based line and display column.
+#[derive(Debug, Copy, Clone)]
+struct Position {
+    line: usize,
+    col: usize,
+}
+
+impl Position {
+    fn new(source: &str, offset: usize) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count(),
+            col: display_width(&before[line_start..]),
+        }
+    }
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+/// The span of a diagnostic or of one of its labels.
+struct Annotation<'a> {
+    start: Position,
+    /// The position right after the last character of the span.
+    end: Position,
+    label: Option<&'a str>,
+    style: Style,
+}
+
+impl<'a> Annotation<'a> {
+    fn new(source: &str, span: span::Span, label: Option<&'a str>, style: Style) -> Self {
+        // A span ending with a newline is drawn as ending on the line the newline terminates
+        let mut end = span.end.clamp(span.start, source.len());
+        if end > span.start && source[..end].ends_with('\n') {
+            end -= 1;
+        }
+        Self {
+            start: Position::new(source, span.start),
+            end: Position::new(source, end),
+            label,
+            style,
+        }
+    }
+
+    fn is_multiline(&self) -> bool {
+        self.start.line != self.end.line
+    }
+
+    fn mark(&self) -> char {
+        if self.style == Style::Accent {
+            '-'
+        } else {
+            '^'
+        }
+    }
+}
+
+/// One row of output below the line numbers, built from styled characters.
+struct Row {
+    cells: Vec<(char, Style)>,
+}
+
+impl Row {
+    fn new() -> Self {
+        Self { cells: Vec::new() }
+    }
+
+    fn push(&mut self, c: char, style: Style) {
+        self.cells.push((c, style));
+    }
+
+    fn push_str(&mut self, text: &str, style: Style) {
+        self.cells.extend(text.chars().map(|c| (c, style)));
+    }
+
+    fn render<W: Write>(&self, emitter: &HumanEmitter<W>) -> String {
+        let len = self
+            .cells
+            .iter()
+            .rposition(|(c, _)| *c != ' ')
+            .map_or(0, |i| i + 1);
+        let mut result = String::new();
+        let mut run = String::new();
+        let mut run_style = Style::Plain;
+        for &(c, style) in &self.cells[..len] {
+            if style != run_style && !run.is_empty() {
+                result.push_str(&emitter.paint(run_style, &run));
+                run.clear();
+            }
+            run_style = style;
+            run.push(c);
+        }
+        result.push_str(&emitter.paint(run_style, &run));
+        result
+    }
+}
+
+impl<W: Write> Emitter for HumanEmitter<W> {
+    fn emit(&mut self, file: &SourceFile, diagnostic: &Diagnostic) -> io::Result<()> {
+        let severity = Style::severity(diagnostic.severity);
+        let header = match diagnostic.code {
+            Some(code) => format!("{}[{}]", severity_str(diagnostic.severity), code),
+            None => severity_str(diagnostic.severity).to_string(),
+        };
+        writeln!(
+            self.writer,
+            "{}: {}",
+            self.paint(severity, &header),
+            self.paint(Style::Bold, &diagnostic.message)
+        )?;
+
+        let mut annotations = vec![Annotation::new(
+            file.source,
+            diagnostic.span,
+            None,
+            severity,
+        )];
+        annotations.extend(diagnostic.labels.iter().map(|label| {
+            Annotation::new(file.source, label.span, Some(&label.message), Style::Accent)
+        }));
+        let lines: Vec<&str> = file
+            .source
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .collect();
+
+        // Only the lines where a span starts or ends are shown, plus the single lines between
+        // them that would otherwise be replaced by `...`
+        let mut shown: Vec<usize> = annotations
+            .iter()
+            .flat_map(|a| [a.start.line, a.end.line])
+            .collect();
+        shown.sort_unstable();
+        shown.dedup();
+        for i in (1..shown.len()).rev() {
+            if shown[i] == shown[i - 1] + 2 {
+                shown.insert(i, shown[i - 1] + 1);
+            }
+        }
+        let width = (shown.last().unwrap() + 1).to_string().len();
+
+        // Each multi-line annotation gets its own gutter column, in the order they start
+        let mut multiline: Vec<usize> = (0..annotations.len())
+            .filter(|&i| annotations[i].is_multiline())
+            .collect();
+        multiline.sort_by_key(|&i| annotations[i].start.line);
+        let gutter_width = if multiline.is_empty() {
+            0
+        } else {
+            multiline.len() + 1
+        };
+        let mut open = vec![false; multiline.len()];
+
+        let (line, col) = span::line_col(file.source, diagnostic.span.start);
+        writeln!(
+            self.writer,
+            "{:width$}{} {}:{}:{}",
+            "",
+            self.paint(Style::Accent, "-->"),
+            file.path.display(),
+            line,
+            col
+        )?;
+        self.empty_row(width)?;
+
+        let mut previous: Option<usize> = None;
+        for &line in &shown {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                writeln!(self.writer, "{}", self.paint(Style::Accent, "..."))?;
+            }
+            previous = Some(line);
+
+            let mut row = self.gutter(&annotations, &multiline, &open, gutter_width);
+            let text = lines.get(line).copied().unwrap_or("");
+            row.push_str(&text.replace('\t', &" ".repeat(TAB_WIDTH)), Style::Plain);
+            self.write_row(Some(line + 1), width, &row)?;
+
+            // Spans within the line, the diagnostic's own first
+            for annotation in annotations
+                .iter()
+                .filter(|a| !a.is_multiline() && a.start.line == line)
+            {
+                let mut row = self.gutter(&annotations, &multiline, &open, gutter_width);
+                row.push_str(&" ".repeat(annotation.start.col), Style::Plain);
+                let len = (annotation.end.col - annotation.start.col).max(1);
+                row.push_str(&annotation.mark().to_string().repeat(len), annotation.style);
+                if let Some(label) = annotation.label {
+                    row.push(' ', Style::Plain);
+                    row.push_str(label, annotation.style);
+                }
+                self.write_row(None, width, &row)?;
+            }
+
+            // Multi-line spans starting here: `_^` leading from the gutter to the first character
+            for (column, &i) in multiline.iter().enumerate() {
+                let annotation = &annotations[i];
+                if annotation.start.line != line {
+                    continue;
+                }
+                let mut row = self.gutter(&annotations, &multiline, &open, gutter_width);
+                row.cells[column] = (' ', Style::Plain);
+                for cell in &mut row.cells[column + 1..] {
+                    *cell = ('_', annotation.style);
+                }
+                row.push_str(&"_".repeat(annotation.start.col), annotation.style);
+                row.push(annotation.mark(), annotation.style);
+                self.write_row(None, width, &row)?;
+                open[column] = true;
+            }
+
+            // Multi-line spans ending here: `|_^` leading from the gutter to the last character
+            for (column, &i) in multiline.iter().enumerate() {
+                let annotation = &annotations[i];
+                if annotation.end.line != line {
+                    continue;
+                }
+                let mut row = self.gutter(&annotations, &multiline, &open, gutter_width);
+                for cell in &mut row.cells[column + 1..] {
+                    *cell = ('_', annotation.style);
+                }
+                row.push_str(
+                    &"_".repeat(annotation.end.col.saturating_sub(1)),
+                    annotation.style,
+                );
+                row.push(annotation.mark(), annotation.style);
+                if let Some(label) = annotation.label {
+                    row.push(' ', Style::Plain);
+                    row.push_str(label, annotation.style);
+                }
+                self.write_row(None, width, &row)?;
+                open[column] = false;
+            }
+        }
+
+        if !diagnostic.notes.is_empty() || !diagnostic.suggestions.is_empty() {
+            self.empty_row(width)?;
+        }
+        for note in &diagnostic.notes {
+            writeln!(
+                self.writer,
+                "{:width$} = {} {}",
+                "",
+                self.paint(Style::Bold, "note:"),
+                note
+            )?;
+        }
+        for suggestion in &diagnostic.suggestions {
+            writeln!(
+                self.writer,
+                "{:width$} = {} {} `{}`",
+                "",
+                self.paint(Style::Help, "help:"),
+                suggestion.message,
+                suggestion.apply(file.source, diagnostic.span)
+            )?;
+        }
+        writeln!(self.writer)
+    }
+}
+
+impl<W: Write> HumanEmitter<W> {
+    /// Starts a row with the gutter bars of the multi-line spans that are currently open.
+    fn gutter(
+        &self,
+        annotations: &[Annotation],
+        multiline: &[usize],
+        open: &[bool],
+        gutter_width: usize,
+    ) -> Row {
+        let mut row = Row::new();
+        for (column, &i) in multiline.iter().enumerate() {
+            if open[column] {
+                row.push('|', annotations[i].style);
+            } else {
+                row.push(' ', Style::Plain);
+            }
+        }
+        row.push_str(&" ".repeat(gutter_width - multiline.len()), Style::Plain);
+        row
+    }
+
+    fn write_row(&mut self, line: Option<usize>, width: usize, row: &Row) -> io::Result<()> {
+        let number = line.map_or(String::new(), |line| line.to_string());
+        let prefix = self.paint(Style::Accent, &format!("{:>width$} |", number));
+        let content = row.render(self);
+        if content.is_empty() {
+            writeln!(self.writer, "{}", prefix)
+        } else {
+            writeln!(self.writer, "{} {}", prefix, content)
+        }
+    }
+
+    fn empty_row(&mut self, width: usize) -> io::Result<()> {
+        self.write_row(None, width, &Row::new())
+    }
+}
@@ -0,0 +1,253 @@
+//! Writing diagnostics out, for humans or for tools
+//!
+//! The machine-readable formats report spans both as byte offsets and as 1-based line/column
+//! pairs, where columns are counted in characters.
+
+mod human;
+
+pub use human::HumanEmitter;
+
+use crate::diagnostics::{Applicability, Diagnostic, Severity, Suggestion};
+use crate::lint::{Level, Linter};
+use crate::span::{self, Span};
+use serde_json::{Value, json};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A source file the diagnostics refer to.
+#[derive(Debug, Copy, Clone)]
+pub struct SourceFile<'a> {
+    pub path: &'a Path,
+    pub source: &'a str,
+}
+
+pub trait Emitter {
+    fn emit(&mut self, file: &SourceFile, diagnostic: &Diagnostic) -> io::Result<()>;
+
+    /// Called once every diagnostic has been emitted.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn severity_str(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    }
+}
+
+fn location_json(source: &str, span: Span) -> Value {
+    let (line, column) = span::line_col(source, span.start);
+    let (end_line, end_column) = span::line_col(source, span.end);
+    json!({
+        "byte_start": span.start,
+        "byte_end": span.end,
+        "line_start": line,
+        "column_start": column,
+        "line_end": end_line,
+        "column_end": end_column,
+    })
+}
+
+fn applicability_str(applicability: Applicability) -> &'static str {
+    match applicability {
+        Applicability::MachineApplicable => "machine-applicable",
+        Applicability::MaybeIncorrect => "maybe-incorrect",
+    }
+}
+
+/// One JSON object per diagnostic and per line.
+pub struct JsonEmitter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonEmitter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Emitter for JsonEmitter<W> {
+    fn emit(&mut self, file: &SourceFile, diagnostic: &Diagnostic) -> io::Result<()> {
+        let labels: Vec<Value> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                json!({
+                    "message": label.message,
+                    "location": location_json(file.source, label.span),
+                })
+            })
+            .collect();
+        let suggestions: Vec<Value> = diagnostic
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                let edits: Vec<Value> = suggestion
+                    .edits
+                    .iter()
+                    .map(|edit| {
+                        json!({
+                            "location": location_json(file.source, edit.span),
+                            "replacement": edit.replacement,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "message": suggestion.message,
+                    "applicability": applicability_str(suggestion.applicability),
+                    "edits": edits,
+                })
+            })
+            .collect();
+        let value = json!({
+            "file": file.path.display().to_string(),
+            "severity": severity_str(diagnostic.severity),
+            "code": diagnostic.code,
+            "message": diagnostic.message,
+            "location": location_json(file.source, diagnostic.span),
+            "labels": labels,
+            "notes": diagnostic.notes,
+            "suggestions": suggestions,
+        });
+        writeln!(self.writer, "{}", value)
+    }
+}
+
+/// A single SARIF 2.1.0 log, written once every diagnostic has been collected.
+pub struct SarifEmitter<W: Write> {
+    writer: W,
+    rules: Vec<Value>,
+    results: Vec<Value>,
+}
+
+impl<W: Write> SarifEmitter<W> {
+    /// The rules of `linter` are listed as the rules of the tool.
+    pub fn new(writer: W, linter: &Linter) -> Self {
+        let rules = linter
+            .rules()
+            .map(|rule| {
+                let level = match rule.default_level() {
+                    Level::Allow => "none",
+                    Level::Warn => "warning",
+                    Level::Deny => "error",
+                };
+                json!({
+                    "id": rule.code(),
+                    "name": rule.name(),
+                    "shortDescription": { "text": rule.description() },
+                    "defaultConfiguration": { "level": level },
+                })
+            })
+            .collect();
+        Self {
+            writer,
+            rules,
+            results: Vec::new(),
+        }
+    }
+}
+
+fn sarif_region(source: &str, span: Span) -> Value {
+    let (line, column) = span::line_col(source, span.start);
+    let (end_line, end_column) = span::line_col(source, span.end);
+    json!({
+        "startLine": line,
+        "startColumn": column,
+        "endLine": end_line,
+        "endColumn": end_column,
+        "byteOffset": span.start,
+        "byteLength": span.end - span.start,
+    })
+}
+
+fn sarif_location(file: &SourceFile, span: Span) -> Value {
+    json!({
+        "physicalLocation": {
+            "artifactLocation": { "uri": file.path.display().to_string() },
+            "region": sarif_region(file.source, span),
+        }
+    })
+}
+
+fn sarif_fix(file: &SourceFile, suggestion: &Suggestion) -> Value {
+    let replacements: Vec<Value> = suggestion
+        .edits
+        .iter()
+        .map(|edit| {
+            json!({
+                "deletedRegion": {
+                    "byteOffset": edit.span.start,
+                    "byteLength": edit.span.end - edit.span.start,
+                },
+                "insertedContent": { "text": edit.replacement },
+            })
+        })
+        .collect();
+    json!({
+        "description": { "text": suggestion.message },
+        "artifactChanges": [{
+            "artifactLocation": { "uri": file.path.display().to_string() },
+            "replacements": replacements,
+        }],
+    })
+}
+
+impl<W: Write> Emitter for SarifEmitter<W> {
+    fn emit(&mut self, file: &SourceFile, diagnostic: &Diagnostic) -> io::Result<()> {
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push('\n');
+            message.push_str(note);
+        }
+        let related: Vec<Value> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                let mut location = sarif_location(file, label.span);
+                location["message"] = json!({ "text": label.message });
+                location
+            })
+            .collect();
+        // SARIF fixes are meant to be applied as a whole, so only the ones that keep the meaning
+        // of the program are included
+        let fixes: Vec<Value> = diagnostic
+            .suggestions
+            .iter()
+            .filter(|s| s.applicability == Applicability::MachineApplicable)
+            .map(|s| sarif_fix(file, s))
+            .collect();
+
+        let mut result = json!({
+            "level": severity_str(diagnostic.severity),
+            "message": { "text": message },
+            "locations": [sarif_location(file, diagnostic.span)],
+            "relatedLocations": related,
+            "fixes": fixes,
+        });
+        if let Some(code) = diagnostic.code {
+            result["ruleId"] = json!(code);
+        }
+        self.results.push(result);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let log = json!({
+            "version": "2.1.0",
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": self.rules,
+                    }
+                },
+                "results": self.results,
+            }],
+        });
+        writeln!(self.writer, "{:#}", log)
+    }
+}
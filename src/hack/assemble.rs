@@ -0,0 +1,269 @@
+//! The Hack assembler
+//!
+//! An A-instruction `@value` loads a constant or the address of a symbol into A, and becomes the
+//! value itself with the top bit cleared. A C-instruction `dest=comp;jump`, where `dest` and
+//! `jump` are optional, becomes `111a cccc ccdd djjj`: `a` selects M rather than A as the second
+//! input of the ALU, the `c` bits its operation, the `d` bits the registers the result goes to
+//! and the `j` bits the conditions on the result to jump on.
+//!
+//! Symbols are the predefined ones, labels declared with `(LABEL)`, which name the address of the
+//! instruction following them, and variables, allocated from address 16 in the order they first
+//! appear.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+/// The number of instructions the ROM holds.
+const ROM_SIZE: usize = 32768;
+
+/// The address of the first variable.
+const FIRST_VARIABLE: u16 = 16;
+
+/// The address of the screen, where variables must stop.
+const SCREEN: u16 = 16384;
+
+/// The predefined symbols besides `R0`-`R15`.
+const PREDEFINED: [(&str, u16); 7] = [
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("SCREEN", SCREEN),
+    ("KBD", 24576),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    /// The line of the error, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// An instruction before symbols are resolved.
+enum Instruction<'a> {
+    Address(u16),
+    Symbol(&'a str),
+    Compute(u16),
+}
+
+/// Assembles Hack assembly, one instruction or label per line, to machine code. Comments start
+/// with `//` and run to the end of the line, and whitespace and blank lines are ignored.
+pub fn assemble(source: &str) -> Result<Vec<u16>, AssembleError> {
+    // The first pass finds the labels, the second one resolves the symbols
+    let mut symbols = HashMap::new();
+    let mut instructions = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message| AssembleError {
+            line: index + 1,
+            message,
+        };
+        let code = match line.find("//") {
+            Some(start) => &line[..start],
+            None => line,
+        };
+        let code = code.trim();
+        if code.is_empty() {
+            continue;
+        }
+        if instructions.len() == ROM_SIZE {
+            return Err(error(format!(
+                "the program doesn't fit in the {} words of the ROM",
+                ROM_SIZE
+            )));
+        }
+        if let Some(label) = code.strip_prefix('(') {
+            let label = label
+                .strip_suffix(')')
+                .ok_or_else(|| error(format!("expected `)` after label `{}`", label)))?;
+            let label = symbol(label).map_err(error)?;
+            if predefined(label).is_some() {
+                return Err(error(format!("`{}` is a predefined symbol", label)));
+            }
+            if symbols.insert(label, instructions.len() as u16).is_some() {
+                return Err(error(format!("label `{}` is declared twice", label)));
+            }
+            continue;
+        }
+        let instruction = parse_instruction(code).map_err(error)?;
+        instructions.push((index + 1, instruction));
+    }
+
+    let mut next_variable = FIRST_VARIABLE;
+    let mut code = Vec::with_capacity(instructions.len());
+    for (line, instruction) in instructions {
+        code.push(match instruction {
+            Instruction::Address(value) | Instruction::Compute(value) => value,
+            Instruction::Symbol(name) => {
+                if let Some(address) = predefined(name).or_else(|| symbols.get(name).copied()) {
+                    address
+                } else if next_variable == SCREEN {
+                    return Err(AssembleError {
+                        line,
+                        message: format!(
+                            "no memory left for variable `{}`, variables go up to {}",
+                            name,
+                            SCREEN - 1
+                        ),
+                    });
+                } else {
+                    symbols.insert(name, next_variable);
+                    next_variable += 1;
+                    next_variable - 1
+                }
+            }
+        });
+    }
+    Ok(code)
+}
+
+/// Returns the address of a predefined symbol: `SP`, `LCL`, `ARG`, `THIS` and `THAT`, `R0`-`R15`,
+/// `SCREEN` and `KBD`.
+fn predefined(name: &str) -> Option<u16> {
+    if let Some(&(_, address)) = PREDEFINED.iter().find(|&&(symbol, _)| symbol == name) {
+        return Some(address);
+    }
+    let register: u16 = name.strip_prefix('R')?.parse().ok()?;
+    (register < 16 && name == format!("R{}", register)).then_some(register)
+}
+
+/// Returns the machine code as text, one word per line written as 16 binary digits, like `.hack`
+/// files hold it.
+pub fn to_binary(code: &[u16]) -> String {
+    code.iter().map(|word| format!("{:016b}\n", word)).collect()
+}
+
+fn parse_instruction(code: &str) -> Result<Instruction<'_>, String> {
+    if let Some(value) = code.strip_prefix('@') {
+        if value.starts_with(|c: char| c.is_ascii_digit()) {
+            return match value.parse::<u16>() {
+                Ok(value) if value <= i16::MAX as u16 => Ok(Instruction::Address(value)),
+                _ if value.chars().all(|c| c.is_ascii_digit()) => Err(format!(
+                    "constant {} is out of range, the maximum is {}",
+                    value,
+                    i16::MAX
+                )),
+                _ => Err(format!("invalid symbol `{}`", value)),
+            };
+        }
+        return symbol(value).map(Instruction::Symbol);
+    }
+
+    // Whitespace is allowed anywhere in C-instructions
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let (dest, rest) = match code.split_once('=') {
+        Some((dest, rest)) => (Some(dest), rest),
+        None => (None, code.as_str()),
+    };
+    let (comp, jump) = match rest.split_once(';') {
+        Some((comp, jump)) => (comp, Some(jump)),
+        None => (rest, None),
+    };
+    let dest = match dest {
+        Some(dest) => destination(dest).ok_or_else(|| format!("invalid destination `{}`", dest))?,
+        None => 0,
+    };
+    let jump = match jump {
+        Some(jump) => condition(jump).ok_or_else(|| format!("invalid jump `{}`", jump))?,
+        None => 0,
+    };
+    let comp = computation(comp).ok_or_else(|| format!("invalid computation `{}`", comp))?;
+    Ok(Instruction::Compute(
+        0b111 << 13 | comp << 6 | dest << 3 | jump,
+    ))
+}
+
+/// Returns the `a` and `c` bits of a computation. Operands of `+`, `&` and `|` can be given in
+/// either order.
+fn computation(comp: &str) -> Option<u16> {
+    // Computations reading M are the ones reading A, with the `a` bit set
+    let (comp, a) = if comp.contains('M') {
+        (comp.replace('M', "A"), 1)
+    } else {
+        (comp.to_string(), 0)
+    };
+    let comp = match comp.as_str() {
+        "A+D" => "D+A",
+        "A&D" => "D&A",
+        "A|D" => "D|A",
+        "1+D" => "D+1",
+        "1+A" => "A+1",
+        comp => comp,
+    };
+    let bits = match comp {
+        "0" => 0b101010,
+        "1" => 0b111111,
+        "-1" => 0b111010,
+        "D" => 0b001100,
+        "A" => 0b110000,
+        "!D" => 0b001101,
+        "!A" => 0b110001,
+        "-D" => 0b001111,
+        "-A" => 0b110011,
+        "D+1" => 0b011111,
+        "A+1" => 0b110111,
+        "D-1" => 0b001110,
+        "A-1" => 0b110010,
+        "D+A" => 0b000010,
+        "D-A" => 0b010011,
+        "A-D" => 0b000111,
+        "D&A" => 0b000000,
+        "D|A" => 0b010101,
+        _ => return None,
+    };
+    Some(a << 6 | bits)
+}
+
+/// Returns the `d` bits of a destination, made of `A`, `D` and `M` in any order.
+fn destination(dest: &str) -> Option<u16> {
+    let mut bits = 0;
+    for c in dest.chars() {
+        let bit = match c {
+            'A' => 0b100,
+            'D' => 0b010,
+            'M' => 0b001,
+            _ => return None,
+        };
+        if bits & bit != 0 {
+            return None;
+        }
+        bits |= bit;
+    }
+    (bits != 0).then_some(bits)
+}
+
+/// Returns the `j` bits of a jump.
+fn condition(jump: &str) -> Option<u16> {
+    Some(match jump {
+        "JGT" => 0b001,
+        "JEQ" => 0b010,
+        "JGE" => 0b011,
+        "JLT" => 0b100,
+        "JNE" => 0b101,
+        "JLE" => 0b110,
+        "JMP" => 0b111,
+        _ => return None,
+    })
+}
+
+/// Checks a symbol: letters, digits, `_`, `.`, `$` and `:`, not starting with a digit.
+fn symbol(word: &str) -> Result<&str, String> {
+    let valid = !word.is_empty()
+        && !word.starts_with(|c: char| c.is_ascii_digit())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':'));
+    if valid {
+        Ok(word)
+    } else {
+        Err(format!("invalid symbol `{}`", word))
+    }
+}
@@ -0,0 +1,105 @@
+//! Hack assembly conventions shared by the backends
+//!
+//! Generated programs follow the calling convention of the VM, so that code from different
+//! backends can call each other. The stack starts at 256 and `SP` points past its top. A call
+//! pushes the arguments, then the return address and the `LCL`, `ARG`, `THIS` and `THAT` of the
+//! caller, points `ARG` to the first argument and `LCL` to the top of the stack, and jumps to the
+//! label named after the function. The callee pushes its local variables, initialised to 0, and
+//! returns by storing its value where its first argument was, which becomes the top of the stack
+//! of the caller, and restoring the pointers of the caller.
+//!
+//! `R13`, `R14` and `R15` are free for the generated code, but calls and returns overwrite them.
+//!
+//! [`assemble`] turns the assembly into machine code.
+
+mod assemble;
+
+pub use assemble::{AssembleError, assemble, to_binary};
+
+/// Hack assembly code being generated, one instruction or label per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Asm {
+    pub lines: Vec<String>,
+}
+
+impl Asm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn emit(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+    }
+
+    /// Emits several instructions.
+    pub fn emit_all(&mut self, lines: &[&str]) {
+        self.lines.extend(lines.iter().map(|line| line.to_string()));
+    }
+
+    /// Declares a label, e.g. `(LOOP)`.
+    pub fn label(&mut self, label: &str) {
+        self.emit(format!("({})", label));
+    }
+
+    /// Pushes the D register.
+    pub fn push_d(&mut self) {
+        self.emit_all(&["@SP", "AM=M+1", "A=A-1", "M=D"]);
+    }
+
+    /// Sets up the stack and calls `Sys.init`, which starts every program.
+    pub fn bootstrap(&mut self) {
+        self.emit_all(&["@256", "D=A", "@SP", "M=D"]);
+        self.call("Sys.init", 0, "Sys.init$bootstrap");
+    }
+
+    /// Calls a function whose `args` arguments are pushed already, continuing at `return_label`,
+    /// where the value of the call is on top of the stack.
+    pub fn call(&mut self, function: &str, args: u16, return_label: &str) {
+        self.emit(format!("@{}", return_label));
+        self.emit("D=A");
+        self.push_d();
+        for pointer in ["LCL", "ARG", "THIS", "THAT"] {
+            self.emit(format!("@{}", pointer));
+            self.emit("D=M");
+            self.push_d();
+        }
+        self.emit_all(&["@SP", "D=M"]);
+        self.emit(format!("@{}", args as u32 + 5));
+        self.emit_all(&["D=D-A", "@ARG", "M=D", "@SP", "D=M", "@LCL", "M=D"]);
+        self.emit(format!("@{}", function));
+        self.emit("0;JMP");
+        self.label(return_label);
+    }
+
+    /// Starts a function, pushing its local variables.
+    pub fn enter(&mut self, function: &str, locals: u16) {
+        self.label(function);
+        if locals == 0 {
+            return;
+        }
+        self.emit_all(&["@SP", "A=M"]);
+        for i in 0..locals {
+            if i > 0 {
+                self.emit("A=A+1");
+            }
+            self.emit("M=0");
+        }
+        self.emit_all(&["D=A+1", "@SP", "M=D"]);
+    }
+
+    /// Returns the value in the D register to the caller.
+    pub fn return_d(&mut self) {
+        // The return address is read before storing the value, which overwrites it when there
+        // are no arguments
+        self.emit_all(&[
+            "@R15", "M=D", "@LCL", "D=M", "@R13", "M=D", "@5", "A=D-A", "D=M", "@R14", "M=D",
+            "@R15", "D=M", "@ARG", "A=M", "M=D", "@ARG", "D=M+1", "@SP", "M=D",
+        ]);
+        for pointer in ["THAT", "THIS", "ARG", "LCL"] {
+            self.emit_all(&["@R13", "AM=M-1", "D=M"]);
+            self.emit(format!("@{}", pointer));
+            self.emit("M=D");
+        }
+        self.emit_all(&["@R14", "A=M", "0;JMP"]);
+    }
+}
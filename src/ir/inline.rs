@@ -0,0 +1,227 @@
+//! Inlining of getters
+//!
+//! A function or method whose body is a single `return` statement lowers to a single block that
+//! only assigns temporaries before returning, after `this = arg0` for methods. [`inline`] replaces
+//! the calls to such subroutines with their instructions when they're small and don't call
+//! themselves.
+//!
+//! The arguments replace the callee's parameters, and its temporaries become new temporaries of
+//! the caller. Calls passing another number of arguments than the callee takes, which the
+//! compiler doesn't reject, are left as they are. A method called on the current object reads its
+//! fields directly, while one called on another object reads them from memory, at the address of
+//! the object plus the index of the field. Statics belong to a class, so subroutines reading them
+//! are only inlined in their own class, and subroutines reading locals, which hold 0 in a body
+//! without `let` statements, aren't inlined.
+
+use super::{BinaryOp, Function, Inst, Operand, Terminator, Var};
+use crate::ast::SubroutineKind;
+use std::collections::HashMap;
+
+/// The maximum number of instructions of an inlined subroutine, not counting its return.
+const MAX_INSTS: usize = 8;
+
+/// The maximum number of times inlining is repeated, each time inlining the getters called by
+/// the code inlined the previous time.
+const MAX_ROUNDS: usize = 4;
+
+/// Inlines the getters of a program, given as the functions of each of its classes, at their call
+/// sites. Getters calling other getters are inlined too, up to a few levels.
+pub fn inline(program: &mut [Vec<Function>]) {
+    for _ in 0..MAX_ROUNDS {
+        let getters: HashMap<String, Getter> = program
+            .iter()
+            .flatten()
+            .filter_map(|function| Some((function.name.clone(), getter(function)?)))
+            .collect();
+        let mut changed = false;
+        for function in program.iter_mut().flatten() {
+            changed |= inline_calls(function, &getters);
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Inlines the calls to getters of a function, returning whether there were any.
+fn inline_calls(function: &mut Function, getters: &HashMap<String, Getter>) -> bool {
+    let class = class_name(&function.name).to_string();
+    let mut temps = function.temps;
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let mut insts = Vec::with_capacity(block.insts.len());
+        let mut spans = Vec::with_capacity(block.spans.len());
+        let original = std::mem::take(&mut block.insts);
+        for (inst, span) in original.into_iter().zip(std::mem::take(&mut block.spans)) {
+            if let Inst::Call {
+                dst,
+                function: callee,
+                args,
+            } = &inst
+                && let Some(getter) = getters.get(callee)
+                && args.len() == getter.args as usize
+                && (!getter.reads_statics || class_name(callee) == class)
+            {
+                getter.expand(*dst, args, &mut temps, &mut insts);
+                changed = true;
+            } else {
+                insts.push(inst);
+            }
+            // The inlined instructions come from the call
+            spans.resize(insts.len(), span);
+        }
+        block.insts = insts;
+        block.spans = spans;
+    }
+    function.temps = temps;
+    changed
+}
+
+/// The code of a subroutine that can be inlined.
+struct Getter {
+    kind: SubroutineKind,
+    /// The number of arguments, including the current object of methods.
+    args: u16,
+    temps: u16,
+    /// The instructions of the body, without the prologue of methods.
+    insts: Vec<Inst>,
+    value: Operand,
+    reads_statics: bool,
+}
+
+/// Returns the code of a function or method that can be inlined, or `None` if it can't be.
+fn getter(function: &Function) -> Option<Getter> {
+    if function.kind == SubroutineKind::Constructor {
+        return None;
+    }
+    let reachable = function.reachable();
+    if reachable.iter().filter(|&&reachable| reachable).count() != 1 {
+        return None;
+    }
+    let block = &function.blocks[0];
+    let Terminator::Return(value) = block.terminator else {
+        return None;
+    };
+    let mut insts = block.insts.as_slice();
+    if function.kind == SubroutineKind::Method {
+        let prologue = Inst::Copy {
+            dst: Var::This,
+            src: Operand::Var(Var::Argument(0)),
+        };
+        insts = insts.strip_prefix(&[prologue])?;
+    }
+    if insts.len() > MAX_INSTS {
+        return None;
+    }
+    let mut reads_statics = false;
+    for inst in insts {
+        if !matches!(inst.dst(), Some(Var::Temp(_))) {
+            return None;
+        }
+        if let Inst::Call { function: name, .. } = inst
+            && *name == function.name
+        {
+            return None;
+        }
+    }
+    for operand in insts.iter().flat_map(Inst::operands).chain([value]) {
+        match operand.var() {
+            Some(Var::Local(_)) => return None,
+            Some(Var::Static(_)) => reads_statics = true,
+            _ => {}
+        }
+    }
+    Some(Getter {
+        kind: function.kind,
+        args: function.args,
+        temps: function.temps,
+        insts: insts.to_vec(),
+        value,
+        reads_statics,
+    })
+}
+
+impl Getter {
+    /// Appends the instructions replacing `dst = call getter(args)` to `insts`, allocating new
+    /// temporaries from `temps`.
+    fn expand(&self, dst: Option<Var>, args: &[Operand], temps: &mut u16, insts: &mut Vec<Inst>) {
+        // Whether the method is called on the current object of the caller
+        let same_object = self.kind == SubroutineKind::Method && args[0] == Operand::Var(Var::This);
+        // Statics and fields passed as arguments are copied, since the calls the getter makes
+        // could change them
+        let args: Vec<Operand> = args
+            .iter()
+            .map(|&arg| match arg {
+                Operand::Var(Var::Static(_) | Var::Field(_)) => {
+                    let temp = Var::Temp(*temps);
+                    *temps += 1;
+                    insts.push(Inst::Copy {
+                        dst: temp,
+                        src: arg,
+                    });
+                    Operand::Var(temp)
+                }
+                _ => arg,
+            })
+            .collect();
+        let base = *temps;
+        *temps += self.temps;
+        let receiver = (self.kind == SubroutineKind::Method).then(|| args[0]);
+
+        let mut map = |operand: Operand, insts: &mut Vec<Inst>| -> Operand {
+            let Operand::Var(var) = operand else {
+                return operand;
+            };
+            match var {
+                Var::Argument(index) => args[index as usize],
+                Var::Temp(index) => Operand::Var(Var::Temp(base + index)),
+                Var::This => receiver.unwrap(),
+                Var::Field(index) if !same_object => {
+                    let receiver = receiver.unwrap();
+                    let address = if index == 0 {
+                        receiver
+                    } else {
+                        *temps += 1;
+                        let address = Var::Temp(*temps - 1);
+                        insts.push(Inst::Binary {
+                            dst: address,
+                            op: BinaryOp::Add,
+                            lhs: receiver,
+                            rhs: Operand::Const(index as i16),
+                        });
+                        Operand::Var(address)
+                    };
+                    *temps += 1;
+                    let field = Var::Temp(*temps - 1);
+                    insts.push(Inst::Load {
+                        dst: field,
+                        address,
+                    });
+                    Operand::Var(field)
+                }
+                Var::Field(_) | Var::Static(_) | Var::Local(_) => operand,
+            }
+        };
+        for inst in &self.insts {
+            let mut inst = inst.clone();
+            for operand in inst.operands_mut() {
+                *operand = map(*operand, insts);
+            }
+            if let Some(Var::Temp(index)) = inst.dst_mut() {
+                *index += base;
+            }
+            insts.push(inst);
+        }
+        let value = map(self.value, insts);
+        if let Some(dst) = dst {
+            insts.push(Inst::Copy { dst, src: value });
+        }
+    }
+}
+
+/// Returns the class of a VM function, e.g. `Main` for `Main.main`.
+fn class_name(function: &str) -> &str {
+    function
+        .split_once('.')
+        .map_or(function, |(class, _)| class)
+}
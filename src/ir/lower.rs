@@ -0,0 +1,498 @@
+//! Lowering of the AST to the IR
+
+use super::{BinaryOp, Block, BlockId, Function, Inst, Operand, Terminator, UnaryOp, Var};
+use crate::ast;
+use crate::codegen::Options;
+use crate::diagnostics::Diagnostic;
+use crate::resolve::{Receiver, Resolver};
+use crate::span::Span;
+use crate::symbol_table::VarKind;
+
+/// Lowers every subroutine of a class, or returns the errors preventing it. Only the
+/// `pool_strings` option applies to the IR.
+pub fn lower_class(
+    class: &ast::Class,
+    options: &Options,
+) -> Result<Vec<Function>, Vec<Diagnostic>> {
+    let mut lowerer = Lowerer {
+        class,
+        options,
+        resolver: Resolver::new(class),
+        function: None,
+        current: None,
+        span: Span::default(),
+        calls: 0,
+        strings: Vec::new(),
+    };
+    let functions: Vec<Function> = class
+        .subroutines
+        .iter()
+        .map(|subroutine| lowerer.subroutine(subroutine))
+        .collect();
+    if lowerer.resolver.diagnostics.is_empty() {
+        Ok(functions)
+    } else {
+        Err(lowerer.resolver.diagnostics)
+    }
+}
+
+/// A point between two instructions of a block, with the span of the code lowered there.
+#[derive(Debug, Copy, Clone)]
+struct Position {
+    block: BlockId,
+    index: usize,
+    span: Span,
+}
+
+struct Lowerer<'a, 'source> {
+    class: &'a ast::Class<'source>,
+    options: &'a Options,
+    resolver: Resolver<'a, 'source>,
+    /// The function being built.
+    function: Option<Function>,
+    /// The block instructions are added to, or `None` after a `return`, until the next
+    /// instruction starts a new block that nothing jumps to.
+    current: Option<BlockId>,
+    /// The span the next instructions are lowered from.
+    span: Span,
+    /// The number of calls lowered so far, to tell whether an expression makes any.
+    calls: usize,
+    /// The pooled string constants, without quotes, in the order of their static variables.
+    strings: Vec<&'source str>,
+}
+
+impl<'a, 'source> Lowerer<'a, 'source> {
+    fn function(&mut self) -> &mut Function {
+        self.function.as_mut().unwrap()
+    }
+
+    /// Starts a new block and makes it the current one.
+    fn new_block(&mut self) -> BlockId {
+        let span = self.span;
+        let blocks = &mut self.function().blocks;
+        blocks.push(Block {
+            insts: Vec::new(),
+            spans: Vec::new(),
+            terminator: Terminator::Unreachable,
+            terminator_span: span,
+        });
+        let block = blocks.len() - 1;
+        self.current = Some(block);
+        block
+    }
+
+    fn current_block(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => self.new_block(),
+        }
+    }
+
+    fn position(&mut self) -> Position {
+        let block = self.current_block();
+        Position {
+            block,
+            index: self.function().blocks[block].insts.len(),
+            span: self.span,
+        }
+    }
+
+    fn emit(&mut self, inst: Inst) {
+        let block = self.current_block();
+        let span = self.span;
+        let block = &mut self.function().blocks[block];
+        block.insts.push(inst);
+        block.spans.push(span);
+    }
+
+    /// Ends the current block, if any, with `terminator`.
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some(block) = self.current.take() {
+            self.set_terminator(block, terminator);
+        }
+    }
+
+    fn set_terminator(&mut self, block: BlockId, terminator: Terminator) {
+        let span = self.span;
+        let block = &mut self.function().blocks[block];
+        block.terminator = terminator;
+        block.terminator_span = span;
+    }
+
+    fn temp(&mut self) -> Var {
+        let function = self.function();
+        function.temps += 1;
+        Var::Temp(function.temps - 1)
+    }
+
+    /// Assigns `value` to `dst`, making the instruction that computed it assign `dst` directly
+    /// when `value` is the temporary it just introduced.
+    fn assign(&mut self, dst: Var, value: Operand) {
+        let block = self.current_block();
+        let function = self.function.as_mut().unwrap();
+        let last = function.blocks[block].insts.last_mut();
+        if let (Some(inst), Operand::Var(Var::Temp(temp))) = (last, value)
+            && temp + 1 == function.temps
+            && let Some(inst_dst) = inst.dst_mut()
+            && *inst_dst == Var::Temp(temp)
+        {
+            *inst_dst = dst;
+            function.temps -= 1;
+            return;
+        }
+        self.emit(Inst::Copy { dst, src: value });
+    }
+
+    /// Copies `operand` to a temporary at `mark` if it's a variable that calls can assign. It's
+    /// used once a call has been lowered after `mark`, so that the operand keeps the value it had
+    /// when it was evaluated.
+    fn pin(&mut self, operand: Operand, mark: Position) -> Operand {
+        let Operand::Var(var @ (Var::Static(_) | Var::Field(_))) = operand else {
+            return operand;
+        };
+        let temp = self.temp();
+        let block = &mut self.function().blocks[mark.block];
+        block.insts.insert(
+            mark.index,
+            Inst::Copy {
+                dst: temp,
+                src: Operand::Var(var),
+            },
+        );
+        block.spans.insert(mark.index, mark.span);
+        Operand::Var(temp)
+    }
+
+    fn subroutine(&mut self, subroutine: &'a ast::SubroutineDec<'source>) -> Function {
+        self.resolver.enter_subroutine(subroutine);
+        let symbols = &self.resolver.symbols;
+        let is_method = subroutine.kind == ast::SubroutineKind::Method;
+        self.function = Some(Function {
+            name: format!("{}.{}", self.class.name, subroutine.name),
+            kind: subroutine.kind,
+            args: symbols.count(VarKind::Argument) + u16::from(is_method),
+            locals: symbols.count(VarKind::Local),
+            temps: 0,
+            blocks: Vec::new(),
+            span: subroutine.span,
+        });
+        self.span = subroutine.span;
+        self.new_block();
+        match subroutine.kind {
+            ast::SubroutineKind::Constructor => {
+                // The new object holds one word per field
+                let fields = self.resolver.symbols.count(VarKind::Field);
+                self.calls += 1;
+                self.emit(Inst::Call {
+                    dst: Some(Var::This),
+                    function: "Memory.alloc".to_string(),
+                    args: vec![Operand::Const(fields as i16)],
+                });
+            }
+            ast::SubroutineKind::Method => self.emit(Inst::Copy {
+                dst: Var::This,
+                src: Operand::Var(Var::Argument(0)),
+            }),
+            ast::SubroutineKind::Function => {}
+        }
+        self.stmts(&subroutine.body.stmts);
+        self.current = None;
+        self.function.take().unwrap()
+    }
+
+    fn stmts(&mut self, stmts: &ast::Stmts<'source>) {
+        for stmt in &stmts.0 {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt<'source>) {
+        let outer = std::mem::replace(&mut self.span, stmt.span());
+        self.stmt_insts(stmt);
+        self.span = outer;
+    }
+
+    fn stmt_insts(&mut self, stmt: &ast::Stmt<'source>) {
+        match stmt {
+            ast::Stmt::Let(let_stmt) => {
+                let Some(idx_expr) = &let_stmt.idx_expr else {
+                    let value = self.expression(&let_stmt.assign_expr);
+                    if let Some(var) = self.variable(&let_stmt.var_name) {
+                        self.assign(var, value);
+                    }
+                    return;
+                };
+                let address = self.element_address(&let_stmt.var_name, idx_expr);
+                let src = self.expression(&let_stmt.assign_expr);
+                self.emit(Inst::Store { address, src });
+            }
+            ast::Stmt::If(if_stmt) => {
+                let cond = self.expression(&if_stmt.condition);
+                let branch = self.current_block();
+                let then = self.new_block();
+                self.stmts(&if_stmt.stmts);
+                let mut ends = vec![self.current.take()];
+                let otherwise = if_stmt.else_stmts.as_ref().map(|else_stmts| {
+                    let otherwise = self.new_block();
+                    self.stmts(else_stmts);
+                    ends.push(self.current.take());
+                    otherwise
+                });
+                // Nothing follows an `if` statement whose branches all return
+                let end = if otherwise.is_none() || ends.iter().any(Option::is_some) {
+                    let end = self.new_block();
+                    for block in ends.into_iter().flatten() {
+                        self.set_terminator(block, Terminator::Jump(end));
+                    }
+                    Some(end)
+                } else {
+                    None
+                };
+                self.set_terminator(
+                    branch,
+                    Terminator::Branch {
+                        cond,
+                        then,
+                        otherwise: otherwise.or(end).unwrap(),
+                    },
+                );
+            }
+            ast::Stmt::While(while_stmt) => {
+                let header = self.function().blocks.len();
+                self.terminate(Terminator::Jump(header));
+                self.new_block();
+                let cond = self.expression(&while_stmt.condition);
+                let branch = self.current_block();
+                let body = self.new_block();
+                self.stmts(&while_stmt.stmts);
+                self.terminate(Terminator::Jump(header));
+                let end = self.new_block();
+                self.set_terminator(
+                    branch,
+                    Terminator::Branch {
+                        cond,
+                        then: body,
+                        otherwise: end,
+                    },
+                );
+            }
+            ast::Stmt::Do(do_stmt) => {
+                self.call(&do_stmt.call, false);
+            }
+            ast::Stmt::Return(return_stmt) => {
+                let value = match &return_stmt.return_val {
+                    Some(expr) => self.expression(expr),
+                    None => Operand::Const(0),
+                };
+                // A `return` in dead code still gets a block to hold its instructions
+                self.current_block();
+                self.terminate(Terminator::Return(value));
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &ast::Expression<'source>) -> Operand {
+        let mut lhs = self.term(&expr.leading_term);
+        for (op, term) in &expr.following_terms {
+            let (mark, calls) = (self.position(), self.calls);
+            let rhs = self.term(term);
+            if self.calls > calls {
+                lhs = self.pin(lhs, mark);
+            }
+            let dst = self.temp();
+            let outer =
+                std::mem::replace(&mut self.span, Span::new(expr.span.start, term.span.end));
+            self.emit(Inst::Binary {
+                dst,
+                op: match op {
+                    ast::Op::Add => BinaryOp::Add,
+                    ast::Op::Sub => BinaryOp::Sub,
+                    ast::Op::Mul => BinaryOp::Mul,
+                    ast::Op::Div => BinaryOp::Div,
+                    ast::Op::And => BinaryOp::And,
+                    ast::Op::Or => BinaryOp::Or,
+                    ast::Op::Lt => BinaryOp::Lt,
+                    ast::Op::Gt => BinaryOp::Gt,
+                    ast::Op::Eq => BinaryOp::Eq,
+                },
+                lhs,
+                rhs,
+            });
+            self.span = outer;
+            lhs = Operand::Var(dst);
+        }
+        lhs
+    }
+
+    fn term(&mut self, term: &ast::Term<'source>) -> Operand {
+        let outer = std::mem::replace(&mut self.span, term.span);
+        let operand = self.term_insts(term);
+        self.span = outer;
+        operand
+    }
+
+    fn term_insts(&mut self, term: &ast::Term<'source>) -> Operand {
+        use ast::TermKind::*;
+        match &term.kind {
+            IntegerConst(n) => Operand::Const(*n as i16),
+            StringConst(literal) => match self.resolver.string(literal, term.span) {
+                Some(content) => self.string(content),
+                None => Operand::Const(0),
+            },
+            KeywordConst(kw) => match kw {
+                ast::KeywordConst::True => Operand::Const(-1),
+                ast::KeywordConst::False | ast::KeywordConst::Null => Operand::Const(0),
+                ast::KeywordConst::This => {
+                    self.resolver.check_this(term.span, "`this`");
+                    Operand::Var(Var::This)
+                }
+            },
+            VarRef(name) => self.variable(name).map_or(Operand::Const(0), Operand::Var),
+            VarRefWithIdx(name, idx_expr) => {
+                let address = self.element_address(name, idx_expr);
+                let dst = self.temp();
+                self.emit(Inst::Load { dst, address });
+                Operand::Var(dst)
+            }
+            SubroutineCall(call) => self.call(call, true),
+            Expr(expr) => self.expression(expr),
+            UnaryOperation(op, operand) => {
+                let operand = self.term(operand);
+                let dst = self.temp();
+                self.emit(Inst::Unary {
+                    dst,
+                    op: match op {
+                        ast::UnaryOp::Negative => UnaryOp::Neg,
+                        ast::UnaryOp::Neg => UnaryOp::Not,
+                    },
+                    operand,
+                });
+                Operand::Var(dst)
+            }
+        }
+    }
+
+    /// Builds a string constant, or with string pooling, reads the static variable holding it
+    /// after creating it the first time.
+    fn string(&mut self, content: &'source str) -> Operand {
+        if !self.options.pool_strings {
+            return self.new_string(content);
+        }
+        let position = match self.strings.iter().position(|s| *s == content) {
+            Some(position) => position,
+            None => {
+                self.strings.push(content);
+                self.strings.len() - 1
+            }
+        };
+        let var = Var::Static(self.resolver.symbols.count(VarKind::Static) + position as u16);
+        // The static variable is 0 until the string is created
+        let branch = self.current_block();
+        let build = self.new_block();
+        let string = self.new_string(content);
+        self.assign(var, string);
+        let ready = self.function().blocks.len();
+        self.terminate(Terminator::Jump(ready));
+        self.new_block();
+        self.set_terminator(
+            branch,
+            Terminator::Branch {
+                cond: Operand::Var(var),
+                then: ready,
+                otherwise: build,
+            },
+        );
+        Operand::Var(var)
+    }
+
+    fn new_string(&mut self, content: &str) -> Operand {
+        let mut string = self.temp();
+        self.calls += 1;
+        self.emit(Inst::Call {
+            dst: Some(string),
+            function: "String.new".to_string(),
+            args: vec![Operand::Const(content.chars().count() as i16)],
+        });
+        for c in content.chars() {
+            let dst = self.temp();
+            self.calls += 1;
+            self.emit(Inst::Call {
+                dst: Some(dst),
+                function: "String.appendChar".to_string(),
+                args: vec![Operand::Var(string), Operand::Const(c as i16)],
+            });
+            string = dst;
+        }
+        Operand::Var(string)
+    }
+
+    /// Lowers a call, returning its result if `result` is set.
+    fn call(&mut self, call: &ast::SubroutineCall<'source>, result: bool) -> Operand {
+        let Some(callee) = self.resolver.callee(call) else {
+            return Operand::Const(0);
+        };
+        let mut args = Vec::new();
+        match callee.receiver {
+            Some(Receiver::This) => args.push((Operand::Var(Var::This), self.position())),
+            Some(Receiver::Variable(kind, index)) => {
+                args.push((Operand::Var(var(kind, index)), self.position()))
+            }
+            None => {}
+        }
+        for arg in &call.args.0 {
+            let calls = self.calls;
+            let operand = self.expression(arg);
+            if self.calls > calls {
+                // Inserting the copies from the last one keeps the earlier positions valid
+                for (operand, mark) in args.iter_mut().rev() {
+                    *operand = self.pin(*operand, *mark);
+                }
+            }
+            args.push((operand, self.position()));
+        }
+        let dst = result.then(|| self.temp());
+        self.calls += 1;
+        self.emit(Inst::Call {
+            dst,
+            function: callee.name,
+            args: args.into_iter().map(|(operand, _)| operand).collect(),
+        });
+        dst.map_or(Operand::Const(0), Operand::Var)
+    }
+
+    /// Computes the address of the element `idx_expr` of the array `name`.
+    fn element_address(
+        &mut self,
+        name: &ast::VarName,
+        idx_expr: &ast::Expression<'source>,
+    ) -> Operand {
+        let mut base = self.variable(name).map_or(Operand::Const(0), Operand::Var);
+        let (mark, calls) = (self.position(), self.calls);
+        let index = self.expression(idx_expr);
+        if self.calls > calls {
+            base = self.pin(base, mark);
+        }
+        let dst = self.temp();
+        self.emit(Inst::Binary {
+            dst,
+            op: BinaryOp::Add,
+            lhs: base,
+            rhs: index,
+        });
+        Operand::Var(dst)
+    }
+
+    fn variable(&mut self, name: &ast::VarName) -> Option<Var> {
+        let (kind, index) = self.resolver.variable(name)?;
+        Some(var(kind, index))
+    }
+}
+
+fn var(kind: VarKind, index: u16) -> Var {
+    match kind {
+        VarKind::Static => Var::Static(index),
+        VarKind::Field => Var::Field(index),
+        VarKind::Argument => Var::Argument(index),
+        VarKind::Local => Var::Local(index),
+    }
+}
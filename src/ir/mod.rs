@@ -0,0 +1,414 @@
+//! A lowered intermediate representation of subroutines
+//!
+//! Every subroutine becomes a [`Function`]: a control-flow graph of basic blocks, each holding
+//! three-address [`Inst`]ructions and ending with a [`Terminator`]. Instructions read
+//! [`Operand`]s, which are constants or variables, and assign variables: the subroutine's locals
+//! and arguments, the class's statics, the fields of the current object, the current object
+//! itself, and temporaries introduced by the lowering.
+//!
+//! The IR is built from the AST by [`lower_class`], checked by [`verify`], optimised by
+//! [`optimize`] and [`inline`], and turned into VM code by [`to_vm`] or into Hack assembly by
+//! [`to_asm`]. Its textual form, given by the `Display` implementations, looks like:
+//!
+//! ```text
+//! function Main.double kind=function args=1 locals=0 temps=1
+//! b0:
+//!     t0 = arg0 * 2
+//!     return t0
+//! ```
+//!
+//! Every instruction and terminator keeps the span of the Jack code it comes from, which passes
+//! transforming the IR carry over to the instructions replacing it, so that the VM code can be
+//! annotated and mapped back to the source.
+//!
+//! Jack evaluates expressions strictly left to right, and the lowering keeps that order: the
+//! instructions of a block run in order, and reads of statics and fields are copied to a
+//! temporary when a call evaluated after them could change their value.
+
+mod inline;
+mod lower;
+mod opt;
+mod to_asm;
+mod to_vm;
+mod verify;
+
+pub use inline::inline;
+pub use lower::lower_class;
+pub use opt::optimize;
+pub use to_asm::to_asm;
+pub use to_vm::{to_vm, to_vm_with_origins};
+pub use verify::{VerifyError, verify};
+
+use crate::ast::{self, SubroutineKind};
+use crate::codegen::Options;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+use std::fmt::{self, Display};
+
+/// Lowers the classes of a program like [`lower_class`], returning the functions of every class
+/// or the errors preventing it. With `options.inline`, getters are then inlined across classes.
+pub fn lower_program(
+    classes: &[ast::Class],
+    options: &Options,
+) -> Vec<Result<Vec<Function>, Vec<Diagnostic>>> {
+    let mut lowered: Vec<_> = classes
+        .iter()
+        .map(|class| lower_class(class, options))
+        .collect();
+    if options.inline {
+        // Classes with errors are left out, their code wouldn't be used anyway
+        let mut program: Vec<Vec<Function>> = lowered
+            .iter_mut()
+            .map(|result| result.as_mut().map(std::mem::take).unwrap_or_default())
+            .collect();
+        inline(&mut program);
+        for (result, functions) in lowered.iter_mut().zip(program) {
+            if let Ok(lowered) = result {
+                *lowered = functions;
+            }
+        }
+    }
+    lowered
+}
+
+/// The index of a block in [`Function::blocks`].
+pub type BlockId = usize;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Var {
+    Local(u16),
+    /// Methods receive the current object as argument 0.
+    Argument(u16),
+    Static(u16),
+    /// A field of the current object.
+    Field(u16),
+    /// The current object, which constructors and methods assign first.
+    This,
+    Temp(u16),
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Var::Local(index) => write!(f, "local{}", index),
+            Var::Argument(index) => write!(f, "arg{}", index),
+            Var::Static(index) => write!(f, "static{}", index),
+            Var::Field(index) => write!(f, "field{}", index),
+            Var::This => f.write_str("this"),
+            Var::Temp(index) => write!(f, "t{}", index),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Var(Var),
+    /// A 16-bit word, negative if its sign bit is set. `true` is -1.
+    Const(i16),
+}
+
+impl Operand {
+    pub fn var(self) -> Option<Var> {
+        match self {
+            Operand::Var(var) => Some(var),
+            Operand::Const(_) => None,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Var(var) => var.fmt(f),
+            Operand::Const(value) => value.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    /// Arithmetic negation, `-x`.
+    Neg,
+    /// Bitwise negation, `~x`.
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "~",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Eq => "==",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Inst {
+    /// `dst = src`
+    Copy { dst: Var, src: Operand },
+    /// `dst = op operand`
+    Unary {
+        dst: Var,
+        op: UnaryOp,
+        operand: Operand,
+    },
+    /// `dst = lhs op rhs`
+    Binary {
+        dst: Var,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// `dst = mem[address]`, reading an array element.
+    Load { dst: Var, address: Operand },
+    /// `mem[address] = src`, writing an array element.
+    Store { address: Operand, src: Operand },
+    /// `dst = call function(args)`, or without `dst` when the result is discarded.
+    Call {
+        dst: Option<Var>,
+        function: String,
+        args: Vec<Operand>,
+    },
+}
+
+impl Inst {
+    /// Returns the variable the instruction assigns, if any.
+    pub fn dst(&self) -> Option<Var> {
+        match self {
+            Inst::Copy { dst, .. }
+            | Inst::Unary { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::Load { dst, .. } => Some(*dst),
+            Inst::Store { .. } => None,
+            Inst::Call { dst, .. } => *dst,
+        }
+    }
+
+    pub fn dst_mut(&mut self) -> Option<&mut Var> {
+        match self {
+            Inst::Copy { dst, .. }
+            | Inst::Unary { dst, .. }
+            | Inst::Binary { dst, .. }
+            | Inst::Load { dst, .. } => Some(dst),
+            Inst::Store { .. } => None,
+            Inst::Call { dst, .. } => dst.as_mut(),
+        }
+    }
+
+    /// Returns the operands the instruction reads, in the order they're evaluated.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Inst::Copy { src, .. } => vec![*src],
+            Inst::Unary { operand, .. } => vec![*operand],
+            Inst::Binary { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Inst::Load { address, .. } => vec![*address],
+            Inst::Store { address, src } => vec![*address, *src],
+            Inst::Call { args, .. } => args.clone(),
+        }
+    }
+
+    /// Returns mutable references to the operands the instruction reads, in order.
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy { src, .. } => vec![src],
+            Inst::Unary { operand, .. } => vec![operand],
+            Inst::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Inst::Load { address, .. } => vec![address],
+            Inst::Store { address, src } => vec![address, src],
+            Inst::Call { args, .. } => args.iter_mut().collect(),
+        }
+    }
+}
+
+impl Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Copy { dst, src } => write!(f, "{} = {}", dst, src),
+            Inst::Unary { dst, op, operand } => write!(f, "{} = {}{}", dst, op.symbol(), operand),
+            Inst::Binary { dst, op, lhs, rhs } => {
+                write!(f, "{} = {} {} {}", dst, lhs, op.symbol(), rhs)
+            }
+            Inst::Load { dst, address } => write!(f, "{} = mem[{}]", dst, address),
+            Inst::Store { address, src } => write!(f, "mem[{}] = {}", address, src),
+            Inst::Call {
+                dst,
+                function,
+                args,
+            } => {
+                if let Some(dst) = dst {
+                    write!(f, "{} = ", dst)?;
+                }
+                write!(f, "call {}(", function)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    arg.fmt(f)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Jumps to `then` if `cond` isn't 0, and to `otherwise` if it is.
+    Branch {
+        cond: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    Return(Operand),
+    /// Ends a block that control never reaches the end of, such as the last block of a
+    /// subroutine that returns on every path.
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operand(&self) -> Option<Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => Some(*cond),
+            Terminator::Return(value) => Some(*value),
+            Terminator::Jump(_) | Terminator::Unreachable => None,
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "goto b{}", target),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "if {} goto b{} else b{}", cond, then, otherwise),
+            Terminator::Return(value) => write!(f, "return {}", value),
+            Terminator::Unreachable => f.write_str("unreachable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    /// The span of the Jack code every instruction comes from, in the same order: the innermost
+    /// term, operation or statement, or the subroutine for the prologue.
+    pub spans: Vec<Span>,
+    pub terminator: Terminator,
+    /// The span of the Jack code the terminator comes from.
+    pub terminator_span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The name of the VM function, e.g. `Main.main`.
+    pub name: String,
+    pub kind: SubroutineKind,
+    /// The number of arguments, including the current object of methods.
+    pub args: u16,
+    pub locals: u16,
+    pub temps: u16,
+    /// The blocks of the function, control entering at the first one.
+    pub blocks: Vec<Block>,
+    pub span: Span,
+}
+
+impl Function {
+    /// Returns whether each block can be reached from the entry block.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        while let Some(block) = pending.pop() {
+            if block >= self.blocks.len() || reachable[block] {
+                continue;
+            }
+            reachable[block] = true;
+            pending.extend(self.blocks[block].terminator.successors());
+        }
+        reachable
+    }
+
+    /// Returns the functions the function calls, in order, including `Math.multiply` and
+    /// `Math.divide`, which the backends call for multiplications and divisions.
+    pub fn callees(&self) -> Vec<&str> {
+        let mut callees = Vec::new();
+        for inst in self.blocks.iter().flat_map(|block| &block.insts) {
+            match inst {
+                Inst::Call { function, .. } => callees.push(function.as_str()),
+                Inst::Binary {
+                    op: BinaryOp::Mul, ..
+                } => callees.push("Math.multiply"),
+                Inst::Binary {
+                    op: BinaryOp::Div, ..
+                } => callees.push("Math.divide"),
+                _ => {}
+            }
+        }
+        callees
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            SubroutineKind::Constructor => "constructor",
+            SubroutineKind::Function => "function",
+            SubroutineKind::Method => "method",
+        };
+        writeln!(
+            f,
+            "function {} kind={} args={} locals={} temps={}",
+            self.name, kind, self.args, self.locals, self.temps
+        )?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "b{}:", id)?;
+            for inst in &block.insts {
+                writeln!(f, "    {}", inst)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
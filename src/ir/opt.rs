@@ -0,0 +1,451 @@
+//! Optimisations of the IR
+//!
+//! [`optimize`] repeats the following rewrites until none applies:
+//!
+//! - Constant folding: operations on constants are computed, temporaries holding a constant or a
+//!   copy of another temporary are replaced by it, and branches on a constant become jumps.
+//! - Reassociation of additions and subtractions of constants, so that `x + 1 + 2` becomes
+//!   `x + 3`.
+//! - Algebraic simplification of `x + 0`, `x - 0`, `x * 1`, `x * 0`, `x / 1`, `x & -1`, `x | 0`,
+//!   their mirrored forms, `~~x` and `--x`.
+//! - Strength reduction of multiplications by a power of two to an addition chain doubling the
+//!   other operand, and of multiplications and divisions by -1 to a negation. Divisions by other
+//!   powers of two are left to `Math.divide`: without shifts, the VM can't compute them more
+//!   cheaply, as they round towards 0.
+//! - Removal of copies of a variable to itself, which would be a `push` and `pop` of the same
+//!   location, and of the computations of temporaries that are never read.
+
+use super::{BinaryOp, Function, Inst, Operand, Terminator, UnaryOp, Var};
+
+/// The maximum number of rounds of rewrites, as a safeguard.
+const MAX_ROUNDS: usize = 16;
+
+/// Optimises a function in place.
+pub fn optimize(function: &mut Function) {
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = propagate(function);
+        changed |= simplify(function);
+        changed |= remove_dead_code(function);
+        if !changed {
+            break;
+        }
+    }
+    compact_temps(function);
+}
+
+/// Returns how many times every temporary is assigned.
+fn assignment_counts(function: &Function) -> Vec<usize> {
+    let mut counts = vec![0; function.temps as usize];
+    for inst in function.blocks.iter().flat_map(|block| &block.insts) {
+        if let Some(Var::Temp(temp)) = inst.dst() {
+            counts[temp as usize] += 1;
+        }
+    }
+    counts
+}
+
+/// Returns the instruction assigning every temporary assigned exactly once, by block and index.
+fn single_definitions(function: &Function) -> Vec<Option<(usize, usize)>> {
+    let counts = assignment_counts(function);
+    let mut definitions = vec![None; function.temps as usize];
+    for (block_id, block) in function.blocks.iter().enumerate() {
+        for (index, inst) in block.insts.iter().enumerate() {
+            if let Some(Var::Temp(temp)) = inst.dst()
+                && counts[temp as usize] == 1
+            {
+                definitions[temp as usize] = Some((block_id, index));
+            }
+        }
+    }
+    definitions
+}
+
+/// Replaces the temporaries assigned once a constant, or a temporary itself assigned once, by
+/// that value. As they're assigned once, their assignment comes before any read.
+fn propagate(function: &mut Function) -> bool {
+    let definitions = single_definitions(function);
+    let values: Vec<Option<Operand>> = definitions
+        .iter()
+        .map(|definition| {
+            let (block, index) = (*definition)?;
+            match function.blocks[block].insts[index] {
+                Inst::Copy {
+                    src: src @ Operand::Const(_),
+                    ..
+                } => Some(src),
+                Inst::Copy {
+                    src: src @ Operand::Var(Var::Temp(temp)),
+                    ..
+                } if definitions[temp as usize].is_some() => Some(src),
+                _ => None,
+            }
+        })
+        .collect();
+    let resolve = |mut operand: Operand| {
+        // Chains of copies end, since a temporary's copy is assigned before it
+        while let Operand::Var(Var::Temp(temp)) = operand
+            && let Some(value) = values[temp as usize]
+        {
+            operand = value;
+        }
+        operand
+    };
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            for operand in inst.operands_mut() {
+                let value = resolve(*operand);
+                changed |= value != *operand;
+                *operand = value;
+            }
+        }
+        match &mut block.terminator {
+            Terminator::Branch { cond: operand, .. } | Terminator::Return(operand) => {
+                let value = resolve(*operand);
+                changed |= value != *operand;
+                *operand = value;
+            }
+            Terminator::Jump(_) | Terminator::Unreachable => {}
+        }
+    }
+    changed
+}
+
+fn simplify(function: &mut Function) -> bool {
+    let mut changed = false;
+    let mut definitions = single_definitions(function);
+    for block_id in 0..function.blocks.len() {
+        let mut index = 0;
+        while index < function.blocks[block_id].insts.len() {
+            let inst = function.blocks[block_id].insts[index].clone();
+            match simplify_inst(function, &definitions, block_id, index, &inst) {
+                Some(replacement) => {
+                    // The replacement comes from the same code
+                    let block = &mut function.blocks[block_id];
+                    let span = block.spans[index];
+                    let spans = vec![span; replacement.len()];
+                    block.insts.splice(index..=index, replacement);
+                    block.spans.splice(index..=index, spans);
+                    definitions = single_definitions(function);
+                    changed = true;
+                    // The replacement may be simplified further
+                }
+                None => index += 1,
+            }
+        }
+        let block = &mut function.blocks[block_id];
+        if let Terminator::Branch {
+            cond: Operand::Const(cond),
+            then,
+            otherwise,
+        } = block.terminator
+        {
+            block.terminator = Terminator::Jump(if cond != 0 { then } else { otherwise });
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Returns the instructions replacing `inst`, the instruction at `index` in the block `block`,
+/// if it can be simplified.
+fn simplify_inst(
+    function: &mut Function,
+    definitions: &[Option<(usize, usize)>],
+    block: usize,
+    index: usize,
+    inst: &Inst,
+) -> Option<Vec<Inst>> {
+    let copy = |dst, src| Some(vec![Inst::Copy { dst, src }]);
+    let unary = |dst, op, operand| Some(vec![Inst::Unary { dst, op, operand }]);
+    // The operation defining a temporary in the same block, if its operands still hold the
+    // values they had then
+    let defining = |operand: Operand| {
+        let Operand::Var(Var::Temp(temp)) = operand else {
+            return None;
+        };
+        let (def_block, def_index) = definitions[temp as usize]?;
+        if def_block != block || def_index >= index {
+            return None;
+        }
+        let insts = &function.blocks[block].insts;
+        let between = &insts[def_index + 1..index];
+        let unchanged = insts[def_index]
+            .operands()
+            .iter()
+            .all(|operand| match operand {
+                Operand::Var(var) => between.iter().all(|inst| !may_assign(inst, *var)),
+                Operand::Const(_) => true,
+            });
+        unchanged.then(|| insts[def_index].clone())
+    };
+
+    // Temporaries copied from a variable that still holds the same value read the variable
+    let mut propagated = inst.clone();
+    let mut copied = false;
+    for operand in propagated.operands_mut() {
+        if let Some(Inst::Copy { src, .. }) = defining(*operand) {
+            *operand = src;
+            copied = true;
+        }
+    }
+    if copied {
+        return Some(vec![propagated]);
+    }
+
+    match *inst {
+        Inst::Copy {
+            dst,
+            src: Operand::Var(src),
+        } if dst == src => Some(Vec::new()),
+        Inst::Unary {
+            dst,
+            op,
+            operand: Operand::Const(value),
+        } => copy(
+            dst,
+            Operand::Const(match op {
+                UnaryOp::Neg => value.wrapping_neg(),
+                UnaryOp::Not => !value,
+            }),
+        ),
+        // ~~x and --x
+        Inst::Unary { dst, op, operand } => match defining(operand) {
+            Some(Inst::Unary {
+                op: inner_op,
+                operand: inner,
+                ..
+            }) if inner_op == op => copy(dst, inner),
+            _ => None,
+        },
+        Inst::Binary {
+            dst,
+            op,
+            lhs: Operand::Const(lhs),
+            rhs: Operand::Const(rhs),
+        } => fold(op, lhs, rhs).and_then(|value| copy(dst, Operand::Const(value))),
+        Inst::Binary {
+            dst,
+            op,
+            lhs: Operand::Const(lhs),
+            rhs,
+        } if op != BinaryOp::Sub && op != BinaryOp::Div => {
+            // Constants go on the right of commutative operations
+            let op = match op {
+                BinaryOp::Lt => BinaryOp::Gt,
+                BinaryOp::Gt => BinaryOp::Lt,
+                op => op,
+            };
+            Some(vec![Inst::Binary {
+                dst,
+                op,
+                lhs: rhs,
+                rhs: Operand::Const(lhs),
+            }])
+        }
+        Inst::Binary {
+            dst,
+            op: BinaryOp::Sub,
+            lhs: Operand::Const(0),
+            rhs,
+        } => unary(dst, UnaryOp::Neg, rhs),
+        Inst::Binary {
+            dst,
+            op,
+            lhs,
+            rhs: Operand::Const(rhs),
+        } => match (op, rhs) {
+            (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or, 0)
+            | (BinaryOp::Mul | BinaryOp::Div, 1)
+            | (BinaryOp::And, -1) => copy(dst, lhs),
+            (BinaryOp::Mul | BinaryOp::And, 0) => copy(dst, Operand::Const(0)),
+            (BinaryOp::Or, -1) => copy(dst, Operand::Const(-1)),
+            (BinaryOp::Mul | BinaryOp::Div, -1) => unary(dst, UnaryOp::Neg, lhs),
+            (BinaryOp::Mul, _) => multiplication(function, dst, lhs, rhs),
+            (BinaryOp::Add | BinaryOp::Sub, _) => {
+                let offset = if op == BinaryOp::Add {
+                    rhs
+                } else {
+                    rhs.wrapping_neg()
+                };
+                reassociate(defining(lhs), dst, offset)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Computes an operation on constants, unless it's a division by 0, which has to fail at run
+/// time.
+fn fold(op: BinaryOp, lhs: i16, rhs: i16) -> Option<i16> {
+    let bool_value = |b: bool| if b { -1 } else { 0 };
+    Some(match op {
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div if rhs == 0 => return None,
+        BinaryOp::Div => lhs.wrapping_div(rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Lt => bool_value(lhs < rhs),
+        BinaryOp::Gt => bool_value(lhs > rhs),
+        BinaryOp::Eq => bool_value(lhs == rhs),
+    })
+}
+
+/// Rewrites `dst = lhs * factor` as an addition chain when `factor` is a power of two, or its
+/// opposite, doubling `lhs` into new temporaries.
+fn multiplication(
+    function: &mut Function,
+    dst: Var,
+    lhs: Operand,
+    factor: i16,
+) -> Option<Vec<Inst>> {
+    let magnitude = factor.checked_abs()?;
+    if magnitude.count_ones() != 1 {
+        return None;
+    }
+    let doublings = magnitude.trailing_zeros();
+    let mut insts = Vec::new();
+    let mut value = lhs;
+    for i in 0..doublings {
+        let target = if i + 1 == doublings && factor > 0 {
+            dst
+        } else {
+            function.temps += 1;
+            Var::Temp(function.temps - 1)
+        };
+        insts.push(Inst::Binary {
+            dst: target,
+            op: BinaryOp::Add,
+            lhs: value,
+            rhs: value,
+        });
+        value = Operand::Var(target);
+    }
+    if factor < 0 {
+        insts.push(Inst::Unary {
+            dst,
+            op: UnaryOp::Neg,
+            operand: value,
+        });
+    }
+    Some(insts)
+}
+
+/// Rewrites `dst = t + offset` as a single addition when `t` is itself the sum or difference of
+/// a value and a constant.
+fn reassociate(definition: Option<Inst>, dst: Var, offset: i16) -> Option<Vec<Inst>> {
+    let Inst::Binary {
+        op: inner_op @ (BinaryOp::Add | BinaryOp::Sub),
+        lhs: base,
+        rhs: Operand::Const(inner),
+        ..
+    } = definition?
+    else {
+        return None;
+    };
+    let inner = if inner_op == BinaryOp::Add {
+        inner
+    } else {
+        inner.wrapping_neg()
+    };
+    let offset = inner.wrapping_add(offset);
+    // Subtracting keeps the constant positive, which is cheaper to push
+    let (op, rhs) = if offset < 0 && offset != i16::MIN {
+        (BinaryOp::Sub, -offset)
+    } else {
+        (BinaryOp::Add, offset)
+    };
+    Some(vec![Inst::Binary {
+        dst,
+        op,
+        lhs: base,
+        rhs: Operand::Const(rhs),
+    }])
+}
+
+/// Returns whether an instruction may change the value of a variable. Calls may change any
+/// static variable and field, but not the variables of the caller, and so may array writes, since
+/// an array can point to an object, or to the memory of the static variables.
+fn may_assign(inst: &Inst, var: Var) -> bool {
+    inst.dst() == Some(var)
+        || matches!(inst, Inst::Call { .. } | Inst::Store { .. })
+            && matches!(var, Var::Static(_) | Var::Field(_))
+}
+
+/// Removes the instructions assigning temporaries that are never read, except calls, which only
+/// lose their result.
+fn remove_dead_code(function: &mut Function) -> bool {
+    let mut read = vec![false; function.temps as usize];
+    for block in &function.blocks {
+        let operands = block.insts.iter().flat_map(Inst::operands);
+        for operand in operands.chain(block.terminator.operand()) {
+            if let Operand::Var(Var::Temp(temp)) = operand {
+                read[temp as usize] = true;
+            }
+        }
+    }
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let mut spans = std::mem::take(&mut block.spans).into_iter();
+        block.insts.retain_mut(|inst| {
+            let span = spans.next().unwrap();
+            let keep = match inst.dst() {
+                Some(Var::Temp(temp)) if !read[temp as usize] => {
+                    changed = true;
+                    if let Inst::Call { dst, .. } = inst {
+                        *dst = None;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                _ => true,
+            };
+            if keep {
+                block.spans.push(span);
+            }
+            keep
+        });
+    }
+    changed
+}
+
+/// Renumbers the temporaries still in use from 0, in the order they appear.
+fn compact_temps(function: &mut Function) {
+    let mut numbers: Vec<Option<u16>> = vec![None; function.temps as usize];
+    let mut count = 0;
+    let mut renumber = |var: &mut Var| {
+        if let Var::Temp(temp) = var {
+            let number = numbers[*temp as usize].get_or_insert_with(|| {
+                count += 1;
+                count - 1
+            });
+            *temp = *number;
+        }
+    };
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            for operand in inst.operands_mut() {
+                if let Operand::Var(var) = operand {
+                    renumber(var);
+                }
+            }
+            if let Some(dst) = inst.dst_mut() {
+                renumber(dst);
+            }
+        }
+        if let Terminator::Branch {
+            cond: Operand::Var(var),
+            ..
+        }
+        | Terminator::Return(Operand::Var(var)) = &mut block.terminator
+        {
+            renumber(var);
+        }
+    }
+    function.temps = count;
+}
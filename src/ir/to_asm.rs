@@ -0,0 +1,601 @@
+//! Translation of the IR to Hack assembly
+//!
+//! Functions follow the calling convention of the VM, described in [`crate::hack`], but only use
+//! the stack to pass arguments: instructions compute their result in the D register and store it
+//! to its variable right away. Locals, arguments and fields are addressed through `LCL`, `ARG`
+//! and `THIS`, statics get the symbol `Class.<index>` like the VM translator gives them, and the
+//! current object is `THIS` itself.
+//!
+//! A temporary assigned once and only read further in the same block, with no call in between,
+//! is kept in `R13` or `R14`. Other temporaries are stored in local variables following the
+//! declared ones. `R15` holds values while the address they're stored to is computed.
+//!
+//! Comparisons subtract their operands and test the sign of the difference, like the usual VM
+//! translators, and a comparison only read by the branch that follows it jumps directly. Blocks
+//! are laid out in order, leaving out the ones control never reaches, and each block that a jump
+//! targets gets the label `Class.function$b<n>`, after its index.
+
+use super::{BinaryOp, Function, Inst, Operand, Terminator, UnaryOp, Var};
+use crate::hack::Asm;
+use std::collections::HashMap;
+
+/// The registers temporaries are kept in.
+const REGISTERS: [&str; 2] = ["R13", "R14"];
+
+/// Appends the Hack assembly code of a function to `asm`.
+pub fn to_asm(function: &Function, asm: &mut Asm) {
+    Translator::new(function, asm).translate();
+}
+
+/// Where a temporary is kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Location {
+    Register(&'static str),
+    /// The index of the local variable holding it.
+    Slot(u16),
+}
+
+/// Returns how many times every temporary of a function is assigned and read.
+fn count_accesses(function: &Function) -> (Vec<usize>, Vec<usize>) {
+    let temps = function.temps as usize;
+    let mut assignments = vec![0; temps];
+    let mut reads = vec![0; temps];
+    for block in &function.blocks {
+        for inst in &block.insts {
+            if let Some(Var::Temp(temp)) = inst.dst() {
+                assignments[temp as usize] += 1;
+            }
+        }
+        let operands = block.insts.iter().flat_map(Inst::operands);
+        for operand in operands.chain(block.terminator.operand()) {
+            if let Some(Var::Temp(temp)) = operand.var() {
+                reads[temp as usize] += 1;
+            }
+        }
+    }
+    (assignments, reads)
+}
+
+/// Returns where every temporary of a function is kept, and the number of local variables used
+/// for them.
+fn locations(function: &Function, assignments: &[usize], reads: &[usize]) -> (Vec<Location>, u16) {
+    let mut registers = vec![None; function.temps as usize];
+    for block in &function.blocks {
+        // The position of the last read of every temporary assigned in the block, the terminator
+        // coming last
+        let mut last_reads = HashMap::new();
+        let mut read_counts: HashMap<u16, usize> = HashMap::new();
+        let operands = block.insts.iter().map(Inst::operands);
+        for (position, operands) in operands
+            .chain([block.terminator.operand().into_iter().collect()])
+            .enumerate()
+        {
+            for operand in operands {
+                if let Some(Var::Temp(temp)) = operand.var() {
+                    last_reads.insert(temp, position);
+                    *read_counts.entry(temp).or_default() += 1;
+                }
+            }
+        }
+        // When each register is free again
+        let mut free = [0; REGISTERS.len()];
+        for (position, inst) in block.insts.iter().enumerate() {
+            let Some(Var::Temp(temp)) = inst.dst() else {
+                continue;
+            };
+            let Some(&last_read) = last_reads.get(&temp) else {
+                continue;
+            };
+            let index = temp as usize;
+            if assignments[index] != 1
+                || read_counts[&temp] != reads[index]
+                || last_read <= position
+                || block.insts[position + 1..last_read].iter().any(calls)
+            {
+                continue;
+            }
+            if let Some(register) =
+                (0..REGISTERS.len()).find(|&register| free[register] <= position)
+            {
+                registers[index] = Some(REGISTERS[register]);
+                free[register] = last_read;
+            }
+        }
+    }
+
+    let mut slots = 0;
+    let locations = registers
+        .into_iter()
+        .map(|register| match register {
+            Some(register) => Location::Register(register),
+            None => {
+                slots += 1;
+                Location::Slot(function.locals + slots - 1)
+            }
+        })
+        .collect();
+    (locations, slots)
+}
+
+/// Returns whether an instruction calls a function, which overwrites the registers.
+fn calls(inst: &Inst) -> bool {
+    matches!(
+        inst,
+        Inst::Call { .. }
+            | Inst::Binary {
+                op: BinaryOp::Mul | BinaryOp::Div,
+                ..
+            }
+    )
+}
+
+struct Translator<'a> {
+    function: &'a Function,
+    class: &'a str,
+    locations: Vec<Location>,
+    slots: u16,
+    /// How many times every temporary is read.
+    reads: Vec<usize>,
+    asm: &'a mut Asm,
+    /// The number of labels generated for calls and comparisons so far.
+    labels: usize,
+}
+
+impl<'a> Translator<'a> {
+    fn new(function: &'a Function, asm: &'a mut Asm) -> Self {
+        let (assignments, reads) = count_accesses(function);
+        let (locations, slots) = locations(function, &assignments, &reads);
+        let class = function
+            .name
+            .split_once('.')
+            .map_or(function.name.as_str(), |(class, _)| class);
+        Self {
+            function,
+            class,
+            locations,
+            slots,
+            reads,
+            asm,
+            labels: 0,
+        }
+    }
+
+    fn emit(&mut self, line: impl Into<String>) {
+        self.asm.emit(line);
+    }
+
+    /// Emits an A-instruction loading a symbol or number.
+    fn at(&mut self, value: impl std::fmt::Display) {
+        self.emit(format!("@{}", value));
+    }
+
+    fn block_label(&self, block: usize) -> String {
+        format!("{}$b{}", self.function.name, block)
+    }
+
+    fn new_label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!("{}${}.{}", self.function.name, kind, self.labels)
+    }
+
+    fn translate(mut self) {
+        let function = self.function;
+        self.asm.enter(&function.name, function.locals + self.slots);
+        let reachable = function.reachable();
+        let layout: Vec<usize> = (0..function.blocks.len())
+            .filter(|&id| reachable[id])
+            .collect();
+
+        // The blocks jumped to, which need a label
+        let mut targets = vec![false; function.blocks.len()];
+        for (position, &id) in layout.iter().enumerate() {
+            let next = layout.get(position + 1).copied();
+            match function.blocks[id].terminator {
+                Terminator::Jump(target) => targets[target] |= Some(target) != next,
+                Terminator::Branch {
+                    then, otherwise, ..
+                } => {
+                    if Some(then) == next {
+                        targets[otherwise] = true;
+                    } else {
+                        targets[then] = true;
+                        targets[otherwise] |= Some(otherwise) != next;
+                    }
+                }
+                Terminator::Return(_) | Terminator::Unreachable => {}
+            }
+        }
+
+        for (position, &id) in layout.iter().enumerate() {
+            if targets[id] {
+                let label = self.block_label(id);
+                self.asm.label(&label);
+            }
+            let block = &function.blocks[id];
+            let mut insts = block.insts.as_slice();
+            // A comparison only read by the branch jumps directly
+            let mut comparison = None;
+            if let Terminator::Branch {
+                cond: Operand::Var(cond @ Var::Temp(temp)),
+                ..
+            } = block.terminator
+                && let Some((
+                    Inst::Binary {
+                        dst,
+                        op: op @ (BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq),
+                        lhs,
+                        rhs,
+                    },
+                    rest,
+                )) = insts.split_last()
+                && *dst == cond
+                && self.reads[temp as usize] == 1
+            {
+                comparison = Some((*op, *lhs, *rhs));
+                insts = rest;
+            }
+            for inst in insts {
+                self.inst(inst);
+            }
+
+            let next = layout.get(position + 1).copied();
+            match block.terminator {
+                Terminator::Jump(target) => {
+                    if Some(target) != next {
+                        self.jump(target, "0", "JMP");
+                    }
+                }
+                Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    // The condition holds when D passes the jump
+                    let (holds, fails) = match comparison {
+                        Some((op, lhs, rhs)) => {
+                            self.difference(lhs, rhs);
+                            match op {
+                                BinaryOp::Lt => ("JLT", "JGE"),
+                                BinaryOp::Gt => ("JGT", "JLE"),
+                                _ => ("JEQ", "JNE"),
+                            }
+                        }
+                        None => {
+                            self.load(cond);
+                            ("JNE", "JEQ")
+                        }
+                    };
+                    if Some(then) == next {
+                        self.jump(otherwise, "D", fails);
+                    } else {
+                        self.jump(then, "D", holds);
+                        if Some(otherwise) != next {
+                            self.jump(otherwise, "0", "JMP");
+                        }
+                    }
+                }
+                Terminator::Return(value) => {
+                    self.load(value);
+                    self.asm.return_d();
+                }
+                Terminator::Unreachable => {}
+            }
+        }
+    }
+
+    fn jump(&mut self, block: usize, comp: &str, jump: &str) {
+        let label = self.block_label(block);
+        self.at(label);
+        self.emit(format!("{};{}", comp, jump));
+    }
+
+    /// Points A to a variable. When `keep_d` is set, the D register must be left untouched, and
+    /// nothing is emitted if that isn't possible.
+    fn address(&mut self, var: Var, keep_d: bool) -> bool {
+        let (base, index) = match var {
+            Var::Static(index) => {
+                let symbol = format!("{}.{}", self.class, index);
+                self.at(symbol);
+                return true;
+            }
+            Var::This => {
+                self.at("THIS");
+                return true;
+            }
+            Var::Local(index) => ("LCL", index),
+            Var::Argument(index) => ("ARG", index),
+            Var::Field(index) => ("THIS", index),
+            Var::Temp(temp) => match self.locations[temp as usize] {
+                Location::Register(register) => {
+                    self.at(register);
+                    return true;
+                }
+                Location::Slot(index) => ("LCL", index),
+            },
+        };
+        match index {
+            0 => {
+                self.at(base);
+                self.emit("A=M");
+            }
+            1 | 2 => {
+                self.at(base);
+                self.emit("A=M+1");
+                if index == 2 {
+                    self.emit("A=A+1");
+                }
+            }
+            _ if keep_d => return false,
+            _ => {
+                self.at(index);
+                self.emit("D=A");
+                self.at(base);
+                self.emit("A=D+M");
+            }
+        }
+        true
+    }
+
+    /// Returns whether [`Self::address`] can point A to a variable without overwriting D.
+    fn keeps_d(&self, var: Var) -> bool {
+        match var {
+            Var::Static(_) | Var::This => true,
+            Var::Local(index) | Var::Argument(index) | Var::Field(index) => index <= 2,
+            Var::Temp(temp) => match self.locations[temp as usize] {
+                Location::Register(_) => true,
+                Location::Slot(index) => index <= 2,
+            },
+        }
+    }
+
+    /// Loads an operand into the D register.
+    fn load(&mut self, operand: Operand) {
+        match operand {
+            Operand::Const(value @ -1..=1) => self.emit(format!("D={}", value)),
+            Operand::Const(i16::MIN) => {
+                self.at(i16::MAX);
+                self.emit("D=!A");
+            }
+            Operand::Const(value) if value < 0 => {
+                self.at(value.unsigned_abs());
+                self.emit("D=-A");
+            }
+            Operand::Const(value) => {
+                self.at(value);
+                self.emit("D=A");
+            }
+            Operand::Var(var) => {
+                self.address(var, false);
+                self.emit("D=M");
+            }
+        }
+    }
+
+    /// Stores the D register to a variable.
+    fn store(&mut self, dst: Var) {
+        if self.address(dst, true) {
+            self.emit("M=D");
+            return;
+        }
+        // Adding the address to the value and subtracting the value stored in R15 gets the
+        // address into A, and subtracting it gets the value back
+        let (base, index) = match dst {
+            Var::Local(index) => ("LCL", index),
+            Var::Argument(index) => ("ARG", index),
+            Var::Field(index) => ("THIS", index),
+            Var::Temp(temp) => match self.locations[temp as usize] {
+                Location::Slot(index) => ("LCL", index),
+                Location::Register(_) => unreachable!(),
+            },
+            Var::Static(_) | Var::This => unreachable!(),
+        };
+        self.asm.emit_all(&["@R15", "M=D"]);
+        self.at(base);
+        self.emit("D=D+M");
+        self.at(index);
+        self.asm
+            .emit_all(&["D=D+A", "@R15", "A=D-M", "D=D-A", "M=D"]);
+    }
+
+    /// Pushes an operand, to pass it to a function.
+    fn push(&mut self, operand: Operand) {
+        self.load(operand);
+        self.asm.push_d();
+    }
+
+    /// Calls a function whose arguments are pushed, leaving its value on top of the stack.
+    fn call(&mut self, function: &str, args: usize) {
+        let label = self.new_label("ret");
+        self.asm.call(function, args as u16, &label);
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy {
+                dst,
+                src: Operand::Const(value @ -1..=1),
+            } => {
+                self.address(*dst, false);
+                self.emit(format!("M={}", value));
+            }
+            Inst::Copy { dst, src } => {
+                self.load(*src);
+                self.store(*dst);
+            }
+            Inst::Unary { dst, op, operand } => {
+                self.load(*operand);
+                self.emit(match op {
+                    UnaryOp::Neg => "D=-D",
+                    UnaryOp::Not => "D=!D",
+                });
+                self.store(*dst);
+            }
+            // Incrementing or decrementing a variable in place
+            Inst::Binary {
+                dst,
+                op: op @ (BinaryOp::Add | BinaryOp::Sub),
+                lhs: Operand::Var(lhs),
+                rhs: Operand::Const(step @ (-1 | 1)),
+            } if dst == lhs => {
+                self.address(*dst, false);
+                let increment = (*op == BinaryOp::Add) == (*step == 1);
+                self.emit(if increment { "M=M+1" } else { "M=M-1" });
+            }
+            Inst::Binary { dst, op, lhs, rhs } => {
+                self.binary(*op, *lhs, *rhs);
+                self.store(*dst);
+            }
+            Inst::Load { dst, address } => {
+                match address {
+                    Operand::Const(address) if *address >= 0 => self.at(address),
+                    _ => {
+                        self.load(*address);
+                        self.emit("A=D");
+                    }
+                }
+                self.emit("D=M");
+                self.store(*dst);
+            }
+            Inst::Store { address, src } => match address {
+                Operand::Const(address) if *address >= 0 => {
+                    self.load(*src);
+                    self.at(address);
+                    self.emit("M=D");
+                }
+                _ => {
+                    self.load(*address);
+                    self.asm.emit_all(&["@R15", "M=D"]);
+                    self.load(*src);
+                    self.asm.emit_all(&["@R15", "A=M", "M=D"]);
+                }
+            },
+            Inst::Call {
+                dst,
+                function,
+                args,
+            } => {
+                for &arg in args {
+                    self.push(arg);
+                }
+                self.call(function, args.len());
+                match dst {
+                    Some(dst) => {
+                        self.asm.emit_all(&["@SP", "AM=M-1", "D=M"]);
+                        self.store(*dst);
+                    }
+                    // Every subroutine returns a value, which is discarded
+                    None => self.asm.emit_all(&["@SP", "M=M-1"]),
+                }
+            }
+        }
+    }
+
+    /// Computes a binary operation into the D register.
+    fn binary(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand) {
+        match op {
+            BinaryOp::Mul | BinaryOp::Div => {
+                self.push(lhs);
+                self.push(rhs);
+                let function = if op == BinaryOp::Mul {
+                    "Math.multiply"
+                } else {
+                    "Math.divide"
+                };
+                self.call(function, 2);
+                self.asm.emit_all(&["@SP", "AM=M-1", "D=M"]);
+            }
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::And | BinaryOp::Or => {
+                self.arithmetic(op, lhs, rhs)
+            }
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => {
+                self.difference(lhs, rhs);
+                let (jump, end) = (self.new_label("true"), self.new_label("end"));
+                self.at(&jump);
+                self.emit(match op {
+                    BinaryOp::Lt => "D;JLT",
+                    BinaryOp::Gt => "D;JGT",
+                    _ => "D;JEQ",
+                });
+                self.emit("D=0");
+                self.at(&end);
+                self.emit("0;JMP");
+                self.asm.label(&jump);
+                self.emit("D=-1");
+                self.asm.label(&end);
+            }
+        }
+    }
+
+    /// Computes `lhs - rhs` into the D register, for a comparison.
+    fn difference(&mut self, lhs: Operand, rhs: Operand) {
+        if rhs == Operand::Const(0) {
+            self.load(lhs);
+        } else {
+            self.arithmetic(BinaryOp::Sub, lhs, rhs);
+        }
+    }
+
+    /// Computes an addition, subtraction or bitwise operation into the D register.
+    fn arithmetic(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand) {
+        let commutative = op != BinaryOp::Sub;
+        if commutative && matches!(lhs, Operand::Const(_)) && !matches!(rhs, Operand::Const(_)) {
+            return self.arithmetic(op, rhs, lhs);
+        }
+        match (op, lhs, rhs) {
+            (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Or, _, Operand::Const(0)) => {
+                return self.load(lhs);
+            }
+            (BinaryOp::Add | BinaryOp::Sub, _, Operand::Const(step @ (-1 | 1))) => {
+                self.load(lhs);
+                let increment = (op == BinaryOp::Add) == (step == 1);
+                return self.emit(if increment { "D=D+1" } else { "D=D-1" });
+            }
+            (BinaryOp::Add | BinaryOp::Sub, _, Operand::Const(value))
+                if value < 0 && value != i16::MIN =>
+            {
+                let op = if op == BinaryOp::Add {
+                    BinaryOp::Sub
+                } else {
+                    BinaryOp::Add
+                };
+                return self.arithmetic(op, lhs, Operand::Const(-value));
+            }
+            (_, _, Operand::Const(value)) if value >= 0 => {
+                self.load(lhs);
+                self.at(value);
+                return self.emit(format!("D=D{}A", operator(op)));
+            }
+            (BinaryOp::Sub, Operand::Const(value), _) if value >= 0 => {
+                self.load(rhs);
+                self.at(value);
+                return self.emit("D=A-D");
+            }
+            _ => {}
+        }
+        // The second operand is read from memory, unless computing its address would overwrite
+        // the first one, in which case it's computed first and kept in R15
+        if let Operand::Var(var) = rhs
+            && self.keeps_d(var)
+        {
+            self.load(lhs);
+            self.address(var, true);
+            return self.emit(format!("D=D{}M", operator(op)));
+        }
+        self.load(rhs);
+        self.asm.emit_all(&["@R15", "M=D"]);
+        self.load(lhs);
+        self.at("R15");
+        self.emit(format!("D=D{}M", operator(op)));
+    }
+}
+
+/// Returns the operator of the Hack ALU computing a binary operation.
+fn operator(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::And => "&",
+        BinaryOp::Or => "|",
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,499 @@
+//! Translation of the IR to VM code
+//!
+//! Variables map to the VM segments the direct code generator uses, and the current object to
+//! `pointer 0`. A temporary assigned once and read once further in the same block stays on the
+//! stack in between when the instructions in between leave it there, which is the case for the
+//! temporaries holding the intermediate results of an expression. Other temporaries are stored in
+//! local variables following the declared ones. Variables and constants an instruction reads before
+//! such temporaries are pushed before those are computed, as the direct code generator does.
+//!
+//! Blocks are laid out in order, leaving out the ones control never reaches. Each block that a
+//! jump targets gets the label `b<n>`, after its index.
+//!
+//! Every VM instruction comes from the span of the IR instruction or terminator it's translated
+//! from, and labels from the span of the instruction they precede.
+
+use super::{BinaryOp, Function, Inst, Operand, Terminator, UnaryOp, Var};
+use crate::span::Span;
+use crate::vm::{ArithmeticOp, Instruction, Segment};
+use std::collections::HashMap;
+
+/// Translates a function to VM code.
+pub fn to_vm(function: &Function) -> Vec<Instruction> {
+    to_vm_with_origins(function).0
+}
+
+/// Translates a function to VM code like [`to_vm`], also returning the span of the Jack code
+/// every instruction comes from.
+pub fn to_vm_with_origins(function: &Function) -> (Vec<Instruction>, Vec<Span>) {
+    let mut stored = vec![false; function.temps as usize];
+    let (assignments, reads) = count_accesses(function);
+    for temp in 0..function.temps as usize {
+        stored[temp] = assignments[temp] != 1 || reads[temp] != 1;
+    }
+    let function = &push_early(function, &stored);
+    stored.resize(function.temps as usize, false);
+    // Every attempt stores at least one more temporary, so this ends
+    loop {
+        match Translator::new(function, &stored).translate() {
+            Ok(code) => return code,
+            Err(temps) => {
+                for temp in temps {
+                    stored[temp as usize] = true;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the function where the variables and constants an instruction reads before temporaries
+/// kept on the stack are copied to new temporaries where the computation of those starts, so that
+/// the operands end up on the stack in order. Reading a variable earlier is only done when the
+/// instructions in between can't assign it.
+fn push_early(function: &Function, stored: &[bool]) -> Function {
+    let mut function = function.clone();
+    let mut next = function.temps;
+    for block in &mut function.blocks {
+        let insts = &mut block.insts;
+        // The instruction assigning each temporary kept on the stack, and the first instruction
+        // computing the operands of each instruction that are on the stack
+        let mut defs = HashMap::new();
+        let mut starts = Vec::with_capacity(insts.len());
+        for (index, inst) in insts.iter().enumerate() {
+            let start = stack_temps(inst, stored)
+                .filter_map(|temp| defs.get(&temp).map(|&def: &usize| starts[def]))
+                .fold(index, usize::min);
+            starts.push(start);
+            if let Some(Var::Temp(temp)) = inst.dst()
+                && !stored[temp as usize]
+            {
+                defs.insert(temp, index);
+            }
+        }
+
+        // Instructions enclosing others come later, and their copies go first
+        let mut copies: Vec<Vec<(Inst, Span)>> = vec![Vec::new(); insts.len()];
+        'insts: for index in (0..insts.len()).rev() {
+            let mut pending = Vec::new();
+            let mut groups = Vec::new();
+            for (position, operand) in insts[index].operands().into_iter().enumerate() {
+                match operand {
+                    Operand::Var(Var::Temp(temp)) if !stored[temp as usize] => {
+                        let Some(&def) = defs.get(&temp) else {
+                            continue 'insts;
+                        };
+                        if !pending.is_empty() {
+                            groups.push((starts[def], std::mem::take(&mut pending)));
+                        }
+                    }
+                    _ => pending.push((position, operand)),
+                }
+            }
+            let clobbered = |start: usize, var: Var| {
+                insts[start..index].iter().any(|inst| {
+                    inst.dst() == Some(var)
+                        || matches!(inst, Inst::Call { .. } | Inst::Store { .. })
+                            && matches!(var, Var::Static(_) | Var::Field(_))
+                })
+            };
+            if groups.iter().any(|(start, operands)| {
+                operands
+                    .iter()
+                    .any(|(_, operand)| operand.var().is_some_and(|var| clobbered(*start, var)))
+            }) {
+                continue;
+            }
+            for (start, operands) in groups {
+                for (position, operand) in operands {
+                    let temp = Var::Temp(next);
+                    next += 1;
+                    copies[start].push((
+                        Inst::Copy {
+                            dst: temp,
+                            src: operand,
+                        },
+                        block.spans[index],
+                    ));
+                    *insts[index].operands_mut()[position] = Operand::Var(temp);
+                }
+            }
+        }
+        let original = std::mem::take(insts);
+        let spans = std::mem::take(&mut block.spans);
+        for ((copies, inst), span) in copies.into_iter().zip(original).zip(spans) {
+            for (copy, copy_span) in copies {
+                insts.push(copy);
+                block.spans.push(copy_span);
+            }
+            insts.push(inst);
+            block.spans.push(span);
+        }
+    }
+    function.temps = next;
+    function
+}
+
+/// Returns the temporaries kept on the stack that an instruction reads.
+fn stack_temps<'a>(inst: &Inst, stored: &'a [bool]) -> impl Iterator<Item = u16> + 'a {
+    inst.operands()
+        .into_iter()
+        .filter_map(|operand| match operand {
+            Operand::Var(Var::Temp(temp)) if !stored[temp as usize] => Some(temp),
+            _ => None,
+        })
+}
+
+/// Returns how many times every temporary is assigned and read.
+fn count_accesses(function: &Function) -> (Vec<usize>, Vec<usize>) {
+    let mut assignments = vec![0; function.temps as usize];
+    let mut reads = vec![0; function.temps as usize];
+    for block in &function.blocks {
+        for inst in &block.insts {
+            for operand in inst.operands() {
+                if let Operand::Var(Var::Temp(temp)) = operand {
+                    reads[temp as usize] += 1;
+                }
+            }
+            if let Some(Var::Temp(temp)) = inst.dst() {
+                assignments[temp as usize] += 1;
+            }
+        }
+        if let Some(Operand::Var(Var::Temp(temp))) = block.terminator.operand() {
+            reads[temp as usize] += 1;
+        }
+    }
+    (assignments, reads)
+}
+
+struct Translator<'a> {
+    function: &'a Function,
+    /// Whether each temporary is kept in a local variable rather than on the stack.
+    stored: &'a [bool],
+    /// The local variable of every stored temporary.
+    slots: HashMap<u16, u16>,
+    instructions: Vec<Instruction>,
+    /// The span every instruction comes from.
+    origins: Vec<Span>,
+    /// The span the next instructions come from.
+    span: Span,
+    /// The temporaries currently on the stack, the last one on top.
+    stack: Vec<u16>,
+    /// Temporaries that turned out not to be on top of the stack when read, or not to be read in the
+    /// block that assigns them.
+    misplaced: Vec<u16>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(function: &'a Function, stored: &'a [bool]) -> Self {
+        let slots = (0..function.temps)
+            .filter(|&temp| stored[temp as usize])
+            .enumerate()
+            .map(|(slot, temp)| (temp, function.locals + slot as u16))
+            .collect();
+        Self {
+            function,
+            stored,
+            slots,
+            instructions: Vec::new(),
+            origins: Vec::new(),
+            span: function.span,
+            stack: Vec::new(),
+            misplaced: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+        self.origins.push(self.span);
+    }
+
+    fn arithmetic(&mut self, op: ArithmeticOp) {
+        self.emit(Instruction::Arithmetic(op));
+    }
+
+    fn translate(mut self) -> Result<(Vec<Instruction>, Vec<Span>), Vec<u16>> {
+        let function = self.function;
+        self.emit(Instruction::Function {
+            name: function.name.clone(),
+            locals: function.locals + self.slots.len() as u16,
+        });
+        let reachable = function.reachable();
+        let layout: Vec<usize> = (0..function.blocks.len())
+            .filter(|&id| reachable[id])
+            .collect();
+        // The position of each block's code, and the blocks jumped to
+        let mut starts = Vec::new();
+        let mut targets = vec![false; function.blocks.len()];
+        for (position, &id) in layout.iter().enumerate() {
+            starts.push((self.instructions.len(), id));
+            let block = &function.blocks[id];
+            for (inst, &span) in block.insts.iter().zip(&block.spans) {
+                self.span = span;
+                self.inst(inst);
+            }
+            self.span = block.terminator_span;
+            let next = layout.get(position + 1).copied();
+            match block.terminator {
+                Terminator::Jump(target) => {
+                    if Some(target) != next {
+                        self.emit(Instruction::Goto(label(target)));
+                        targets[target] = true;
+                    }
+                }
+                Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    self.operands(&[cond]);
+                    if Some(then) == next {
+                        self.arithmetic(ArithmeticOp::Not);
+                        self.emit(Instruction::IfGoto(label(otherwise)));
+                        targets[otherwise] = true;
+                    } else {
+                        self.emit(Instruction::IfGoto(label(then)));
+                        targets[then] = true;
+                        if Some(otherwise) != next {
+                            self.emit(Instruction::Goto(label(otherwise)));
+                            targets[otherwise] = true;
+                        }
+                    }
+                }
+                Terminator::Return(value) => {
+                    self.operands(&[value]);
+                    self.emit(Instruction::Return);
+                }
+                Terminator::Unreachable => {}
+            }
+            // Temporaries read in other blocks have to be stored
+            let left = std::mem::take(&mut self.stack);
+            self.misplaced.extend(left);
+        }
+        if !self.misplaced.is_empty() {
+            return Err(self.misplaced);
+        }
+
+        // Blocks without instructions start where the next one does, possibly at the very end
+        let mut instructions = Vec::with_capacity(self.instructions.len());
+        let mut origins = Vec::with_capacity(self.origins.len());
+        let mut starts = starts.into_iter().peekable();
+        let code = self.instructions.into_iter().zip(self.origins);
+        for (index, (instruction, origin)) in code.enumerate() {
+            while let Some((_, id)) = starts.next_if(|(start, _)| *start == index) {
+                if targets[id] {
+                    instructions.push(Instruction::Label(label(id)));
+                    origins.push(origin);
+                }
+            }
+            instructions.push(instruction);
+            origins.push(origin);
+        }
+        for (_, id) in starts {
+            if targets[id] {
+                instructions.push(Instruction::Label(label(id)));
+                origins.push(function.span);
+            }
+        }
+        Ok((instructions, origins))
+    }
+
+    /// Returns whether a temporary stays on the stack.
+    fn on_stack(&self, operand: Operand) -> bool {
+        matches!(operand, Operand::Var(Var::Temp(temp)) if !self.stored[temp as usize])
+    }
+
+    /// Pushes the operands of an instruction in order. Those on the stack already must come
+    /// first, in the same order as they are on the stack, and are only checked for.
+    fn operands(&mut self, operands: &[Operand]) {
+        let on_stack = operands
+            .iter()
+            .take_while(|&&operand| self.on_stack(operand))
+            .count();
+        self.check_top(&operands[..on_stack]);
+        for &operand in &operands[on_stack..] {
+            self.push(operand);
+        }
+    }
+
+    /// Checks that the given temporaries are on top of the stack, and removes them from it.
+    fn check_top(&mut self, operands: &[Operand]) {
+        let temps: Vec<u16> = operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::Var(Var::Temp(temp)) => Some(*temp),
+                _ => None,
+            })
+            .collect();
+        if self.stack.ends_with(&temps) {
+            self.stack.truncate(self.stack.len() - temps.len());
+        } else {
+            self.misplaced.extend(temps);
+        }
+    }
+
+    fn push(&mut self, operand: Operand) {
+        match operand {
+            Operand::Const(-1) => {
+                self.emit(Instruction::Push(Segment::Constant, 0));
+                self.arithmetic(ArithmeticOp::Not);
+            }
+            Operand::Const(i16::MIN) => {
+                self.emit(Instruction::Push(Segment::Constant, i16::MAX as u16));
+                self.arithmetic(ArithmeticOp::Not);
+            }
+            Operand::Const(value) if value < 0 => {
+                self.emit(Instruction::Push(Segment::Constant, value.unsigned_abs()));
+                self.arithmetic(ArithmeticOp::Neg);
+            }
+            Operand::Const(value) => self.emit(Instruction::Push(Segment::Constant, value as u16)),
+            Operand::Var(Var::Temp(temp)) if !self.stored[temp as usize] => {
+                // Read out of order: it has to be stored
+                self.misplaced.push(temp);
+            }
+            Operand::Var(var) => {
+                let (segment, index) = self.location(var);
+                self.emit(Instruction::Push(segment, index));
+            }
+        }
+    }
+
+    /// Moves the value on top of the stack to `dst`, or leaves it there if `dst` stays on the
+    /// stack.
+    fn assign(&mut self, dst: Var) {
+        match dst {
+            Var::Temp(temp) if !self.stored[temp as usize] => self.stack.push(temp),
+            _ => {
+                let (segment, index) = self.location(dst);
+                self.emit(Instruction::Pop(segment, index));
+            }
+        }
+    }
+
+    fn location(&self, var: Var) -> (Segment, u16) {
+        match var {
+            Var::Local(index) => (Segment::Local, index),
+            Var::Argument(index) => (Segment::Argument, index),
+            Var::Static(index) => (Segment::Static, index),
+            Var::Field(index) => (Segment::This, index),
+            Var::This => (Segment::Pointer, 0),
+            Var::Temp(temp) => (Segment::Local, self.slots[&temp]),
+        }
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dst, src } => {
+                self.operands(&[*src]);
+                self.assign(*dst);
+            }
+            Inst::Unary { dst, op, operand } => {
+                self.operands(&[*operand]);
+                self.arithmetic(match op {
+                    UnaryOp::Neg => ArithmeticOp::Neg,
+                    UnaryOp::Not => ArithmeticOp::Not,
+                });
+                self.assign(*dst);
+            }
+            Inst::Binary { dst, op, lhs, rhs } => {
+                self.binary(*op, *lhs, *rhs);
+                self.assign(*dst);
+            }
+            Inst::Load { dst, address } => {
+                self.operands(&[*address]);
+                self.emit(Instruction::Pop(Segment::Pointer, 1));
+                self.emit(Instruction::Push(Segment::That, 0));
+                self.assign(*dst);
+            }
+            Inst::Store { address, src } => self.store(*address, *src),
+            Inst::Call {
+                dst,
+                function,
+                args,
+            } => {
+                self.operands(args);
+                self.emit(Instruction::Call {
+                    name: function.clone(),
+                    args: args.len() as u16,
+                });
+                match dst {
+                    Some(dst) => self.assign(*dst),
+                    // Every subroutine returns a value, which is discarded
+                    None => self.emit(Instruction::Pop(Segment::Temp, 0)),
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: Operand, rhs: Operand) {
+        // An operand computed on the stack after reading the other one comes first, unless
+        // the operation can take its operands the other way around
+        if !self.on_stack(lhs) && self.on_stack(rhs) {
+            let swapped = match op {
+                BinaryOp::Add | BinaryOp::Mul | BinaryOp::And | BinaryOp::Or | BinaryOp::Eq => {
+                    Some(op)
+                }
+                BinaryOp::Lt => Some(BinaryOp::Gt),
+                BinaryOp::Gt => Some(BinaryOp::Lt),
+                BinaryOp::Sub | BinaryOp::Div => None,
+            };
+            if let Some(swapped) = swapped {
+                self.binary(swapped, rhs, lhs);
+                return;
+            }
+            if op == BinaryOp::Sub {
+                // lhs - rhs = -rhs + lhs
+                self.operands(&[rhs]);
+                self.arithmetic(ArithmeticOp::Neg);
+                self.push(lhs);
+                self.arithmetic(ArithmeticOp::Add);
+                return;
+            }
+        }
+        self.operands(&[lhs, rhs]);
+        match op {
+            BinaryOp::Add => self.arithmetic(ArithmeticOp::Add),
+            BinaryOp::Sub => self.arithmetic(ArithmeticOp::Sub),
+            BinaryOp::Mul => self.emit(Instruction::Call {
+                name: "Math.multiply".to_string(),
+                args: 2,
+            }),
+            BinaryOp::Div => self.emit(Instruction::Call {
+                name: "Math.divide".to_string(),
+                args: 2,
+            }),
+            BinaryOp::And => self.arithmetic(ArithmeticOp::And),
+            BinaryOp::Or => self.arithmetic(ArithmeticOp::Or),
+            BinaryOp::Lt => self.arithmetic(ArithmeticOp::Lt),
+            BinaryOp::Gt => self.arithmetic(ArithmeticOp::Gt),
+            BinaryOp::Eq => self.arithmetic(ArithmeticOp::Eq),
+        }
+    }
+
+    /// Writes an array element. The address goes to `pointer 1` once both operands are
+    /// computed, since computing the value may access an array itself.
+    fn store(&mut self, address: Operand, src: Operand) {
+        match (self.on_stack(address), self.on_stack(src)) {
+            (true, true) => {
+                self.check_top(&[address, src]);
+                self.emit(Instruction::Pop(Segment::Temp, 0));
+                self.emit(Instruction::Pop(Segment::Pointer, 1));
+                self.emit(Instruction::Push(Segment::Temp, 0));
+            }
+            (false, true) => {
+                self.check_top(&[src]);
+                self.push(address);
+                self.emit(Instruction::Pop(Segment::Pointer, 1));
+            }
+            (_, false) => {
+                self.operands(&[address]);
+                self.emit(Instruction::Pop(Segment::Pointer, 1));
+                self.push(src);
+            }
+        }
+        self.emit(Instruction::Pop(Segment::That, 0));
+    }
+}
+
+fn label(block: usize) -> String {
+    format!("b{}", block)
+}
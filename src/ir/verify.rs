@@ -0,0 +1,192 @@
+//! Well-formedness checks of the IR
+//!
+//! Passes transforming the IR are expected to keep it well-formed, which [`verify`] checks: the
+//! function has an entry block, every instruction has a span, jumps target existing blocks,
+//! variables are within the bounds the function declares, functions don't use the current
+//! object, and temporaries are assigned on every path leading to where they're used.
+
+use super::{BlockId, Function, Operand, Var};
+use crate::ast::SubroutineKind;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The name of the function, e.g. `Main.main`.
+    pub function: String,
+    /// The block where the problem is, unless it's about the whole function.
+    pub block: Option<BlockId>,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.block {
+            Some(block) => write!(f, "{}: b{}: {}", self.function, block, self.message),
+            None => write!(f, "{}: {}", self.function, self.message),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Checks that a function is well-formed, returning every problem found otherwise.
+pub fn verify(function: &Function) -> Result<(), Vec<VerifyError>> {
+    let mut verifier = Verifier {
+        function,
+        errors: Vec::new(),
+    };
+    verifier.verify();
+    if verifier.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(verifier.errors)
+    }
+}
+
+struct Verifier<'a> {
+    function: &'a Function,
+    errors: Vec<VerifyError>,
+}
+
+impl Verifier<'_> {
+    fn error(&mut self, block: Option<BlockId>, message: String) {
+        self.errors.push(VerifyError {
+            function: self.function.name.clone(),
+            block,
+            message,
+        });
+    }
+
+    fn verify(&mut self) {
+        let function = self.function;
+        if function.blocks.is_empty() {
+            self.error(None, "function has no blocks".to_string());
+            return;
+        }
+        for (id, block) in function.blocks.iter().enumerate() {
+            if block.spans.len() != block.insts.len() {
+                self.error(
+                    Some(id),
+                    format!(
+                        "{} spans for {} instructions",
+                        block.spans.len(),
+                        block.insts.len()
+                    ),
+                );
+            }
+            for inst in &block.insts {
+                for operand in inst.operands() {
+                    self.operand(id, operand);
+                }
+                if let Some(dst) = inst.dst() {
+                    self.var(id, dst);
+                }
+            }
+            if let Some(operand) = block.terminator.operand() {
+                self.operand(id, operand);
+            }
+            for successor in block.terminator.successors() {
+                if successor >= function.blocks.len() {
+                    self.error(Some(id), format!("jump to missing block b{}", successor));
+                }
+            }
+        }
+        if self.errors.is_empty() {
+            self.check_temps_assigned();
+        }
+    }
+
+    fn operand(&mut self, block: BlockId, operand: Operand) {
+        if let Operand::Var(var) = operand {
+            self.var(block, var);
+        }
+    }
+
+    fn var(&mut self, block: BlockId, var: Var) {
+        let function = self.function;
+        let bound = match var {
+            Var::Local(index) => Some((index, function.locals, "locals")),
+            Var::Argument(index) => Some((index, function.args, "arguments")),
+            Var::Temp(index) => Some((index, function.temps, "temporaries")),
+            Var::Static(_) => None,
+            Var::Field(_) | Var::This => {
+                if function.kind == SubroutineKind::Function {
+                    self.error(
+                        Some(block),
+                        format!("{} is used in a function, which has no current object", var),
+                    );
+                }
+                None
+            }
+        };
+        if let Some((index, count, what)) = bound
+            && index >= count
+        {
+            self.error(
+                Some(block),
+                format!(
+                    "{} is out of range, the function has {} {}",
+                    var, count, what
+                ),
+            );
+        }
+    }
+
+    /// Checks that temporaries are assigned before they're used, computing the ones assigned on
+    /// every path to the start of each reachable block until reaching a fixed point.
+    fn check_temps_assigned(&mut self) {
+        let function = self.function;
+        let reachable = function.reachable();
+        let temps = function.temps as usize;
+        // Unreached blocks start with every temporary, the identity of the intersection
+        let mut assigned_in: Vec<Vec<bool>> = vec![vec![true; temps]; function.blocks.len()];
+        assigned_in[0] = vec![false; temps];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, block) in function.blocks.iter().enumerate() {
+                if !reachable[id] {
+                    continue;
+                }
+                let mut assigned = assigned_in[id].clone();
+                for inst in &block.insts {
+                    if let Some(Var::Temp(temp)) = inst.dst() {
+                        assigned[temp as usize] = true;
+                    }
+                }
+                for successor in block.terminator.successors() {
+                    for (temp, assigned_in) in assigned_in[successor].iter_mut().enumerate() {
+                        if *assigned_in && !assigned[temp] {
+                            *assigned_in = false;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (id, block) in function.blocks.iter().enumerate() {
+            if !reachable[id] {
+                continue;
+            }
+            let mut assigned = assigned_in[id].clone();
+            let operands = block.insts.iter().map(|inst| (inst.operands(), inst.dst()));
+            let terminator = (block.terminator.operand().into_iter().collect(), None);
+            for (operands, dst) in operands.chain([terminator]) {
+                for operand in operands {
+                    if let Operand::Var(Var::Temp(temp)) = operand
+                        && !assigned[temp as usize]
+                    {
+                        self.error(
+                            Some(id),
+                            format!("t{} may be used before it's assigned", temp),
+                        );
+                    }
+                }
+                if let Some(Var::Temp(temp)) = dst {
+                    assigned[temp as usize] = true;
+                }
+            }
+        }
+    }
+}
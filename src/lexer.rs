@@ -1,8 +1,16 @@
+use crate::span::Span;
 use crate::token::{LexicalError, Token};
 use logos::{Logos, SpannedIter};
 
 pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
 
+/// A lexical error along with the location of the offending text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub error: LexicalError,
+    pub span: Span,
+}
+
 pub struct Lexer<'source> {
     token_stream: SpannedIter<'source, Token<'source>>,
 }
@@ -16,11 +24,15 @@ impl<'source> Lexer<'source> {
 }
 
 impl<'source> Iterator for Lexer<'source> {
-    type Item = Spanned<Token<'source>, usize, LexicalError>;
+    type Item = Spanned<Token<'source>, usize, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.token_stream
-            .next()
-            .map(|(token, span)| Ok((span.start, token?, span.end)))
+        self.token_stream.next().map(|(token, span)| match token {
+            Ok(token) => Ok((span.start, token, span.end)),
+            Err(error) => Err(LexError {
+                error,
+                span: Span::new(span.start, span.end),
+            }),
+        })
     }
 }
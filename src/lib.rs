@@ -1,5 +1,20 @@
+pub mod analysis;
 pub mod ast;
+pub mod codegen;
+pub mod const_eval;
+pub mod diagnostics;
+pub mod emitter;
+pub mod hack;
+pub mod ir;
 pub mod lexer;
+pub mod lint;
 pub mod parser;
+pub mod resolve;
+pub mod source_map;
+pub mod span;
+pub mod symbol_table;
 pub mod token;
+pub mod tree_shake;
 pub mod utils;
+pub mod visit;
+pub mod vm;
@@ -0,0 +1,222 @@
+//! The built-in rules, backed by the checks in [`crate::analysis`]
+
+use super::{Context, Level, Rule};
+use crate::analysis::{
+    constant, control_flow, definite_assignment, precedence, shadowing, style, unused,
+};
+use crate::diagnostics::Diagnostic;
+use std::collections::HashMap;
+
+/// A rule reporting the diagnostics of an analysis that carry the rule's code.
+struct AnalysisRule {
+    code: &'static str,
+    name: &'static str,
+    description: &'static str,
+    default_level: Level,
+    analysis: Analysis,
+}
+
+impl Rule for AnalysisRule {
+    fn code(&self) -> &'static str {
+        self.code
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn default_level(&self) -> Level {
+        self.default_level
+    }
+
+    fn check(&self, cx: &Context, diagnostics: &mut Vec<Diagnostic>) {
+        // Some analyses report for several rules, so the first rule runs the analysis for the
+        // others, which take their diagnostics from the context
+        let mut analyses = cx.analyses.borrow_mut();
+        let by_code = analyses.entry(self.analysis).or_insert_with(|| {
+            let mut all = Vec::new();
+            self.analysis.run(cx, &mut all);
+            let mut by_code: HashMap<&'static str, Vec<Diagnostic>> = HashMap::new();
+            for diagnostic in all {
+                if let Some(code) = diagnostic.code {
+                    by_code.entry(code).or_default().push(diagnostic);
+                }
+            }
+            by_code
+        });
+        diagnostics.extend(by_code.remove(self.code).unwrap_or_default());
+    }
+}
+
+/// The analyses backing the built-in rules.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(super) enum Analysis {
+    ControlFlow,
+    DefiniteAssignment,
+    Unused,
+    Shadowing,
+    Constant,
+    Precedence,
+    Naming,
+    Length,
+    Nesting,
+}
+
+impl Analysis {
+    fn run(self, cx: &Context, diagnostics: &mut Vec<Diagnostic>) {
+        match self {
+            Analysis::ControlFlow => control_flow::check_class(cx.class, diagnostics),
+            Analysis::DefiniteAssignment => definite_assignment::check_class(cx.class, diagnostics),
+            Analysis::Unused => unused::check_class(cx.class, diagnostics),
+            Analysis::Shadowing => shadowing::check_class(cx.class, cx.class_names, diagnostics),
+            Analysis::Constant => constant::check_class(cx.class, diagnostics),
+            Analysis::Precedence => precedence::check_class(cx.class, diagnostics),
+            Analysis::Naming => style::check_names(cx.class, diagnostics),
+            Analysis::Length => {
+                style::check_length(cx.class, cx.options.max_statements, diagnostics)
+            }
+            Analysis::Nesting => {
+                style::check_nesting(cx.class, cx.options.max_nesting, diagnostics)
+            }
+        }
+    }
+}
+
+pub(super) fn rules() -> Vec<Box<dyn Rule>> {
+    let rules = [
+        AnalysisRule {
+            code: control_flow::MISSING_RETURN,
+            name: "missing-return",
+            description: "a subroutine may reach its end without a `return` statement",
+            default_level: Level::Deny,
+            analysis: Analysis::ControlFlow,
+        },
+        AnalysisRule {
+            code: control_flow::UNREACHABLE_CODE,
+            name: "unreachable-code",
+            description: "statements following a statement that returns on every path",
+            default_level: Level::Warn,
+            analysis: Analysis::ControlFlow,
+        },
+        AnalysisRule {
+            code: definite_assignment::UNASSIGNED_READ,
+            name: "unassigned-read",
+            description: "a local variable may be read before it is assigned",
+            default_level: Level::Warn,
+            analysis: Analysis::DefiniteAssignment,
+        },
+        AnalysisRule {
+            code: unused::UNUSED_LOCAL,
+            name: "unused-local",
+            description: "a local variable is never referenced",
+            default_level: Level::Warn,
+            analysis: Analysis::Unused,
+        },
+        AnalysisRule {
+            code: unused::UNUSED_PARAMETER,
+            name: "unused-parameter",
+            description: "a parameter is never referenced",
+            default_level: Level::Warn,
+            analysis: Analysis::Unused,
+        },
+        AnalysisRule {
+            code: unused::UNUSED_FIELD,
+            name: "unused-field",
+            description: "a field is never referenced",
+            default_level: Level::Warn,
+            analysis: Analysis::Unused,
+        },
+        AnalysisRule {
+            code: unused::UNUSED_STATIC,
+            name: "unused-static",
+            description: "a static variable is never referenced",
+            default_level: Level::Warn,
+            analysis: Analysis::Unused,
+        },
+        AnalysisRule {
+            code: shadowing::SHADOWED_CLASS_VARIABLE,
+            name: "shadowed-class-variable",
+            description: "a parameter or local variable shadows a field or static variable",
+            default_level: Level::Warn,
+            analysis: Analysis::Shadowing,
+        },
+        AnalysisRule {
+            code: shadowing::SHADOWED_CLASS_NAME,
+            name: "shadowed-class-name",
+            description: "a parameter or local variable shadows a class used as a call prefix",
+            default_level: Level::Warn,
+            analysis: Analysis::Shadowing,
+        },
+        AnalysisRule {
+            code: style::CLASS_NAME_CASE,
+            name: "class-name-case",
+            description: "a class name that isn't UpperCamelCase",
+            default_level: Level::Warn,
+            analysis: Analysis::Naming,
+        },
+        AnalysisRule {
+            code: style::SUBROUTINE_NAME_CASE,
+            name: "subroutine-name-case",
+            description: "a subroutine name that isn't lowerCamelCase",
+            default_level: Level::Warn,
+            analysis: Analysis::Naming,
+        },
+        AnalysisRule {
+            code: style::VARIABLE_NAME_CASE,
+            name: "variable-name-case",
+            description: "a variable or parameter name that isn't lowerCamelCase",
+            default_level: Level::Warn,
+            analysis: Analysis::Naming,
+        },
+        AnalysisRule {
+            code: style::LONG_SUBROUTINE,
+            name: "long-subroutine",
+            description: "a subroutine with more statements than `max-statements`",
+            default_level: Level::Warn,
+            analysis: Analysis::Length,
+        },
+        AnalysisRule {
+            code: style::DEEP_NESTING,
+            name: "deep-nesting",
+            description: "`if`/`while` statements nested deeper than `max-nesting`",
+            default_level: Level::Warn,
+            analysis: Analysis::Nesting,
+        },
+        AnalysisRule {
+            code: constant::CONSTANT_OVERFLOW,
+            name: "constant-overflow",
+            description: "a constant expression overflows 16-bit arithmetic",
+            default_level: Level::Warn,
+            analysis: Analysis::Constant,
+        },
+        AnalysisRule {
+            code: constant::DIVISION_BY_ZERO,
+            name: "division-by-zero",
+            description: "a division by the constant 0",
+            default_level: Level::Warn,
+            analysis: Analysis::Constant,
+        },
+        AnalysisRule {
+            code: constant::CONSTANT_CONDITION,
+            name: "constant-condition",
+            description: "an `if`/`while` condition comparing constants is always true or false",
+            default_level: Level::Warn,
+            analysis: Analysis::Constant,
+        },
+        AnalysisRule {
+            code: precedence::AMBIGUOUS_PRECEDENCE,
+            name: "ambiguous-precedence",
+            description: "operators of different conventional precedence in one expression",
+            default_level: Level::Warn,
+            analysis: Analysis::Precedence,
+        },
+    ];
+    rules
+        .into_iter()
+        .map(|rule| Box::new(rule) as Box<dyn Rule>)
+        .collect()
+}
@@ -0,0 +1,285 @@
+//! A configurable lint framework
+//!
+//! Every rule has a stable code (e.g. `J0101`), a kebab-case name (e.g. `unused-local`) and a
+//! default [`Level`]. The level of a rule can be changed through [`Linter::set_level`], and a
+//! `// jack-allow(<code or name>, ...)` comment silences the listed rules on the following line.
+//!
+//! Rules with thresholds read them from [`LintOptions`], set through [`Linter::set_option`].
+//!
+//! Custom rules implement [`Rule`] and are added with [`Linter::register`].
+
+mod builtin;
+
+use crate::analysis::OS_CLASSES;
+use crate::ast;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::span;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The rule doesn't run.
+    Allow,
+    /// The rule reports warnings.
+    Warn,
+    /// The rule reports errors, which stop the compilation.
+    Deny,
+}
+
+/// What a rule can see while checking a class.
+pub struct Context<'a, 'source> {
+    /// The class being checked.
+    pub class: &'a ast::Class<'source>,
+    /// Every class compiled together with `class`, including `class` itself.
+    pub program: &'a [ast::Class<'source>],
+    /// The names of every class in the program and of the Jack OS classes.
+    pub class_names: &'a HashSet<&'source str>,
+    pub options: &'a LintOptions,
+    /// The diagnostics of the built-in analyses that have run on `class`, by code, until their
+    /// rule takes them.
+    analyses: RefCell<HashMap<builtin::Analysis, HashMap<&'static str, Vec<Diagnostic>>>>,
+}
+
+/// Thresholds used by the style rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintOptions {
+    /// The maximum number of statements in a subroutine, counting nested ones.
+    pub max_statements: usize,
+    /// The maximum number of nested `if`/`while` statements.
+    pub max_nesting: usize,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            max_statements: 60,
+            max_nesting: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintOptionError {
+    UnknownOption(String),
+    InvalidValue(String),
+}
+
+impl Display for LintOptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintOptionError::UnknownOption(key) => write!(f, "unknown lint option `{}`", key),
+            LintOptionError::InvalidValue(value) => {
+                write!(f, "invalid lint option value `{}`", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LintOptionError {}
+
+impl LintOptions {
+    /// Sets an option by its kebab-case name, e.g. `max-statements`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), LintOptionError> {
+        let option = match key {
+            "max-statements" => &mut self.max_statements,
+            "max-nesting" => &mut self.max_nesting,
+            _ => return Err(LintOptionError::UnknownOption(key.to_string())),
+        };
+        *option = value
+            .parse()
+            .map_err(|_| LintOptionError::InvalidValue(value.to_string()))?;
+        Ok(())
+    }
+}
+
+pub trait Rule {
+    /// The stable code of the rule, e.g. `J0101`.
+    fn code(&self) -> &'static str;
+
+    /// The kebab-case name of the rule, e.g. `unused-local`.
+    fn name(&self) -> &'static str;
+
+    /// A one-line description of what the rule reports.
+    fn description(&self) -> &'static str;
+
+    fn default_level(&self) -> Level;
+
+    /// Checks `cx.class` and pushes a diagnostic for every violation. The linter sets the code
+    /// and severity of the pushed diagnostics, so the rule doesn't have to.
+    fn check(&self, cx: &Context, diagnostics: &mut Vec<Diagnostic>);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRuleError {
+    rule: String,
+}
+
+impl Display for UnknownRuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown lint rule `{}`", self.rule)
+    }
+}
+
+impl std::error::Error for UnknownRuleError {}
+
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+    /// Levels overriding the default level of a rule, keyed by code.
+    levels: HashMap<&'static str, Level>,
+    options: LintOptions,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    /// Creates a linter with every built-in rule at its default level.
+    pub fn new() -> Self {
+        Self {
+            rules: builtin::rules(),
+            levels: HashMap::new(),
+            options: LintOptions::default(),
+        }
+    }
+
+    pub fn register(&mut self, rule: Box<dyn Rule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &dyn Rule> {
+        self.rules.iter().map(|rule| rule.as_ref())
+    }
+
+    /// Sets the level of the rule with the given code or name. `warnings` refers to every rule
+    /// whose level is currently [`Level::Warn`].
+    pub fn set_level(&mut self, rule: &str, level: Level) -> Result<(), UnknownRuleError> {
+        if rule == "warnings" {
+            let warn_rules: Vec<_> = self
+                .rules()
+                .filter(|r| self.level(*r) == Level::Warn)
+                .map(|r| r.code())
+                .collect();
+            for code in warn_rules {
+                self.levels.insert(code, level);
+            }
+            return Ok(());
+        }
+        let code = self
+            .rules()
+            .find(|r| r.code() == rule || r.name() == rule)
+            .map(|r| r.code())
+            .ok_or_else(|| UnknownRuleError {
+                rule: rule.to_string(),
+            })?;
+        self.levels.insert(code, level);
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<(), LintOptionError> {
+        self.options.set(key, value)
+    }
+
+    pub fn options(&self) -> &LintOptions {
+        &self.options
+    }
+
+    pub fn level(&self, rule: &dyn Rule) -> Level {
+        self.levels
+            .get(rule.code())
+            .copied()
+            .unwrap_or_else(|| rule.default_level())
+    }
+
+    /// Checks every class of a program. `sources` holds the source of each class, which is
+    /// scanned for `jack-allow` comments. The diagnostics of each class are returned in source
+    /// order, at the same index as the class.
+    pub fn check_program(&self, classes: &[ast::Class], sources: &[&str]) -> Vec<Vec<Diagnostic>> {
+        let class_names: HashSet<&str> = classes
+            .iter()
+            .map(|class| class.name.name)
+            .chain(OS_CLASSES)
+            .collect();
+        classes
+            .iter()
+            .zip(sources)
+            .map(|(class, source)| {
+                let cx = Context {
+                    class,
+                    program: classes,
+                    class_names: &class_names,
+                    options: &self.options,
+                    analyses: RefCell::default(),
+                };
+                self.check_class(&cx, source)
+            })
+            .collect()
+    }
+
+    fn check_class(&self, cx: &Context, source: &str) -> Vec<Diagnostic> {
+        let allowed = allow_comments(source);
+        let mut diagnostics = Vec::new();
+        for rule in self.rules() {
+            let severity = match self.level(rule) {
+                Level::Allow => continue,
+                Level::Warn => Severity::Warning,
+                Level::Deny => Severity::Error,
+            };
+            let mut rule_diagnostics = Vec::new();
+            rule.check(cx, &mut rule_diagnostics);
+            for mut diagnostic in rule_diagnostics {
+                let (line, _) = span::line_col(source, diagnostic.span.start);
+                let silenced = allowed.get(&line).is_some_and(|names| {
+                    names.iter().any(|n| *n == rule.code() || *n == rule.name())
+                });
+                if !silenced {
+                    diagnostic.code = Some(rule.code());
+                    diagnostic.severity = severity;
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+        diagnostics.sort_by_key(|d| d.span.start);
+        diagnostics
+    }
+}
+
+/// Maps each line preceded by a `// jack-allow(...)` comment to the rules listed in it.
+fn allow_comments(source: &str) -> HashMap<usize, Vec<&str>> {
+    const MARKER: &str = "jack-allow(";
+
+    let mut allowed = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let Some(comment) = line_comment(line).map(str::trim_start) else {
+            continue;
+        };
+        let Some(list) = comment
+            .strip_prefix(MARKER)
+            .and_then(|rest| rest.split_once(')'))
+            .map(|(list, _)| list)
+        else {
+            continue;
+        };
+        // Lines are 1-based, so the next line of the i-th line is line i + 2
+        allowed.insert(i + 2, list.split(',').map(str::trim).collect());
+    }
+    allowed
+}
+
+/// Returns the text following the `//` starting a comment on `line`, if any. String constants
+/// can't span lines, so a `//` between an odd and an even quote is inside one.
+fn line_comment(line: &str) -> Option<&str> {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '/' if !in_string && line[i + 1..].starts_with('/') => return Some(&line[i + 2..]),
+            _ => {}
+        }
+    }
+    None
+}
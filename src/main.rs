@@ -1,49 +1,868 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use jack_compiler::{
-    lexer, parser,
+    ast,
+    codegen::{self, ClassCode},
+    diagnostics::Diagnostic,
+    emitter::{Emitter, HumanEmitter, JsonEmitter, SarifEmitter, SourceFile},
+    hack::{self, Asm},
+    ir, lexer,
+    lint::{Level, Linter},
+    parser,
+    source_map::SourceMap,
+    tree_shake,
     utils::{self, XmlWrite},
+    vm,
 };
+use std::collections::HashSet;
+use std::env;
 use std::ffi::OsStr;
+use std::fmt::Display;
 use std::fs;
-use std::io::{self, BufWriter, Write};
-use std::path::Path;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
-fn main() -> io::Result<()> {
+fn main() {
+    let color = color_enabled();
+    if let Err(e) = run(color) {
+        exit_with_error(e, color, 1);
+    }
+}
+
+fn run(color: bool) -> io::Result<()> {
     let matches = Command::new("jack-compiler")
-        .about("Jack compiler frontend")
-        .arg(Arg::new("output").short('o').long("output").help(
-            "The output path for the generated AST. If not set, the output would be set to stdout.",
-        ))
+        .about("Jack compiler")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("peephole")
+                .about("Optimises VM code with peephole rewrites.")
+                .long_about(
+"Optimises VM code with peephole rewrites, and reports the number of instructions removed from
+every function to stderr. The code doesn't have to come from this compiler."
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("The output path for the optimised VM code. If not set, the output would be set to stdout.")
+                        .long_help(
+"The output path for the optimised VM code. If not set, the output would be set to stdout. If the
+input is a directory, this is the directory one output file per input file is written to."
+                        ),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("The input VM file, or a directory of VM files.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("assemble")
+                .about("Assembles Hack assembly to machine code.")
+                .long_about(
+"Assembles Hack assembly to machine code, written as a '.hack' file: one instruction per line,
+as 16 binary digits."
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("The output path for the machine code. If not set, the output would be set to stdout."),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("The input assembly file.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("translate")
+                .about("Translates VM code to Hack assembly.")
+                .long_about(
+"Translates VM code to Hack assembly. Every VM file of the input becomes part of a single
+assembly program, which starts with a bootstrap that sets SP to 256 and calls 'Sys.init'."
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("The output path for the assembly program. If not set, the output would be set to stdout."),
+                )
+                .arg(
+                    Arg::new("no-bootstrap")
+                        .long("no-bootstrap")
+                        .action(ArgAction::SetTrue)
+                        .help("Leaves out the bootstrap, so that the program starts with the first VM instruction."),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("The input VM file, or a directory of VM files.")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("build")
+                .about("Compiles a program to Hack machine code.")
+                .long_about(
+"Compiles a program to Hack machine code: every class is parsed and checked, compiled to VM
+code, linked with the Jack OS, translated to assembly and assembled. Only the functions that can
+be called from 'Sys.init' are kept, so that the program fits in the ROM."
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("The directory the output files are written to. The default value is the 'build' directory of the input.")
+                )
+                .arg(
+                    Arg::new("emit")
+                        .long("emit")
+                        .value_parser(["vm", "asm", "hack"])
+                        .value_delimiter(',')
+                        .default_value("hack")
+                        .help("The output files to write.")
+                        .long_help(
+"The output files to write, separated by commas. Possible values are: 'vm' (one file per class),
+'asm' (the assembly program) and 'hack' (the machine code). The assembly program and the
+machine code are named after the input directory. The default value is 'hack'."
+                        ),
+                )
+                .arg(
+                    Arg::new("os")
+                        .long("os")
+                        .value_name("DIR")
+                        .help("A directory of VM files of the Jack OS to link the program with.")
+                        .long_help(
+"A directory of VM files of the Jack OS to link the program with. The classes of the program
+replace the OS files of the same name, so that the program can provide its own version of an
+OS class."
+                        ),
+                )
+                .arg(
+                    Arg::new("optimize")
+                        .short('O')
+                        .long("optimize")
+                        .action(ArgAction::SetTrue)
+                        .help("Optimises the VM code."),
+                )
+                .arg(
+                    Arg::new("inline")
+                        .long("inline")
+                        .action(ArgAction::SetTrue)
+                        .requires("optimize")
+                        .help("Inlines getters at their call sites. Requires '--optimize'."),
+                )
+                .arg(lint_level_arg("allow", 'A', "Disables the given lint rule."))
+                .arg(lint_level_arg("warn", 'W', "Reports violations of the given lint rule as warnings."))
+                .arg(lint_level_arg("deny", 'D', "Reports violations of the given lint rule as errors."))
+                .arg(lint_option_arg())
+                .arg(diagnostics_format_arg())
+                .arg(
+                    Arg::new("input")
+                        .help("The input Jack source file, or a directory of Jack source files.")
+                        .required(true),
+                ),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("The output path for the generated AST or VM code. If not set, the output would be set to stdout.")
+                .long_help(
+"The output path for the generated AST or VM code. If not set, the output would be set to stdout. If the
+input is a directory, this is the directory one output file per class is written to."
+                ),
+        )
         .arg(
             Arg::new("format")
                 .short('f')
                 .long("format")
-                .value_parser(["xml", "debug"])
+                .value_parser(["xml", "debug", "vm", "ir", "asm"])
                 .default_value("xml")
                 .long_help(
-"The output format. Possible values are: 'xml', 'debug'. They correspond to the 2 formats we
-supports: XML and Rust debug print. The default value is 'xml'."
+"The output format. Possible values are: 'xml', 'debug', 'vm', 'ir', 'asm'. The first 2 print
+the AST as XML or with Rust debug print, 'vm' compiles the program to VM code, and 'ir' prints
+the intermediate representation of its subroutines. 'asm' compiles the whole program to a single
+file of Hack assembly, starting with a bootstrap that calls 'Sys.init'. The default value is
+'xml'."
+                ),
+        )
+        .arg(
+            Arg::new("pool-strings")
+                .long("pool-strings")
+                .action(ArgAction::SetTrue)
+                .help("Creates each distinct string constant of a class only once.")
+                .long_help(
+"Creates each distinct string constant of a class only once, the first time it's evaluated, and
+keeps it in a static variable. This avoids allocating a new string every time a loop evaluates
+a string constant, but the program must not modify or dispose of these strings."
+                ),
+        )
+        .arg(
+            Arg::new("annotate")
+                .long("annotate")
+                .action(ArgAction::SetTrue)
+                .help("Precedes the VM code of every statement with a comment quoting it.")
+                .long_help(
+"Precedes the VM code of every statement with a comment quoting it, e.g.
+'// Main.jack:12: let x = x + 1;'. Only the first line of statements spanning several lines is
+quoted."
+                ),
+        )
+        .arg(
+            Arg::new("source-map")
+                .long("source-map")
+                .action(ArgAction::SetTrue)
+                .requires("output")
+                .help("Writes a source map next to every VM file.")
+                .long_help(
+"Writes a source map next to every VM file, named like it with an additional '.map' extension.
+It records the Jack code and subroutine every VM instruction comes from. Requires '--output'."
+                ),
+        )
+        .arg(
+            Arg::new("peephole")
+                .long("peephole")
+                .action(ArgAction::SetTrue)
+                .help("Removes redundant VM instructions with peephole rewrites.")
+                .long_help(
+"Removes redundant VM instructions with peephole rewrites, such as a 'push' followed by a 'pop'
+to the same place, or a 'goto' to the next instruction, and reports the number of instructions
+removed from every function to stderr. It can't be combined with '-f ir' or '-f asm'."
+                ),
+        )
+        .arg(
+            Arg::new("tree-shake")
+                .long("tree-shake")
+                .action(ArgAction::SetTrue)
+                .help("Leaves out the subroutines the program never calls.")
+                .long_help(
+"Leaves out the subroutines the program never calls, starting from 'Main.main' and 'Sys.init',
+and reports them to stderr. The classes of the Jack OS compiled with the program are kept whole
+unless '--tree-shake-os' is given. This is meant for compiling a directory holding the whole
+program."
+                ),
+        )
+        .arg(
+            Arg::new("tree-shake-os")
+                .long("tree-shake-os")
+                .action(ArgAction::SetTrue)
+                .requires("tree-shake")
+                .help("Also leaves out the unused subroutines of the Jack OS classes.")
+                .long_help(
+"Also leaves out the unused subroutines of the Jack OS classes compiled with the program, such
+as 'Math' or 'String'. The calls the generated code makes to them, e.g. to 'Math.multiply' for
+'*', count as uses. Requires '--tree-shake'."
+                ),
+        )
+        .arg(
+            Arg::new("compat")
+                .long("compat")
+                .value_parser(["official"])
+                .conflicts_with_all(["pool-strings", "peephole"])
+                .help("Produces the same VM code as another Jack compiler.")
+                .long_help(
+"Produces the same VM code as another Jack compiler, for programs it accepts. The only possible
+value is 'official', for the JackCompiler of the nand2tetris software suite."
                 ),
         )
+        .arg(
+            Arg::new("optimize")
+                .short('O')
+                .long("optimize")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("compat")
+                .help("Optimises the VM code.")
+                .long_help(
+"Optimises the VM code, compiling it through the intermediate representation: constant
+expressions are folded, operations with a neutral operand simplified, multiplications by powers
+of two replaced by additions, and unneeded instructions removed. With '-f ir', prints the
+optimised intermediate representation, and with '-f asm', translates it. It can't be combined
+with '--compat'."
+                ),
+        )
+        .arg(
+            Arg::new("inline")
+                .long("inline")
+                .action(ArgAction::SetTrue)
+                .requires("optimize")
+                .help("Inlines getters at their call sites.")
+                .long_help(
+"Inlines small functions and methods whose body is a single 'return' statement at their call
+sites, across the classes compiled together. Requires '--optimize'."
+                ),
+        )
+        .arg(lint_level_arg("allow", 'A', "Disables the given lint rule."))
+        .arg(lint_level_arg("warn", 'W', "Reports violations of the given lint rule as warnings."))
+        .arg(lint_level_arg("deny", 'D', "Reports violations of the given lint rule as errors."))
+        .arg(lint_option_arg())
+        .arg(diagnostics_format_arg())
         .arg(
             Arg::new("input")
-                .help("The input Jack source file.")
+                .help("The input Jack source file, or a directory of Jack source files.")
                 .required(true)
         )
         .get_matches();
+    match matches.subcommand() {
+        Some(("peephole", matches)) => return peephole(matches),
+        Some(("translate", matches)) => return translate(matches),
+        Some(("assemble", matches)) => return assemble(matches),
+        Some(("build", matches)) => return build(matches, color),
+        _ => {}
+    }
 
-    let input = matches.get_one::<String>("input").unwrap();
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
     let output = matches.get_one::<String>("output");
     let format = matches.get_one::<String>("format").unwrap();
+    if matches.get_flag("peephole") && (format == "ir" || format == "asm") {
+        exit_with_error(
+            format!(
+                "`--peephole` rewrites VM code, and can't be combined with `-f {}`",
+                format
+            ),
+            color,
+            2,
+        );
+    }
+
+    let paths = source_paths(input, "jack")?;
+    let sources = read_sources(&paths)?;
+
+    let linter = linter(&matches, color);
+    let mut emitter = diagnostics_emitter(&matches, &linter, color);
+    let files: Vec<SourceFile> = paths
+        .iter()
+        .zip(&sources)
+        .map(|(path, source)| SourceFile { path, source })
+        .collect();
+    let (mut classes, mut has_errors) = check(&files, &linter, emitter.as_mut())?;
+
+    let mut vm_code = Vec::new();
+    let mut ir_code = Vec::new();
+    let mut removed_subroutines = None;
+    let mut peephole_removed = Vec::new();
+    if (format == "vm" || format == "ir" || format == "asm") && !has_errors {
+        if matches.get_flag("tree-shake") {
+            let has_main = classes.iter().any(|class| {
+                class.name.name == "Main"
+                    && class
+                        .subroutines
+                        .iter()
+                        .any(|subroutine| subroutine.name.name == "main")
+            });
+            if !has_main {
+                exit_with_error(
+                    "`--tree-shake` needs a `Main.main` function to start from",
+                    color,
+                    1,
+                );
+            }
+            removed_subroutines = Some(tree_shake::tree_shake(
+                &mut classes,
+                matches.get_flag("tree-shake-os"),
+            ));
+        }
+        let options = codegen::Options {
+            pool_strings: matches.get_flag("pool-strings"),
+            compat: matches
+                .get_one::<String>("compat")
+                .map(|_| codegen::Compat::Official),
+            optimize: matches.get_flag("optimize"),
+            inline: matches.get_flag("inline"),
+        };
+        let results: Vec<_> = if format == "vm" {
+            codegen::compile_program(&classes, &options)
+                .into_iter()
+                .map(|result| {
+                    result.map(|mut code| {
+                        if matches.get_flag("peephole") {
+                            peephole_removed.extend(code.peephole());
+                        }
+                        vm_code.push(code)
+                    })
+                })
+                .collect()
+        } else {
+            ir::lower_program(&classes, &options)
+                .into_iter()
+                .map(|result| {
+                    result.map(|mut functions| {
+                        if options.optimize {
+                            functions.iter_mut().for_each(ir::optimize);
+                        }
+                        ir_code.push(functions)
+                    })
+                })
+                .collect()
+        };
+        for (file, result) in files.iter().zip(results) {
+            if let Err(diagnostics) = result {
+                for diagnostic in &diagnostics {
+                    emitter.emit(file, diagnostic)?;
+                }
+                has_errors = true;
+            }
+        }
+    }
+    emitter.finish()?;
+    if has_errors {
+        process::exit(1);
+    }
+    if let Some(removed) = removed_subroutines {
+        for name in &removed {
+            eprintln!("removed {}", name);
+        }
+        eprintln!("{} removed", plural(removed.len(), "subroutine"));
+    }
+    if matches.get_flag("peephole") && format == "vm" {
+        let total = report_peephole(&peephole_removed);
+        eprintln!("{} removed in total", plural(total, "instruction"));
+    }
+
+    let write = |index: usize, output: Option<&Path>| {
+        let writer = open_output(output)?;
+        if format == "vm" {
+            let file = &files[index];
+            if let Some(out_path) = output
+                && matches.get_flag("source-map")
+            {
+                let file_name = file.path.file_name().unwrap_or_default().to_string_lossy();
+                let source_map = SourceMap::new(&vm_code[index], &file_name, file.source);
+                let mut map_path = out_path.as_os_str().to_owned();
+                map_path.push(".map");
+                fs::write(map_path, source_map.to_json())?;
+            }
+            let annotate = matches.get_flag("annotate").then_some(file);
+            write_vm(&vm_code[index], annotate, writer)
+        } else if format == "ir" {
+            write_ir(&ir_code[index], writer)
+        } else {
+            write_ast(&classes[index], format, writer)
+        }
+    };
+
+    if format == "asm" {
+        check_calls(&ir_code)?;
+        return write_asm(&ir_code, open_output(output.map(Path::new))?);
+    }
+    if input.is_dir() {
+        // When compiling a directory, the output path names a directory holding one file per
+        // class.
+        let extension = match format.as_str() {
+            "xml" => "xml",
+            "vm" => "vm",
+            "ir" => "ir",
+            _ => "txt",
+        };
+        for (index, path) in paths.iter().enumerate() {
+            let out_path = output.map(|out_dir| {
+                Path::new(out_dir)
+                    .join(path.file_name().unwrap())
+                    .with_extension(extension)
+            });
+            write(index, out_path.as_deref())?;
+        }
+        Ok(())
+    } else {
+        write(0, output.map(Path::new))
+    }
+}
+
+/// Runs the `peephole` subcommand.
+fn peephole(matches: &ArgMatches) -> io::Result<()> {
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let output = matches.get_one::<String>("output").map(Path::new);
+    let mut total = 0;
+    for path in source_paths(input, "vm")? {
+        let instructions = read_vm(&path)?;
+        let optimized = vm::peephole(&instructions);
+        total += report_peephole(&optimized.removed);
+
+        let out_path = match output {
+            Some(out_dir) if input.is_dir() => Some(out_dir.join(path.file_name().unwrap())),
+            _ => output.map(Path::to_path_buf),
+        };
+        let mut writer = BufWriter::new(open_output(out_path.as_deref())?);
+        for instruction in &optimized.instructions {
+            writeln!(writer, "{}", instruction)?;
+        }
+        writer.flush()?;
+    }
+    eprintln!("{} removed in total", plural(total, "instruction"));
+    Ok(())
+}
+
+/// Reports the number of instructions the peephole optimiser removed from every function that
+/// lost some, and returns the total.
+fn report_peephole(removed: &[(String, usize)]) -> usize {
+    for (function, removed) in removed {
+        if *removed > 0 {
+            eprintln!("{}: {} removed", function, plural(*removed, "instruction"));
+        }
+    }
+    removed.iter().map(|(_, removed)| removed).sum()
+}
+
+/// Runs the `translate` subcommand.
+fn translate(matches: &ArgMatches) -> io::Result<()> {
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let output = matches.get_one::<String>("output").map(Path::new);
+    let mut asm = Asm::new();
+    if !matches.get_flag("no-bootstrap") {
+        asm.bootstrap();
+    }
+    for path in source_paths(input, "vm")? {
+        let instructions = read_vm(&path)?;
+        let file = path.file_stem().unwrap_or_default().to_string_lossy();
+        vm::translate(&file, &instructions, &mut asm);
+    }
+    write_lines(&asm.lines, open_output(output)?)
+}
+
+/// Runs the `assemble` subcommand.
+fn assemble(matches: &ArgMatches) -> io::Result<()> {
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let output = matches.get_one::<String>("output").map(Path::new);
+    let source = fs::read_to_string(input).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("couldn't read {}: {}", input.display(), e),
+        )
+    })?;
+    let code = hack::assemble(&source).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", input.display(), e),
+        )
+    })?;
+    let mut writer = open_output(output)?;
+    writer.write_all(hack::to_binary(&code).as_bytes())?;
+    writer.flush()
+}
+
+/// Runs the `build` subcommand.
+fn build(matches: &ArgMatches, color: bool) -> io::Result<()> {
+    let input = Path::new(matches.get_one::<String>("input").unwrap());
+    let output = match matches.get_one::<String>("output") {
+        Some(output) => PathBuf::from(output),
+        None if input.is_dir() => input.join("build"),
+        None => input.with_file_name("build"),
+    };
+    let emit: Vec<&str> = matches
+        .get_many::<String>("emit")
+        .unwrap()
+        .map(String::as_str)
+        .collect();
+    // The program is named after its directory, which only the full path gives for `.`
+    let input = input.canonicalize().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("couldn't read {}: {}", input.display(), e),
+        )
+    })?;
+    let name = if input.is_dir() {
+        input.file_name()
+    } else {
+        input.file_stem()
+    }
+    .unwrap_or_default()
+    .to_string_lossy()
+    .into_owned();
+
+    let paths = source_paths(&input, "jack")?;
+    let sources = read_sources(&paths)?;
+    let linter = linter(matches, color);
+    let mut emitter = diagnostics_emitter(matches, &linter, color);
+    let files: Vec<SourceFile> = paths
+        .iter()
+        .zip(&sources)
+        .map(|(path, source)| SourceFile { path, source })
+        .collect();
+    let (classes, mut has_errors) = check(&files, &linter, emitter.as_mut())?;
+    let mut vm_files = Vec::with_capacity(classes.len());
+    if !has_errors {
+        let options = codegen::Options {
+            optimize: matches.get_flag("optimize"),
+            inline: matches.get_flag("inline"),
+            ..codegen::Options::default()
+        };
+        let results = codegen::compile_program(&classes, &options);
+        for ((file, class), result) in files.iter().zip(&classes).zip(results) {
+            match result {
+                Ok(code) => vm_files.push(vm::VmFile {
+                    name: class.name.name.to_string(),
+                    instructions: code.instructions,
+                }),
+                Err(diagnostics) => {
+                    for diagnostic in &diagnostics {
+                        emitter.emit(file, diagnostic)?;
+                    }
+                    has_errors = true;
+                }
+            }
+        }
+    }
+    emitter.finish()?;
+    if has_errors {
+        process::exit(1);
+    }
+
+    if emit.contains(&"vm") {
+        for file in &vm_files {
+            let writer = open_output(Some(&output.join(format!("{}.vm", file.name))))?;
+            let lines: Vec<String> = file.instructions.iter().map(ToString::to_string).collect();
+            write_lines(&lines, writer)?;
+        }
+    }
+    // Only the assembly and the machine code need a whole program
+    if !emit.contains(&"asm") && !emit.contains(&"hack") {
+        return Ok(());
+    }
+    let os = matches.get_one::<String>("os");
+    if let Some(os) = os {
+        let program_files = vm_files.len();
+        for path in source_paths(Path::new(os), "vm")? {
+            let file = path.file_stem().unwrap_or_default().to_string_lossy();
+            if !vm_files[..program_files]
+                .iter()
+                .any(|vm_file| vm_file.name == file)
+            {
+                vm_files.push(vm::VmFile {
+                    name: file.into_owned(),
+                    instructions: read_vm(&path)?,
+                });
+            }
+        }
+    }
+    let linked = vm::link(&vm_files, "Sys.init").map_err(|e| {
+        let message = match e {
+            vm::LinkError::MissingEntry(_) | vm::LinkError::Undefined { .. } if os.is_none() => {
+                format!("{}, the Jack OS can be linked with `--os`", e)
+            }
+            _ => e.to_string(),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
+
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    for file in &linked {
+        vm::translate(&file.name, &file.instructions, &mut asm);
+    }
+    let asm_path = output.join(format!("{}.asm", name));
+    if emit.contains(&"asm") {
+        write_lines(&asm.lines, open_output(Some(&asm_path))?)?;
+    }
+    let code = hack::assemble(&asm.lines.join("\n")).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", asm_path.display(), e),
+        )
+    })?;
+    if emit.contains(&"hack") {
+        let mut writer = open_output(Some(&output.join(format!("{}.hack", name))))?;
+        writer.write_all(hack::to_binary(&code).as_bytes())?;
+        writer.flush()?;
+    }
+    eprintln!("{}: {}", name, plural(code.len(), "instruction"));
+    Ok(())
+}
+
+/// Reads and parses a VM file.
+fn read_vm(path: &Path) -> io::Result<Vec<vm::Instruction>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        io::Error::new(e.kind(), format!("couldn't read {}: {}", path.display(), e))
+    })?;
+    vm::parse(&source).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Returns e.g. `1 instruction` or `2 instructions`.
+fn plural(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn lint_level_arg(name: &'static str, short: char, help: &'static str) -> Arg {
+    Arg::new(name)
+        .short(short)
+        .long(name)
+        .value_name("RULE")
+        .action(ArgAction::Append)
+        .help(help)
+        .long_help(format!(
+            "{} RULE is a rule code such as 'J0101', a rule name such as 'unused-local', or
+'warnings' for every rule that currently reports warnings. Can be given multiple times; later
+flags override earlier ones.",
+            help
+        ))
+}
+
+fn lint_option_arg() -> Arg {
+    Arg::new("lint-option")
+        .long("lint-option")
+        .value_name("KEY=VALUE")
+        .action(ArgAction::Append)
+        .help("Sets a threshold used by the style lint rules.")
+        .long_help(
+"Sets a threshold used by the style lint rules. Possible keys are: 'max-statements' (the
+maximum number of statements in a subroutine, default 60) and 'max-nesting' (the maximum depth
+of nested if/while statements, default 4)."
+        )
+}
 
-    let source = fs::read_to_string(input)?;
-    let lexer = lexer::Lexer::new(&source);
-    let parser = parser::ClassParser::new();
-    let ast = parser
-        .parse(&source, lexer)
-        .unwrap_or_else(|e| panic!("error occurs while parsing: {:?}", e));
+fn diagnostics_format_arg() -> Arg {
+    Arg::new("diagnostics-format")
+        .long("diagnostics-format")
+        .value_parser(["human", "json", "sarif"])
+        .default_value("human")
+        .help("The format of the diagnostics written to stderr.")
+        .long_help(
+            "The format of the diagnostics written to stderr. Possible values are: 'human', 'json',
+'sarif'. 'json' writes one JSON object per diagnostic and per line, and 'sarif' writes a single
+SARIF 2.1.0 log. The default value is 'human'.",
+        )
+}
 
-    let mut inner_writer: Box<dyn Write> = if let Some(out_path) = output {
+/// Creates a linter with the rule levels given on the command line applied in order.
+fn linter(matches: &ArgMatches, color: bool) -> Linter {
+    let mut overrides = Vec::new();
+    for (arg, level) in [
+        ("allow", Level::Allow),
+        ("warn", Level::Warn),
+        ("deny", Level::Deny),
+    ] {
+        if let (Some(indices), Some(rules)) =
+            (matches.indices_of(arg), matches.get_many::<String>(arg))
+        {
+            overrides.extend(indices.zip(rules).map(|(index, rule)| (index, rule, level)));
+        }
+    }
+    overrides.sort_by_key(|(index, _, _)| *index);
+
+    let mut linter = Linter::new();
+    for (_, rule, level) in overrides {
+        if let Err(e) = linter.set_level(rule, level) {
+            exit_with_error(e, color, 2);
+        }
+    }
+    for option in matches
+        .get_many::<String>("lint-option")
+        .into_iter()
+        .flatten()
+    {
+        let result = match option.split_once('=') {
+            Some((key, value)) => linter.set_option(key, value).map_err(|e| e.to_string()),
+            None => Err(format!(
+                "lint option `{}` isn't of the form KEY=VALUE",
+                option
+            )),
+        };
+        if let Err(e) = result {
+            exit_with_error(e, color, 2);
+        }
+    }
+    linter
+}
+
+/// Creates the emitter of the diagnostics in the format given on the command line.
+fn diagnostics_emitter(matches: &ArgMatches, linter: &Linter, color: bool) -> Box<dyn Emitter> {
+    match matches
+        .get_one::<String>("diagnostics-format")
+        .unwrap()
+        .as_str()
+    {
+        "json" => Box::new(JsonEmitter::new(io::stderr())),
+        "sarif" => Box::new(SarifEmitter::new(io::stderr(), linter)),
+        _ => Box::new(HumanEmitter::new(io::stderr()).with_color(color)),
+    }
+}
+
+fn read_sources(paths: &[PathBuf]) -> io::Result<Vec<String>> {
+    paths
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path).map_err(|e| {
+                io::Error::new(e.kind(), format!("couldn't read {}: {}", path.display(), e))
+            })
+        })
+        .collect()
+}
+
+/// Parses the files and runs the lint rules on them, and returns the classes parsed and whether
+/// an error was reported.
+fn check<'a>(
+    files: &[SourceFile<'a>],
+    linter: &Linter,
+    emitter: &mut dyn Emitter,
+) -> io::Result<(Vec<ast::Class<'a>>, bool)> {
+    let mut classes = Vec::with_capacity(files.len());
+    let mut has_errors = false;
+    for file in files {
+        let lexer = lexer::Lexer::new(file.source);
+        let parser = parser::ClassParser::new();
+        match parser.parse(file.source, lexer) {
+            Ok(class) => classes.push(class),
+            Err(e) => {
+                emitter.emit(file, &parser::error_diagnostic(file.source, &e))?;
+                has_errors = true;
+            }
+        }
+    }
+
+    // The lint rules look at the whole program, so they only run once every class is parsed
+    if !has_errors {
+        let sources: Vec<&str> = files.iter().map(|file| file.source).collect();
+        let diagnostics = linter.check_program(&classes, &sources);
+        for (file, diagnostics) in files.iter().zip(&diagnostics) {
+            for diagnostic in diagnostics {
+                emitter.emit(file, diagnostic)?;
+            }
+            has_errors |= diagnostics.iter().any(Diagnostic::is_error);
+        }
+    }
+    Ok((classes, has_errors))
+}
+
+/// Colours are used when stderr is a terminal, unless the `NO_COLOR` environment variable is set
+/// to a non-empty value (see <https://no-color.org>).
+fn color_enabled() -> bool {
+    io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Reports an error that doesn't refer to any source file and exits with `code`.
+fn exit_with_error(message: impl Display, color: bool, code: i32) -> ! {
+    // There's nothing left to report the error to if stderr itself fails
+    let _ = HumanEmitter::new(io::stderr())
+        .with_color(color)
+        .error(message);
+    process::exit(code);
+}
+
+/// Returns the input file itself, or every file with the given extension in the input directory
+/// in name order.
+fn source_paths(input: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    if !input.is_dir() {
+        return Ok(vec![input.to_path_buf()]);
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(input)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Opens the output file, or stdout if there's none.
+fn open_output(output: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    Ok(if let Some(out_path) = output {
         ensure_parent(out_path)?;
         Box::new(
             fs::OpenOptions::new()
@@ -54,14 +873,88 @@ supports: XML and Rust debug print. The default value is 'xml'."
         )
     } else {
         Box::new(io::stdout())
+    })
+}
+
+/// Writes VM code, annotated with the statements of `file` if it's given.
+fn write_vm(code: &ClassCode, file: Option<&SourceFile>, writer: Box<dyn Write>) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    match file {
+        Some(file) => {
+            let file_name = file.path.file_name().unwrap_or_default().to_string_lossy();
+            code.write_annotated(&mut writer, &file_name, file.source)?
+        }
+        None => code.write(&mut writer)?,
+    }
+    writer.flush()
+}
+
+/// Writes the IR of the subroutines of a class, separated by blank lines.
+fn write_ir(functions: &[ir::Function], writer: Box<dyn Write>) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    for (i, function) in functions.iter().enumerate() {
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        write!(writer, "{}", function)?;
+    }
+    writer.flush()
+}
+
+/// Checks that `Sys.init` and every function the program calls are defined, since the assembler
+/// would take the name of an undefined one for a variable.
+fn check_calls(program: &[Vec<ir::Function>]) -> io::Result<()> {
+    let defined: HashSet<&str> = program
+        .iter()
+        .flatten()
+        .map(|function| function.name.as_str())
+        .collect();
+    let error = if !defined.contains("Sys.init") {
+        Some(vm::LinkError::MissingEntry("Sys.init".to_string()))
+    } else {
+        program.iter().flatten().find_map(|function| {
+            let callee = function
+                .callees()
+                .into_iter()
+                .find(|callee| !defined.contains(callee))?;
+            Some(vm::LinkError::Undefined {
+                function: callee.to_string(),
+                caller: function.name.clone(),
+            })
+        })
     };
+    if let Some(e) = error {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}, the Jack OS has to be compiled with the program", e),
+        ));
+    }
+    Ok(())
+}
+
+fn write_asm(program: &[Vec<ir::Function>], writer: Box<dyn Write>) -> io::Result<()> {
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    for function in program.iter().flatten() {
+        ir::to_asm(function, &mut asm);
+    }
+    write_lines(&asm.lines, writer)
+}
 
+fn write_lines(lines: &[String], writer: Box<dyn Write>) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+    writer.flush()
+}
+
+fn write_ast(ast: &ast::Class, format: &str, mut inner_writer: Box<dyn Write>) -> io::Result<()> {
     if format == "xml" {
         // writing XML involves a lot of small I/Os, so it would benefit from a write buffer
         let mut writer = utils::init_writer(BufWriter::new(inner_writer));
-        if let Err(e) = ast.write_xml(&mut writer) {
-            panic!("error occurs while writing output: {}", e);
-        }
+        ast.write_xml(&mut writer)
+            .map_err(|e| io::Error::other(format!("error occurs while writing output: {}", e)))?;
         // flush the write buffer
         writer.inner_mut().flush()
     } else {
@@ -74,7 +967,10 @@ supports: XML and Rust debug print. The default value is 'xml'."
 fn ensure_parent<S: AsRef<OsStr> + ?Sized>(s: &S) -> io::Result<()> {
     let path = Path::new(s);
     let Some(parent) = path.parent() else {
-        panic!("invalid output path");
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid output path `{}`", path.display()),
+        ));
     };
     fs::create_dir_all(parent)
 }
@@ -1,5 +1,9 @@
 //! A wrapper module that loads the generated parser
 
+use crate::diagnostics::Diagnostic;
+use crate::lexer::LexError;
+use crate::span::Span;
+use crate::token::Token;
 use lalrpop_util::lalrpop_mod;
 
 lalrpop_mod!(
@@ -8,3 +12,50 @@ lalrpop_mod!(
 );
 
 pub use parser::*;
+
+pub type ParseError<'source> = lalrpop_util::ParseError<usize, Token<'source>, LexError>;
+
+/// Converts an error returned by a parser into a diagnostic.
+pub fn error_diagnostic(source: &str, error: &ParseError) -> Diagnostic {
+    use lalrpop_util::ParseError::*;
+    match error {
+        InvalidToken { location } => {
+            Diagnostic::error("invalid token", Span::new(*location, *location))
+        }
+        UnrecognizedEof { location, expected } => {
+            Diagnostic::error("unexpected end of file", Span::new(*location, *location))
+                .with_note(expected_note(expected))
+        }
+        UnrecognizedToken {
+            token: (start, _, end),
+            expected,
+        } => Diagnostic::error(
+            format!("unexpected token `{}`", &source[*start..*end]),
+            Span::new(*start, *end),
+        )
+        .with_note(expected_note(expected)),
+        ExtraToken {
+            token: (start, _, end),
+        } => Diagnostic::error(
+            format!(
+                "unexpected token `{}` after the end of the class",
+                &source[*start..*end]
+            ),
+            Span::new(*start, *end),
+        ),
+        User { error } => Diagnostic::error(error.error.to_string(), error.span),
+    }
+}
+
+/// Lists the expected terminals, which LALRPOP reports quoted, e.g. `"\";\""`.
+fn expected_note(expected: &[String]) -> String {
+    let expected: Vec<String> = expected
+        .iter()
+        .map(|terminal| format!("`{}`", terminal.trim_matches('"')))
+        .collect();
+    match expected.as_slice() {
+        [] => "no more tokens were expected".to_string(),
+        [only] => format!("expected {}", only),
+        _ => format!("expected one of {}", expected.join(", ")),
+    }
+}
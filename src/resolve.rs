@@ -0,0 +1,158 @@
+//! Name resolution shared by the code generators
+//!
+//! A [`Resolver`] turns the names used in a subroutine into the variables and functions they
+//! refer to, and reports those that can't be used: unknown variables, the current object used in
+//! a function, methods called on primitive values and string constants outside the Hack character
+//! set.
+
+use crate::ast;
+use crate::codegen::is_hack_char;
+use crate::diagnostics::Diagnostic;
+use crate::span::Span;
+use crate::symbol_table::{SymbolTable, VarKind};
+
+/// The object a method is called on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Receiver {
+    /// The current object, for unqualified calls.
+    This,
+    Variable(VarKind, u16),
+}
+
+/// The subroutine a call refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Callee {
+    /// The name of the VM function, e.g. `Math.multiply`.
+    pub name: String,
+    /// The object passed as argument 0, for method calls.
+    pub receiver: Option<Receiver>,
+}
+
+pub struct Resolver<'a, 'source> {
+    class: &'a ast::Class<'source>,
+    pub symbols: SymbolTable<'source>,
+    /// The subroutine being resolved.
+    subroutine: Option<&'a ast::SubroutineDec<'source>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a, 'source> Resolver<'a, 'source> {
+    pub fn new(class: &'a ast::Class<'source>) -> Self {
+        Self {
+            class,
+            symbols: SymbolTable::new(class),
+            subroutine: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn enter_subroutine(&mut self, subroutine: &'a ast::SubroutineDec<'source>) {
+        self.symbols.enter_subroutine(subroutine);
+        self.subroutine = Some(subroutine);
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Resolves a variable to its kind and index, reporting an error if it can't be used here.
+    pub fn variable(&mut self, name: &ast::VarName) -> Option<(VarKind, u16)> {
+        let Some(variable) = self.symbols.lookup(name.name) else {
+            self.error(Diagnostic::error(
+                format!("cannot find variable `{}` in this scope", name),
+                name.span,
+            ));
+            return None;
+        };
+        let (kind, index) = (variable.kind, variable.index);
+        if kind == VarKind::Field && !self.check_this(name.span, &format!("field `{}`", name)) {
+            return None;
+        }
+        Some((kind, index))
+    }
+
+    /// Reports an error if `what`, which needs the current object, is used in a function.
+    pub fn check_this(&mut self, span: Span, what: &str) -> bool {
+        let subroutine = self.subroutine.unwrap();
+        if subroutine.kind != ast::SubroutineKind::Function {
+            return true;
+        }
+        self.error(
+            Diagnostic::error(
+                format!("cannot use {} in function `{}`", what, subroutine.name),
+                span,
+            )
+            .with_note("functions have no current object; only constructors and methods do"),
+        );
+        false
+    }
+
+    /// Resolves the subroutine a call refers to. Returns `None` after reporting an error if the
+    /// call can't be made at all, in which case its arguments aren't worth resolving either.
+    pub fn callee(&mut self, call: &ast::SubroutineCall) -> Option<Callee> {
+        let (class_name, receiver) = match call.prefix {
+            // `Foo.bar()` where `Foo` isn't a variable calls a function or constructor
+            Some(prefix) if self.symbols.lookup(prefix.name).is_none() => (prefix.name, None),
+            // `foo.bar()` calls a method on the object in `foo`
+            Some(prefix) => {
+                let variable = self.symbols.lookup(prefix.name).unwrap();
+                let (kind, index) = (variable.kind, variable.index);
+                let ast::Ty::Class(class_name) = variable.ty else {
+                    let ty = match variable.ty {
+                        ast::Ty::Int => "int",
+                        ast::Ty::Char => "char",
+                        _ => "boolean",
+                    };
+                    self.error(Diagnostic::error(
+                        format!("`{}` is of type `{}`, which has no methods", prefix, ty),
+                        prefix.span,
+                    ));
+                    return None;
+                };
+                self.variable(&prefix);
+                (class_name.name, Some(Receiver::Variable(kind, index)))
+            }
+            // `bar()` calls a subroutine of the current class, on the current object unless it's
+            // declared as a function or constructor
+            None => {
+                let is_method = !self.class.subroutines.iter().any(|subroutine| {
+                    subroutine.name.name == call.name.name
+                        && subroutine.kind != ast::SubroutineKind::Method
+                });
+                let receiver = if is_method {
+                    self.check_this(call.name.span, &format!("method `{}`", call.name));
+                    Some(Receiver::This)
+                } else {
+                    None
+                };
+                (self.class.name.name, receiver)
+            }
+        };
+        Some(Callee {
+            name: format!("{}.{}", class_name, call.name),
+            receiver,
+        })
+    }
+
+    /// Returns the characters of a string constant without its quotes, or `None` after reporting
+    /// the ones that aren't in the Hack character set.
+    pub fn string(&mut self, literal: &'source str, span: Span) -> Option<&'source str> {
+        // The lexer keeps the quotes around the string
+        let content = &literal[1..literal.len() - 1];
+        let mut valid = true;
+        for (offset, c) in content.char_indices() {
+            if !is_hack_char(c) {
+                let start = span.start + 1 + offset;
+                self.error(
+                    Diagnostic::error(
+                        format!("character {:?} isn't in the Hack character set", c),
+                        Span::new(start, start + c.len_utf8()),
+                    )
+                    .with_note("string constants can only hold printable ASCII characters"),
+                );
+                valid = false;
+            }
+        }
+        valid.then_some(content)
+    }
+}
@@ -0,0 +1,235 @@
+//! Source maps from VM code back to the Jack source
+//!
+//! A source map is written next to a `.vm` file as `<name>.vm.map`. It's a JSON object of the
+//! form:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "file": "Main.jack",
+//!   "subroutines": [
+//!     { "name": "Main.main", "start": 13, "end": 104, "line": 2 }
+//!   ],
+//!   "instructions": [
+//!     { "subroutine": 0, "start": 13, "end": 104, "line": 2, "column": 5 },
+//!     { "subroutine": 0, "start": 70, "end": 71, "line": 4, "column": 17 }
+//!   ]
+//! }
+//! ```
+//!
+//! `file` is the Jack source file the VM code was compiled from. `subroutines` lists its
+//! subroutines in order, by the name of their VM function and their span. `instructions` has one
+//! entry per instruction of the `.vm` file, in order, not counting comments and blank lines: the
+//! index of the subroutine the instruction belongs to, and the span of the Jack code it was
+//! generated from. Spans are byte offsets into the Jack file, `start` included and `end`
+//! excluded, and `line` and `column` are the 1-based position of `start`, columns being counted in
+//! characters.
+
+use crate::codegen::ClassCode;
+use crate::span::{self, Span};
+use crate::vm::Instruction;
+use serde_json::{Value, json};
+use std::fmt::{self, Display};
+
+/// The version of the format written by [`SourceMap::to_json`].
+pub const VERSION: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    pub file: String,
+    pub subroutines: Vec<Subroutine>,
+    pub instructions: Vec<Origin>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subroutine {
+    /// The name of the VM function, e.g. `Main.main`.
+    pub name: String,
+    pub span: Span,
+    pub line: usize,
+}
+
+/// Where an instruction comes from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Origin {
+    /// The index of the subroutine in [`SourceMap::subroutines`].
+    pub subroutine: usize,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMapError(String);
+
+impl Display for SourceMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid source map: {}", self.0)
+    }
+}
+
+impl std::error::Error for SourceMapError {}
+
+impl SourceMap {
+    /// Creates the source map of the code compiled from `source`, read from the file `file`.
+    pub fn new(code: &ClassCode, file: &str, source: &str) -> Self {
+        let subroutines = code
+            .subroutines
+            .iter()
+            .map(|&(index, span)| {
+                let Instruction::Function { name, .. } = &code.instructions[index] else {
+                    panic!(
+                        "subroutine {} doesn't start with a function instruction",
+                        index
+                    );
+                };
+                Subroutine {
+                    name: name.clone(),
+                    span,
+                    line: span::line_col(source, span.start).0,
+                }
+            })
+            .collect();
+        let mut subroutine = 0;
+        let instructions = code
+            .origins
+            .iter()
+            .enumerate()
+            .map(|(index, &span)| {
+                while code
+                    .subroutines
+                    .get(subroutine + 1)
+                    .is_some_and(|&(start, _)| start <= index)
+                {
+                    subroutine += 1;
+                }
+                let (line, column) = span::line_col(source, span.start);
+                Origin {
+                    subroutine,
+                    span,
+                    line,
+                    column,
+                }
+            })
+            .collect();
+        Self {
+            file: file.to_string(),
+            subroutines,
+            instructions,
+        }
+    }
+
+    /// Returns where the instruction at `index` comes from, along with its subroutine.
+    pub fn lookup(&self, index: usize) -> Option<(&Origin, &Subroutine)> {
+        let origin = self.instructions.get(index)?;
+        Some((origin, self.subroutines.get(origin.subroutine)?))
+    }
+
+    pub fn to_json(&self) -> String {
+        let subroutines: Vec<Value> = self
+            .subroutines
+            .iter()
+            .map(|subroutine| {
+                json!({
+                    "name": subroutine.name,
+                    "start": subroutine.span.start,
+                    "end": subroutine.span.end,
+                    "line": subroutine.line,
+                })
+            })
+            .collect();
+        let instructions: Vec<Value> = self
+            .instructions
+            .iter()
+            .map(|origin| {
+                json!({
+                    "subroutine": origin.subroutine,
+                    "start": origin.span.start,
+                    "end": origin.span.end,
+                    "line": origin.line,
+                    "column": origin.column,
+                })
+            })
+            .collect();
+        json!({
+            "version": VERSION,
+            "file": self.file,
+            "subroutines": subroutines,
+            "instructions": instructions,
+        })
+        .to_string()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SourceMapError> {
+        let value: Value = serde_json::from_str(json).map_err(|e| SourceMapError(e.to_string()))?;
+        let version = get_usize(&value, "version")?;
+        if version as u64 != VERSION {
+            return Err(SourceMapError(format!("unsupported version {}", version)));
+        }
+        let file = value
+            .get("file")
+            .and_then(Value::as_str)
+            .ok_or_else(|| missing("file"))?
+            .to_string();
+        let subroutines = get_array(&value, "subroutines")?
+            .iter()
+            .map(|subroutine| {
+                Ok(Subroutine {
+                    name: subroutine
+                        .get("name")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| missing("name"))?
+                        .to_string(),
+                    span: get_span(subroutine)?,
+                    line: get_usize(subroutine, "line")?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let instructions = get_array(&value, "instructions")?
+            .iter()
+            .map(|origin| {
+                let subroutine = get_usize(origin, "subroutine")?;
+                if subroutine >= subroutines.len() {
+                    return Err(SourceMapError(format!("unknown subroutine {}", subroutine)));
+                }
+                Ok(Origin {
+                    subroutine,
+                    span: get_span(origin)?,
+                    line: get_usize(origin, "line")?,
+                    column: get_usize(origin, "column")?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            file,
+            subroutines,
+            instructions,
+        })
+    }
+}
+
+fn missing(key: &str) -> SourceMapError {
+    SourceMapError(format!("missing or invalid `{}`", key))
+}
+
+fn get_usize(value: &Value, key: &str) -> Result<usize, SourceMapError> {
+    value
+        .get(key)
+        .and_then(Value::as_u64)
+        .map(|n| n as usize)
+        .ok_or_else(|| missing(key))
+}
+
+fn get_array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, SourceMapError> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| missing(key))
+}
+
+fn get_span(value: &Value) -> Result<Span, SourceMapError> {
+    Ok(Span::new(
+        get_usize(value, "start")?,
+        get_usize(value, "end")?,
+    ))
+}
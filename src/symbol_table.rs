@@ -0,0 +1,118 @@
+//! Variable scopes of a class and its subroutines
+//!
+//! Jack has exactly two scopes: the class scope holding `static` and `field` variables, and the
+//! subroutine scope holding parameters and locals. Subroutine-scope names shadow class-scope ones.
+
+use crate::ast;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VarKind {
+    Static,
+    Field,
+    Argument,
+    Local,
+}
+
+impl VarKind {
+    /// The kind as it's referred to in diagnostics.
+    pub fn description(self) -> &'static str {
+        match self {
+            VarKind::Static => "static variable",
+            VarKind::Field => "field",
+            VarKind::Argument => "parameter",
+            VarKind::Local => "local variable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable<'source> {
+    pub name: ast::VarName<'source>,
+    pub ty: ast::Ty<'source>,
+    pub kind: VarKind,
+    /// The index within the variable's VM segment.
+    pub index: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable<'source> {
+    class_scope: Vec<Variable<'source>>,
+    subroutine_scope: Vec<Variable<'source>>,
+    /// The index of the first declared parameter, which is 1 in methods.
+    argument_base: u16,
+}
+
+impl<'source> SymbolTable<'source> {
+    /// Creates a table holding the class-scope variables of `class`.
+    pub fn new(class: &ast::Class<'source>) -> Self {
+        let mut table = Self::default();
+        for var_dec in &class.variables {
+            let kind = match var_dec.kind {
+                ast::VarKind::Static => VarKind::Static,
+                ast::VarKind::Field => VarKind::Field,
+            };
+            for name in &var_dec.names {
+                table.define(*name, var_dec.ty.clone(), kind);
+            }
+        }
+        table
+    }
+
+    /// Replaces the subroutine scope with the parameters and locals of `subroutine`. Methods
+    /// receive `this` as argument 0, so their parameters start at index 1.
+    pub fn enter_subroutine(&mut self, subroutine: &ast::SubroutineDec<'source>) {
+        self.subroutine_scope.clear();
+        self.argument_base = u16::from(subroutine.kind == ast::SubroutineKind::Method);
+        for param in &subroutine.params.0 {
+            self.define(param.name, param.ty.clone(), VarKind::Argument);
+        }
+        for var_dec in &subroutine.body.variables {
+            for name in &var_dec.names {
+                self.define(*name, var_dec.ty.clone(), VarKind::Local);
+            }
+        }
+    }
+
+    fn define(&mut self, name: ast::VarName<'source>, ty: ast::Ty<'source>, kind: VarKind) {
+        let index = match kind {
+            VarKind::Argument => self.argument_base + self.count(kind),
+            _ => self.count(kind),
+        };
+        let variable = Variable {
+            name,
+            ty,
+            kind,
+            index,
+        };
+        match kind {
+            VarKind::Static | VarKind::Field => self.class_scope.push(variable),
+            VarKind::Argument | VarKind::Local => self.subroutine_scope.push(variable),
+        }
+    }
+
+    /// Returns the number of declared variables of the given kind. The implicit `this` argument
+    /// of methods is not counted.
+    pub fn count(&self, kind: VarKind) -> u16 {
+        self.class_scope
+            .iter()
+            .chain(&self.subroutine_scope)
+            .filter(|var| var.kind == kind)
+            .count() as u16
+    }
+
+    /// Resolves a name, looking in the subroutine scope first.
+    pub fn lookup(&self, name: &str) -> Option<&Variable<'source>> {
+        self.subroutine_scope
+            .iter()
+            .find(|var| var.name.name == name)
+            .or_else(|| self.class_scope.iter().find(|var| var.name.name == name))
+    }
+
+    pub fn class_scope(&self) -> &[Variable<'source>] {
+        &self.class_scope
+    }
+
+    pub fn subroutine_scope(&self) -> &[Variable<'source>] {
+        &self.subroutine_scope
+    }
+}
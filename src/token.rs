@@ -12,6 +12,21 @@ pub enum LexicalError {
     InvalidToken,
 }
 
+impl Display for LexicalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexicalError::InvalidInteger(err) => write!(f, "invalid integer constant: {}", err),
+            LexicalError::UnrecognizedKeyword(err) => {
+                write!(f, "unrecognized keyword `{}`", err.unrecognized_keyword)
+            }
+            LexicalError::UnrecognizedSymbol(err) => {
+                write!(f, "unrecognized symbol `{}`", err.unrecognized_symbol)
+            }
+            LexicalError::InvalidToken => write!(f, "invalid token"),
+        }
+    }
+}
+
 impl From<ParseIntError> for LexicalError {
     fn from(err: ParseIntError) -> Self {
         LexicalError::InvalidInteger(err)
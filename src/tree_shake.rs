@@ -0,0 +1,107 @@
+//! Whole-program dead subroutine elimination
+//!
+//! [`call_graph`] finds the subroutines every subroutine of a program calls, and [`tree_shake`]
+//! removes the ones that can't be reached from `Main.main` and `Sys.init`, where programs start.
+//!
+//! Besides the calls written in the program, the generated code calls into the Jack OS: every
+//! constructor calls `Memory.alloc`, string constants call `String.new` and `String.appendChar`,
+//! and `*` and `/` call `Math.multiply` and `Math.divide`. These calls only matter when the OS
+//! classes are compiled as part of the program and shaken too.
+
+use crate::analysis::OS_CLASSES;
+use crate::ast;
+use crate::resolve::Resolver;
+use crate::visit::{self, Visitor};
+use std::collections::{BTreeMap, HashSet};
+
+/// The subroutines programs start from.
+pub const ROOTS: [&str; 2] = ["Main.main", "Sys.init"];
+
+/// Returns the VM functions every subroutine of a program calls, keyed by the name of the VM
+/// function of the subroutine, e.g. `Main.main`. Calls that can't be resolved are left out.
+pub fn call_graph(program: &[ast::Class]) -> BTreeMap<String, Vec<String>> {
+    let mut graph = BTreeMap::new();
+    for class in program {
+        let mut calls = Calls {
+            resolver: Resolver::new(class),
+            callees: Vec::new(),
+        };
+        for subroutine in &class.subroutines {
+            calls.resolver.enter_subroutine(subroutine);
+            if subroutine.kind == ast::SubroutineKind::Constructor {
+                calls.callees.push("Memory.alloc".to_string());
+            }
+            calls.visit_subroutine_dec(subroutine);
+            let name = format!("{}.{}", class.name, subroutine.name);
+            graph.insert(name, std::mem::take(&mut calls.callees));
+        }
+    }
+    graph
+}
+
+/// Removes the subroutines of a program that can't be reached from [`ROOTS`], and returns their
+/// names in order. Unless `shake_os` is set, the Jack OS classes of the program are kept whole.
+pub fn tree_shake(program: &mut [ast::Class], shake_os: bool) -> Vec<String> {
+    let graph = call_graph(program);
+    let mut pending: Vec<&str> = ROOTS.to_vec();
+    if !shake_os {
+        pending.extend(graph.keys().map(String::as_str).filter(|name| {
+            let class = name.split_once('.').map_or("", |(class, _)| class);
+            OS_CLASSES.contains(&class)
+        }));
+    }
+    let mut reachable = HashSet::new();
+    while let Some(name) = pending.pop() {
+        if reachable.insert(name) {
+            pending.extend(graph.get(name).into_iter().flatten().map(String::as_str));
+        }
+    }
+
+    let mut removed = Vec::new();
+    for class in program {
+        class.subroutines.retain(|subroutine| {
+            let name = format!("{}.{}", class.name, subroutine.name);
+            let keep = reachable.contains(name.as_str());
+            if !keep {
+                removed.push(name);
+            }
+            keep
+        });
+    }
+    removed
+}
+
+/// Collects the functions a subroutine calls.
+struct Calls<'a, 'source> {
+    resolver: Resolver<'a, 'source>,
+    callees: Vec<String>,
+}
+
+impl<'source> Visitor<'source> for Calls<'_, 'source> {
+    fn visit_expression(&mut self, expr: &ast::Expression<'source>) {
+        for (op, _) in &expr.following_terms {
+            match op {
+                ast::Op::Mul => self.callees.push("Math.multiply".to_string()),
+                ast::Op::Div => self.callees.push("Math.divide".to_string()),
+                _ => {}
+            }
+        }
+        visit::walk_expression(self, expr);
+    }
+
+    fn visit_term(&mut self, term: &ast::Term<'source>) {
+        if let ast::TermKind::StringConst(_) = term.kind {
+            self.callees.push("String.new".to_string());
+            self.callees.push("String.appendChar".to_string());
+        }
+        visit::walk_term(self, term);
+    }
+
+    fn visit_subroutine_call(&mut self, call: &ast::SubroutineCall<'source>) {
+        // The arguments are evaluated first. Errors are left for the code generator to report.
+        visit::walk_subroutine_call(self, call);
+        if let Some(callee) = self.resolver.callee(call) {
+            self.callees.push(callee.name);
+        }
+    }
+}
@@ -32,9 +32,9 @@ pub trait XmlWrite {
     fn write_xml<W: Write>(&self, writer: &mut EventWriter<W>) -> WriteResult;
 }
 
-impl XmlWrite for &str {
+impl<'source> XmlWrite for ast::Identifier<'source> {
     fn write_xml<W: Write>(&self, writer: &mut EventWriter<W>) -> WriteResult {
-        let chars = format!(" {} ", *self);
+        let chars = format!(" {} ", self.name);
         write_element(writer, "identifier", &chars)
     }
 }
@@ -287,9 +287,9 @@ impl<'source> XmlWrite for ast::Expression<'source> {
 
 impl<'source> XmlWrite for ast::Term<'source> {
     fn write_xml<W: Write>(&self, writer: &mut EventWriter<W>) -> WriteResult {
-        use ast::Term::*;
+        use ast::TermKind::*;
         write_start(writer, "term")?;
-        match self {
+        match &self.kind {
             IntegerConst(n) => {
                 write_element(writer, "integerConstant", &format!(" {} ", n))?;
             }
@@ -0,0 +1,134 @@
+//! A read-only AST traversal
+//!
+//! Implementors override the `visit_*` methods they're interested in and call the matching
+//! `walk_*` function to keep descending into the children.
+
+use crate::ast;
+
+pub trait Visitor<'source> {
+    fn visit_class(&mut self, class: &ast::Class<'source>) {
+        walk_class(self, class);
+    }
+
+    fn visit_subroutine_dec(&mut self, subroutine: &ast::SubroutineDec<'source>) {
+        walk_subroutine_dec(self, subroutine);
+    }
+
+    fn visit_stmts(&mut self, stmts: &ast::Stmts<'source>) {
+        walk_stmts(self, stmts);
+    }
+
+    fn visit_stmt(&mut self, stmt: &ast::Stmt<'source>) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_let_stmt(&mut self, let_stmt: &ast::LetStmt<'source>) {
+        walk_let_stmt(self, let_stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &ast::Expression<'source>) {
+        walk_expression(self, expr);
+    }
+
+    fn visit_term(&mut self, term: &ast::Term<'source>) {
+        walk_term(self, term);
+    }
+
+    fn visit_subroutine_call(&mut self, call: &ast::SubroutineCall<'source>) {
+        walk_subroutine_call(self, call);
+    }
+}
+
+pub fn walk_class<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    class: &ast::Class<'source>,
+) {
+    for subroutine in &class.subroutines {
+        visitor.visit_subroutine_dec(subroutine);
+    }
+}
+
+pub fn walk_subroutine_dec<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    subroutine: &ast::SubroutineDec<'source>,
+) {
+    visitor.visit_stmts(&subroutine.body.stmts);
+}
+
+pub fn walk_stmts<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    stmts: &ast::Stmts<'source>,
+) {
+    for stmt in &stmts.0 {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    stmt: &ast::Stmt<'source>,
+) {
+    use ast::Stmt::*;
+    match stmt {
+        Let(let_stmt) => visitor.visit_let_stmt(let_stmt),
+        If(if_stmt) => {
+            visitor.visit_expression(&if_stmt.condition);
+            visitor.visit_stmts(&if_stmt.stmts);
+            if let Some(ref stmts) = if_stmt.else_stmts {
+                visitor.visit_stmts(stmts);
+            }
+        }
+        While(while_stmt) => {
+            visitor.visit_expression(&while_stmt.condition);
+            visitor.visit_stmts(&while_stmt.stmts);
+        }
+        Do(do_stmt) => visitor.visit_subroutine_call(&do_stmt.call),
+        Return(return_stmt) => {
+            if let Some(ref expr) = return_stmt.return_val {
+                visitor.visit_expression(expr);
+            }
+        }
+    }
+}
+
+pub fn walk_let_stmt<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    let_stmt: &ast::LetStmt<'source>,
+) {
+    if let Some(ref idx) = let_stmt.idx_expr {
+        visitor.visit_expression(idx);
+    }
+    visitor.visit_expression(&let_stmt.assign_expr);
+}
+
+pub fn walk_expression<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    expr: &ast::Expression<'source>,
+) {
+    visitor.visit_term(&expr.leading_term);
+    for (_, term) in &expr.following_terms {
+        visitor.visit_term(term);
+    }
+}
+
+pub fn walk_term<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    term: &ast::Term<'source>,
+) {
+    use ast::TermKind::*;
+    match &term.kind {
+        IntegerConst(_) | StringConst(_) | KeywordConst(_) | VarRef(_) => {}
+        VarRefWithIdx(_, expr) | Expr(expr) => visitor.visit_expression(expr),
+        SubroutineCall(call) => visitor.visit_subroutine_call(call),
+        UnaryOperation(_, term) => visitor.visit_term(term),
+    }
+}
+
+pub fn walk_subroutine_call<'source, V: Visitor<'source> + ?Sized>(
+    visitor: &mut V,
+    call: &ast::SubroutineCall<'source>,
+) {
+    for arg in &call.args.0 {
+        visitor.visit_expression(arg);
+    }
+}
@@ -0,0 +1,134 @@
+//! Linking of VM files into a single program
+//!
+//! [`link`] keeps the functions that can be reached from the entry point through `call`
+//! instructions, and leaves the others out, so that a program using a few functions of the Jack
+//! OS doesn't pay for the whole of it: translated to assembly, the full OS alone doesn't fit in
+//! the ROM. Instructions before the first function of a file can't be called, and are left out
+//! too.
+
+use super::Instruction;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+/// A VM file, named without its extension, e.g. `Main`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmFile {
+    pub name: String,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// No file defines the entry point.
+    MissingEntry(String),
+    /// A function is defined twice, in the files named.
+    Duplicate {
+        function: String,
+        files: (String, String),
+    },
+    /// A reachable function calls a function that isn't defined.
+    Undefined { function: String, caller: String },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::MissingEntry(entry) => write!(f, "no function `{}` to start from", entry),
+            LinkError::Duplicate {
+                function,
+                files: (first, second),
+            } => write!(
+                f,
+                "function `{}` is defined in both {} and {}",
+                function, first, second
+            ),
+            LinkError::Undefined { function, caller } => write!(
+                f,
+                "function `{}` is called by `{}` but isn't defined",
+                function, caller
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// Links VM files, starting from the function `entry`. The files are returned in the same order,
+/// with only the functions reachable from `entry`, and the files left with none are removed.
+pub fn link(files: &[VmFile], entry: &str) -> Result<Vec<VmFile>, LinkError> {
+    // Every function is the range of instructions from its `function` instruction to the next
+    let mut functions: HashMap<&str, (usize, Range<usize>)> = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        for range in function_ranges(&file.instructions) {
+            let Instruction::Function { name, .. } = &file.instructions[range.start] else {
+                unreachable!("functions start with a `function` instruction");
+            };
+            if let Some((other, _)) = functions.insert(name, (index, range)) {
+                return Err(LinkError::Duplicate {
+                    function: name.clone(),
+                    files: (files[other].name.clone(), file.name.clone()),
+                });
+            }
+        }
+    }
+
+    if !functions.contains_key(entry) {
+        return Err(LinkError::MissingEntry(entry.to_string()));
+    }
+    let mut reachable = HashSet::from([entry]);
+    let mut pending = vec![entry];
+    while let Some(caller) = pending.pop() {
+        let (index, range) = &functions[caller];
+        for instruction in &files[*index].instructions[range.clone()] {
+            let Instruction::Call { name, .. } = instruction else {
+                continue;
+            };
+            if !functions.contains_key(name.as_str()) {
+                return Err(LinkError::Undefined {
+                    function: name.clone(),
+                    caller: caller.to_string(),
+                });
+            }
+            if reachable.insert(name.as_str()) {
+                pending.push(name);
+            }
+        }
+    }
+
+    let mut ranges: Vec<(usize, Range<usize>)> = reachable
+        .into_iter()
+        .map(|name| functions[name].clone())
+        .collect();
+    ranges.sort_by_key(|(index, range)| (*index, range.start));
+    let mut linked: Vec<VmFile> = Vec::new();
+    for (index, range) in ranges {
+        let file = &files[index];
+        let instructions = &file.instructions[range];
+        match linked.last_mut() {
+            Some(last) if last.name == file.name => {
+                last.instructions.extend_from_slice(instructions)
+            }
+            _ => linked.push(VmFile {
+                name: file.name.clone(),
+                instructions: instructions.to_vec(),
+            }),
+        }
+    }
+    Ok(linked)
+}
+
+/// Returns the range of instructions of every function, in order.
+fn function_ranges(instructions: &[Instruction]) -> Vec<Range<usize>> {
+    let starts: Vec<usize> = instructions
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| matches!(instruction, Instruction::Function { .. }))
+        .map(|(index, _)| index)
+        .collect();
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain([&instructions.len()]))
+        .map(|(&start, &end)| start..end)
+        .collect()
+}
@@ -0,0 +1,110 @@
+//! The stack-based VM language that Jack compiles to
+//!
+//! A program is a sequence of [`Instruction`]s, written out one per line in the textual `.vm`
+//! format through their `Display` implementation, and read back by [`parse`]. [`link`] puts files
+//! together into a program, and [`translate`] turns them into Hack assembly.
+
+mod link;
+mod parse;
+mod peephole;
+mod translate;
+
+pub use link::{LinkError, VmFile, link};
+pub use parse::{ParseError, parse};
+pub use peephole::{Peephole, peephole};
+pub use translate::translate;
+
+use std::fmt::{self, Display};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Constant,
+    Argument,
+    Local,
+    Static,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+impl Segment {
+    pub fn name(self) -> &'static str {
+        match self {
+            Segment::Constant => "constant",
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+impl ArithmeticOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            ArithmeticOp::Add => "add",
+            ArithmeticOp::Sub => "sub",
+            ArithmeticOp::Neg => "neg",
+            ArithmeticOp::Eq => "eq",
+            ArithmeticOp::Gt => "gt",
+            ArithmeticOp::Lt => "lt",
+            ArithmeticOp::And => "and",
+            ArithmeticOp::Or => "or",
+            ArithmeticOp::Not => "not",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arithmetic(ArithmeticOp),
+    /// Labels are scoped to the function they appear in.
+    Label(String),
+    Goto(String),
+    /// Pops the top of the stack and jumps if it isn't 0.
+    IfGoto(String),
+    Function {
+        name: String,
+        locals: u16,
+    },
+    Call {
+        name: String,
+        args: u16,
+    },
+    Return,
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Push(segment, index) => write!(f, "push {} {}", segment.name(), index),
+            Instruction::Pop(segment, index) => write!(f, "pop {} {}", segment.name(), index),
+            Instruction::Arithmetic(op) => f.write_str(op.name()),
+            Instruction::Label(label) => write!(f, "label {}", label),
+            Instruction::Goto(label) => write!(f, "goto {}", label),
+            Instruction::IfGoto(label) => write!(f, "if-goto {}", label),
+            Instruction::Function { name, locals } => write!(f, "function {} {}", name, locals),
+            Instruction::Call { name, args } => write!(f, "call {} {}", name, args),
+            Instruction::Return => f.write_str("return"),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Parsing of the textual `.vm` format
+
+use super::{ArithmeticOp, Instruction, Segment};
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line of the error, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses VM code, one instruction per line. Comments start with `//` and run to the end of the
+/// line, and blank lines are ignored.
+pub fn parse(source: &str) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find("//") {
+            Some(start) => &line[..start],
+            None => line,
+        };
+        let words: Vec<&str> = code.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let instruction = parse_instruction(&words).map_err(|message| ParseError {
+            line: index + 1,
+            message,
+        })?;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+fn parse_instruction(words: &[&str]) -> Result<Instruction, String> {
+    let command = words[0];
+    let operands = &words[1..];
+    let expected = match command {
+        "push" | "pop" | "function" | "call" => 2,
+        "label" | "goto" | "if-goto" => 1,
+        "return" => 0,
+        _ if arithmetic_op(command).is_some() => 0,
+        _ => return Err(format!("unknown command `{}`", command)),
+    };
+    if operands.len() != expected {
+        return Err(format!(
+            "`{}` takes {} operand{}, found {}",
+            command,
+            expected,
+            if expected == 1 { "" } else { "s" },
+            operands.len()
+        ));
+    }
+    Ok(match command {
+        "push" | "pop" => {
+            let segment = segment(operands[0])?;
+            let index = number(operands[1])?;
+            let max = match segment {
+                Segment::Constant => i16::MAX as u16,
+                Segment::Pointer => 1,
+                Segment::Temp => 7,
+                _ => u16::MAX,
+            };
+            if index > max {
+                return Err(format!(
+                    "index {} is out of range for segment `{}`, which goes up to {}",
+                    index,
+                    segment.name(),
+                    max
+                ));
+            }
+            if command == "push" {
+                Instruction::Push(segment, index)
+            } else if segment == Segment::Constant {
+                return Err("can't pop to segment `constant`".to_string());
+            } else {
+                Instruction::Pop(segment, index)
+            }
+        }
+        "label" => Instruction::Label(symbol(operands[0])?),
+        "goto" => Instruction::Goto(symbol(operands[0])?),
+        "if-goto" => Instruction::IfGoto(symbol(operands[0])?),
+        "function" => Instruction::Function {
+            name: symbol(operands[0])?,
+            locals: number(operands[1])?,
+        },
+        "call" => Instruction::Call {
+            name: symbol(operands[0])?,
+            args: number(operands[1])?,
+        },
+        "return" => Instruction::Return,
+        _ => Instruction::Arithmetic(arithmetic_op(command).unwrap()),
+    })
+}
+
+fn arithmetic_op(command: &str) -> Option<ArithmeticOp> {
+    Some(match command {
+        "add" => ArithmeticOp::Add,
+        "sub" => ArithmeticOp::Sub,
+        "neg" => ArithmeticOp::Neg,
+        "eq" => ArithmeticOp::Eq,
+        "gt" => ArithmeticOp::Gt,
+        "lt" => ArithmeticOp::Lt,
+        "and" => ArithmeticOp::And,
+        "or" => ArithmeticOp::Or,
+        "not" => ArithmeticOp::Not,
+        _ => return None,
+    })
+}
+
+fn segment(name: &str) -> Result<Segment, String> {
+    Ok(match name {
+        "constant" => Segment::Constant,
+        "argument" => Segment::Argument,
+        "local" => Segment::Local,
+        "static" => Segment::Static,
+        "this" => Segment::This,
+        "that" => Segment::That,
+        "pointer" => Segment::Pointer,
+        "temp" => Segment::Temp,
+        _ => return Err(format!("unknown segment `{}`", name)),
+    })
+}
+
+fn number(word: &str) -> Result<u16, String> {
+    word.parse()
+        .map_err(|_| format!("expected a non-negative number, found `{}`", word))
+}
+
+/// Checks a label or function name: letters, digits, `_`, `.`, `$` and `:`, not starting with a
+/// digit.
+fn symbol(word: &str) -> Result<String, String> {
+    let valid = !word.starts_with(|c: char| c.is_ascii_digit())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':'));
+    if valid {
+        Ok(word.to_string())
+    } else {
+        Err(format!("invalid symbol `{}`", word))
+    }
+}
@@ -0,0 +1,134 @@
+//! A peephole optimiser for VM code
+//!
+//! [`peephole`] replaces short sequences of instructions with shorter ones doing the same, and
+//! works on any VM code, generated by this compiler or not:
+//!
+//! - `push x` followed by `pop x` is removed, as are `not; not` and `push constant 0` followed by
+//!   `add`, `sub` or `or`.
+//! - `if-goto a; goto b; label a` becomes `not; if-goto b; label a`, or `if-goto b; label a` when
+//!   the condition was already negated. This is only done when the condition is the result of a
+//!   comparison, since `if-goto` jumps for any value that isn't 0, and `not` only turns -1 into 0.
+//! - A `goto` to a label following it, possibly after other labels, is removed.
+//!
+//! The rewrites are repeated until none applies, since one can make another possible.
+
+use super::{ArithmeticOp, Instruction, Segment};
+
+/// The result of [`peephole`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peephole {
+    pub instructions: Vec<Instruction>,
+    /// The index of the input instruction every instruction comes from, in increasing order.
+    pub sources: Vec<usize>,
+    /// The name of every function, in order, with the number of instructions removed from it.
+    pub removed: Vec<(String, usize)>,
+}
+
+/// Optimises VM code.
+pub fn peephole(input: &[Instruction]) -> Peephole {
+    let mut code: Vec<(Instruction, usize)> = input.iter().cloned().zip(0..).collect();
+    while let Some(rewritten) = rewrite(&code) {
+        code = rewritten;
+    }
+    let (instructions, sources): (Vec<_>, Vec<_>) = code.into_iter().unzip();
+    // Functions are never removed, so they're the same in the input and the output
+    let removed = function_sizes(input)
+        .into_iter()
+        .zip(function_sizes(&instructions))
+        .map(|((name, before), (_, after))| (name, before - after))
+        .collect();
+    Peephole {
+        instructions,
+        sources,
+        removed,
+    }
+}
+
+/// Applies every rewrite once, returning `None` if none applies.
+fn rewrite(code: &[(Instruction, usize)]) -> Option<Vec<(Instruction, usize)>> {
+    use Instruction::*;
+
+    let mut rewritten: Vec<(Instruction, usize)> = Vec::with_capacity(code.len());
+    let mut changed = false;
+    let mut index = 0;
+    while index < code.len() {
+        let removed = match &code[index..] {
+            [(Push(pushed, i), _), (Pop(popped, j), _), ..] if pushed == popped && i == j => 2,
+            [
+                (Arithmetic(ArithmeticOp::Not), _),
+                (Arithmetic(ArithmeticOp::Not), _),
+                ..,
+            ] => 2,
+            [
+                (Push(Segment::Constant, 0), _),
+                (Arithmetic(ArithmeticOp::Add | ArithmeticOp::Sub | ArithmeticOp::Or), _),
+                ..,
+            ] => 2,
+            [(Goto(target), _), rest @ ..] if jumps_to_next(target, rest) => 1,
+            [
+                (IfGoto(then), if_goto),
+                (Goto(otherwise), goto),
+                (Label(label), _),
+                ..,
+            ] if then == label && is_comparison(&rewritten) => {
+                if let Some((Arithmetic(ArithmeticOp::Not), _)) = rewritten.last() {
+                    rewritten.pop();
+                } else {
+                    rewritten.push((Arithmetic(ArithmeticOp::Not), *if_goto));
+                }
+                rewritten.push((IfGoto(otherwise.clone()), *goto));
+                2
+            }
+            _ => {
+                rewritten.push(code[index].clone());
+                index += 1;
+                continue;
+            }
+        };
+        changed = true;
+        index += removed;
+    }
+    changed.then_some(rewritten)
+}
+
+/// Returns whether the code following a `goto target` starts with `label target`, possibly
+/// after other labels.
+fn jumps_to_next(target: &str, rest: &[(Instruction, usize)]) -> bool {
+    rest.iter()
+        .map_while(|(instruction, _)| match instruction {
+            Instruction::Label(label) => Some(label),
+            _ => None,
+        })
+        .any(|label| label == target)
+}
+
+/// Returns whether the value on top of the stack at the end of the code is the result of a
+/// comparison, possibly negated, which is 0 or -1.
+fn is_comparison(code: &[(Instruction, usize)]) -> bool {
+    let last = code
+        .iter()
+        .rev()
+        .find(|(instruction, _)| *instruction != Instruction::Arithmetic(ArithmeticOp::Not));
+    matches!(
+        last,
+        Some((
+            Instruction::Arithmetic(ArithmeticOp::Eq | ArithmeticOp::Gt | ArithmeticOp::Lt),
+            _
+        ))
+    )
+}
+
+/// Returns the name of every function with its number of instructions, including the
+/// `function` instruction. Instructions before the first function aren't counted.
+fn function_sizes(instructions: &[Instruction]) -> Vec<(String, usize)> {
+    let mut sizes: Vec<(String, usize)> = Vec::new();
+    for instruction in instructions {
+        if let Instruction::Function { name, .. } = instruction {
+            sizes.push((name.clone(), 0));
+        }
+        if let Some((_, size)) = sizes.last_mut() {
+            *size += 1;
+        }
+    }
+    sizes
+}
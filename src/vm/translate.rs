@@ -0,0 +1,182 @@
+//! Translation of VM code to Hack assembly
+//!
+//! Every instruction becomes the assembly operating on the stack that the VM specification
+//! describes, and function calls and returns use the calling convention of [`crate::hack`]. The
+//! `local`, `argument`, `this` and `that` segments are addressed through `LCL`, `ARG`, `THIS` and
+//! `THAT`, `pointer` is `THIS` and `THAT` themselves, `temp` is `R5`-`R12`, and the statics of a
+//! file get the symbols `File.<index>`, which the assembler allocates from address 16.
+//!
+//! Labels are local to the function declaring them, and become `Function$label`.
+
+use super::{ArithmeticOp, Instruction, Segment};
+use crate::hack::Asm;
+
+/// The address of the `temp` segment.
+const TEMP: u16 = 5;
+
+/// Appends the Hack assembly code of the instructions of a VM file to `asm`. `file` names the
+/// file without its extension, e.g. `Main`, and prefixes the symbols of its statics.
+pub fn translate(file: &str, instructions: &[Instruction], asm: &mut Asm) {
+    let mut translator = Translator {
+        file,
+        function: file.to_string(),
+        labels: 0,
+        asm,
+    };
+    for instruction in instructions {
+        translator.instruction(instruction);
+    }
+}
+
+struct Translator<'a> {
+    file: &'a str,
+    /// The function being translated, or the file before the first one.
+    function: String,
+    /// The number of labels generated in the file so far.
+    labels: usize,
+    asm: &'a mut Asm,
+}
+
+impl Translator<'_> {
+    fn emit(&mut self, line: impl Into<String>) {
+        self.asm.emit(line);
+    }
+
+    fn label(&self, label: &str) -> String {
+        format!("{}${}", self.function, label)
+    }
+
+    fn new_label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!("{}${}.{}", self.function, kind, self.labels)
+    }
+
+    /// Pops the top of the stack into the D register.
+    fn pop_d(&mut self) {
+        self.asm.emit_all(&["@SP", "AM=M-1", "D=M"]);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Push(Segment::Constant, value) => {
+                self.emit(format!("@{}", value));
+                self.emit("D=A");
+                self.asm.push_d();
+            }
+            Instruction::Push(segment, index) => {
+                self.address(*segment, *index);
+                self.emit("D=M");
+                self.asm.push_d();
+            }
+            Instruction::Pop(
+                segment @ (Segment::Static | Segment::Pointer | Segment::Temp),
+                index,
+            )
+            | Instruction::Pop(segment, index @ (0 | 1)) => {
+                self.pop_d();
+                self.address(*segment, *index);
+                self.emit("M=D");
+            }
+            Instruction::Pop(segment, index) => {
+                // The address is computed first and kept in R13
+                self.address(*segment, *index);
+                self.asm.emit_all(&["D=A", "@R13", "M=D"]);
+                self.pop_d();
+                self.asm.emit_all(&["@R13", "A=M", "M=D"]);
+            }
+            Instruction::Arithmetic(op) => self.arithmetic(*op),
+            Instruction::Label(label) => {
+                let label = self.label(label);
+                self.asm.label(&label);
+            }
+            Instruction::Goto(label) => {
+                self.emit(format!("@{}", self.label(label)));
+                self.emit("0;JMP");
+            }
+            Instruction::IfGoto(label) => {
+                self.pop_d();
+                self.emit(format!("@{}", self.label(label)));
+                self.emit("D;JNE");
+            }
+            Instruction::Function { name, locals } => {
+                self.function = name.clone();
+                self.asm.enter(name, *locals);
+            }
+            Instruction::Call { name, args } => {
+                let label = self.new_label("ret");
+                self.asm.call(name, *args, &label);
+            }
+            Instruction::Return => {
+                self.pop_d();
+                self.asm.return_d();
+            }
+        }
+    }
+
+    /// Points A to an element of a segment other than `constant`. `D` is overwritten for the
+    /// elements of the segments that are addressed through a pointer, past the second one.
+    fn address(&mut self, segment: Segment, index: u16) {
+        let base = match segment {
+            Segment::Constant => unreachable!("constants aren't stored"),
+            Segment::Static => {
+                self.emit(format!("@{}.{}", self.file, index));
+                return;
+            }
+            Segment::Pointer => {
+                self.emit(if index == 0 { "@THIS" } else { "@THAT" });
+                return;
+            }
+            Segment::Temp => {
+                self.emit(format!("@R{}", TEMP + index));
+                return;
+            }
+            Segment::Local => "LCL",
+            Segment::Argument => "ARG",
+            Segment::This => "THIS",
+            Segment::That => "THAT",
+        };
+        match index {
+            0 => self.asm.emit_all(&[&format!("@{}", base), "A=M"]),
+            1 => self.asm.emit_all(&[&format!("@{}", base), "A=M+1"]),
+            _ => {
+                self.emit(format!("@{}", index));
+                self.emit("D=A");
+                self.emit(format!("@{}", base));
+                self.emit("A=D+M");
+            }
+        }
+    }
+
+    fn arithmetic(&mut self, op: ArithmeticOp) {
+        let unary = match op {
+            ArithmeticOp::Neg => Some("M=-M"),
+            ArithmeticOp::Not => Some("M=!M"),
+            _ => None,
+        };
+        if let Some(comp) = unary {
+            self.asm.emit_all(&["@SP", "A=M-1", comp]);
+            return;
+        }
+        // The second operand goes to D, and A to the first one, which is replaced by the result
+        self.pop_d();
+        self.emit("A=A-1");
+        let jump = match op {
+            ArithmeticOp::Add => return self.emit("M=D+M"),
+            ArithmeticOp::Sub => return self.emit("M=M-D"),
+            ArithmeticOp::And => return self.emit("M=D&M"),
+            ArithmeticOp::Or => return self.emit("M=D|M"),
+            ArithmeticOp::Eq => "D;JEQ",
+            ArithmeticOp::Gt => "D;JGT",
+            ArithmeticOp::Lt => "D;JLT",
+            ArithmeticOp::Neg | ArithmeticOp::Not => unreachable!(),
+        };
+        // Comparisons store true, then replace it with false unless the difference of the
+        // operands passes the jump
+        let end = self.new_label("cmp");
+        self.asm.emit_all(&["D=M-D", "M=-1"]);
+        self.emit(format!("@{}", end));
+        self.emit(jump);
+        self.asm.emit_all(&["@SP", "A=M-1", "M=0"]);
+        self.asm.label(&end);
+    }
+}
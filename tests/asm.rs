@@ -0,0 +1,411 @@
+mod cpu;
+mod mini_os;
+mod utils;
+
+use cpu::Cpu;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::hack::Asm;
+use jack_compiler::{ir, vm};
+use mini_os::{OS, RESULT};
+use std::fs;
+use utils::parse_class;
+
+fn compile(sources: &[&str], options: &Options) -> Vec<String> {
+    let classes: Vec<_> = OS
+        .iter()
+        .chain(sources)
+        .map(|source| parse_class(source))
+        .collect();
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    for result in ir::lower_program(&classes, options) {
+        for mut function in result.unwrap() {
+            if options.optimize {
+                ir::optimize(&mut function);
+            }
+            ir::to_asm(&function, &mut asm);
+        }
+    }
+    asm.lines
+}
+
+fn run(sources: &[&str]) -> i16 {
+    let optimized = Options {
+        optimize: true,
+        inline: true,
+        ..Options::default()
+    };
+    let mut results = Vec::new();
+    for options in [Options::default(), optimized] {
+        let mut cpu = Cpu::new(&compile(sources, &options));
+        assert!(cpu.run(1_000_000), "the program didn't halt");
+        results.push(cpu.ram[RESULT]);
+    }
+    assert_eq!(results[0], results[1]);
+    results[0]
+}
+
+#[test]
+fn test_arithmetic() {
+    let source = "class Main {
+    function int main() {
+        var int a, b, c, d, e;
+        let a = 7;
+        let b = -3;
+        let c = a + b - (a - 10);
+        let d = (a * -b) / 7;
+        let e = (a & 6) | (~b & 8);
+        return (100 * c) + (10 * d) + e + -(32767 - a) + 32767 - 7;
+    }
+}";
+    // c = 7, d = 3, e = 6 | 0 = 6
+    assert_eq!(run(&[source]), 700 + 30 + 6);
+}
+
+#[test]
+fn test_comparisons() {
+    let source = "class Main {
+    function int main() {
+        var int count, i;
+        let i = -5;
+        while (i < 5) {
+            if (i > 0) {
+                let count = count + 1;
+            }
+            if (i = 0) {
+                let count = count + 10;
+            }
+            if ((i < 0) & ~(i = -5)) {
+                let count = count + 100;
+            }
+            let i = i + 1;
+        }
+        return count;
+    }
+}";
+    assert_eq!(run(&[source]), 4 + 10 + 400);
+}
+
+#[test]
+fn test_calls() {
+    let source = "class Main {
+    static int calls;
+
+    function int main() {
+        var int a, b, c, d, e;
+        let a = Main.fib(10);
+        let b = Main.sum(1, 2, 3, 4);
+        let c = Main.zero();
+        let d = a - b;
+        let e = d;
+        return e + calls + c;
+    }
+
+    function int fib(int n) {
+        let calls = calls + 1;
+        if (n < 2) {
+            return n;
+        }
+        return Main.fib(n - 1) + Main.fib(n - 2);
+    }
+
+    function int sum(int a, int b, int c, int d) {
+        return a + b + c + d;
+    }
+
+    function int zero() {
+        return 0;
+    }
+}";
+    // fib(10) = 55, with 177 calls
+    assert_eq!(run(&[source]), 55 - 10 + 177);
+}
+
+#[test]
+fn test_objects() {
+    let point = "class Point {
+    field int x, y;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        return this;
+    }
+
+    method int getX() {
+        return x;
+    }
+
+    method void move(int dx, int dy) {
+        let x = x + dx;
+        let y = y + dy;
+        return;
+    }
+
+    method int dot(Point other) {
+        return (x * other.getX()) + (y * other.getY());
+    }
+
+    method int getY() {
+        return y;
+    }
+}";
+    let main = "class Main {
+    function int main() {
+        var Point p, q;
+        var Array a;
+        var String s;
+        var int i, sum;
+        let p = Point.new(2, 3);
+        let q = Point.new(4, 5);
+        do p.move(1, 1);
+        let a = Array.new(10);
+        while (i < 10) {
+            let a[i] = i * i;
+            let i = i + 1;
+        }
+        let i = 0;
+        while (i < 10) {
+            let sum = sum + a[a[i] - (i * (i - 1))];
+            let i = i + 1;
+        }
+        let s = \"Hack\";
+        return p.dot(q) + sum + s.length() + s.charAt(0);
+    }
+}";
+    // 3 * 4 + 4 * 5, then a[i] for i in 0..10, then 4 + 'H'
+    assert_eq!(run(&[point, main]), 32 + 285 + 4 + 72);
+}
+
+#[test]
+fn test_registers() {
+    // Temporaries of expressions without calls are kept in registers, and the only stack accesses
+    // are the ones of the function prologue and return
+    let source = "class Main {
+    function int main() {
+        return Main.f(1, 2, 3, 4);
+    }
+
+    function int f(int a, int b, int c, int d) {
+        var int x;
+        let x = (a + b) - (c + d);
+        return x - (a - b);
+    }
+}";
+    let program = parse_class(source);
+    let function = &ir::lower_class(&program, &Options::default()).unwrap()[1];
+    let mut asm = Asm::new();
+    ir::to_asm(function, &mut asm);
+    let lines = asm.lines.join("\n");
+    assert!(lines.contains("@R13"), "{}", lines);
+    assert!(!lines.contains("AM=M+1"), "{}", lines);
+    assert_eq!(lines.matches("@SP").count(), 3, "{}", lines);
+    assert_eq!(run(&[source]), -4 + 1);
+}
+
+#[test]
+fn test_many_locals() {
+    // Locals past the first few are addressed with an offset, which uses R15 when storing
+    let source = "class Main {
+    function int main() {
+        var int a, b, c, d, e, f, g, h;
+        let a = 1;
+        let h = 8;
+        let g = h - a;
+        let f = g + h;
+        let e = f - g;
+        let d = e + f + g + h;
+        return d + a;
+    }
+}";
+    assert_eq!(run(&[source]), 8 + 15 + 7 + 8 + 1);
+}
+
+#[test]
+fn test_cli() {
+    let dir = std::env::temp_dir().join(format!("jack-asm-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for source in OS {
+        let class = parse_class(source);
+        fs::write(dir.join(format!("{}.jack", class.name.name)), source).unwrap();
+    }
+    fs::write(
+        dir.join("Main.jack"),
+        "class Main {
+    function int main() {
+        return 6 * 7;
+    }
+}",
+    )
+    .unwrap();
+    let output = dir.join("Main.asm");
+    let run = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_jack-compiler"))
+            .args(["-f", "asm", "-o"])
+            .arg(&output)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    assert!(run().status.success());
+    let lines: Vec<String> = fs::read_to_string(&output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+
+    // A call to a function that isn't compiled would jump to the address of a variable
+    fs::write(
+        dir.join("Main.jack"),
+        "class Main {
+    function int main() {
+        do Output.printInt(42);
+        return 0;
+    }
+}",
+    )
+    .unwrap();
+    let result = run();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "function `Output.printInt` is called by `Main.main` but isn't defined, the Jack OS \
+             has to be compiled with the program"
+        ),
+        "{}",
+        stderr
+    );
+    assert_eq!(&lines[..4], ["@256", "D=A", "@SP", "M=D"]);
+    let mut cpu = Cpu::new(&lines);
+    assert!(cpu.run(100_000));
+    assert_eq!(cpu.ram[RESULT], 42);
+}
+
+/// Stubs of the OS classes the test programs use besides the ones of [`OS`]. `Keyboard` returns
+/// the numbers and keys the tests of the VM code use, `Output.printInt` stores its argument at
+/// `RESULT + 1` and `Screen.drawRectangle` counts its calls at `RESULT + 2`.
+const IO: [&str; 3] = [
+    "class Keyboard {
+    static int reads, presses;
+
+    function int readInt(String message) {
+        let reads = reads + 1;
+        if (reads = 1) { return 4; }
+        if (reads = 2) { return 10; }
+        if (reads = 3) { return 20; }
+        if (reads = 4) { return 33; }
+        return -3;
+    }
+
+    function char keyPressed() {
+        let presses = presses + 1;
+        if (presses = 1) { return 132; }
+        if (presses = 5) { return 81; }
+        return 0;
+    }
+}",
+    "class Output {
+    function void printString(String s) {
+        return;
+    }
+
+    function void printInt(int i) {
+        var Array ram;
+        let ram = 0;
+        let ram[8001] = i;
+        return;
+    }
+
+    function void println() {
+        return;
+    }
+}",
+    "class Screen {
+    function void setColor(boolean black) {
+        return;
+    }
+
+    function void drawRectangle(int x1, int y1, int x2, int y2) {
+        var Array ram;
+        let ram = 0;
+        let ram[8002] = ram[8002] + 1;
+        return;
+    }
+}",
+];
+
+#[test]
+fn test_compared_to_vm_translation() {
+    // The size and the run time of the assembly generated from the IR, and of the one translated
+    // from the VM code, on the test programs
+    for (program, classes) in [
+        ("ArrayTest", vec!["Main"]),
+        ("Square", vec!["Main", "Square", "SquareGame"]),
+    ] {
+        let sources: Vec<String> = classes
+            .iter()
+            .map(|class| {
+                fs::read_to_string(format!("tests/programs/{}/{}.jack", program, class)).unwrap()
+            })
+            .collect();
+        let classes: Vec<_> = OS
+            .iter()
+            .chain(&IO)
+            .copied()
+            .chain(sources.iter().map(String::as_str))
+            .map(parse_class)
+            .collect();
+        let options = Options::default();
+
+        let mut direct = Asm::new();
+        direct.bootstrap();
+        for result in ir::lower_program(&classes, &options) {
+            for function in result.unwrap() {
+                ir::to_asm(&function, &mut direct);
+            }
+        }
+        let mut translated = Asm::new();
+        translated.bootstrap();
+        for (class, result) in classes
+            .iter()
+            .zip(codegen::compile_program(&classes, &options))
+        {
+            vm::translate(
+                class.name.name,
+                &result.unwrap().instructions,
+                &mut translated,
+            );
+        }
+
+        // The average of ArrayTest, and the rectangles Square draws moving 5 times
+        let expected = if program == "ArrayTest" {
+            (15, 0)
+        } else {
+            (0, 11)
+        };
+        let run = |asm: &Asm| {
+            let mut cpu = Cpu::new(&asm.lines);
+            assert!(cpu.run(1_000_000), "{} didn't halt", program);
+            assert_eq!((cpu.ram[RESULT + 1], cpu.ram[RESULT + 2]), expected);
+            (cpu.rom.len(), cpu.steps)
+        };
+        let (direct_size, direct_steps) = run(&direct);
+        let (translated_size, translated_steps) = run(&translated);
+        assert!(
+            direct_size < translated_size,
+            "{}: {} >= {} instructions",
+            program,
+            direct_size,
+            translated_size
+        );
+        assert!(
+            direct_steps < translated_steps,
+            "{}: {} >= {} steps",
+            program,
+            direct_steps,
+            translated_steps
+        );
+    }
+}
@@ -0,0 +1,207 @@
+mod cpu;
+
+use cpu::Cpu;
+use jack_compiler::hack::{assemble, to_binary};
+use std::fs;
+
+fn error(source: &str) -> String {
+    assemble(source).unwrap_err().to_string()
+}
+
+#[test]
+fn test_instructions() {
+    // Add.asm of nand2tetris, with comments and whitespace
+    let source = "\
+// Computes R0 = 2 + 3
+@2
+D=A
+   @3   // the second operand
+D = D + A
+@0
+M=D
+";
+    let expected = "\
+0000000000000010
+1110110000010000
+0000000000000011
+1110000010010000
+0000000000000000
+1110001100001000
+";
+    assert_eq!(to_binary(&assemble(source).unwrap()), expected);
+
+    let encode = |line: &str| assemble(line).unwrap()[0];
+    assert_eq!(encode("@32767"), 0x7fff);
+    assert_eq!(encode("0;JMP"), 0b1110_1010_1000_0111);
+    assert_eq!(encode("AMD=M+1;JGT"), 0b1111_1101_1111_1001);
+    assert_eq!(encode("D;JEQ"), 0b1110_0011_0000_0010);
+    // Destinations in any order, and operands of commutative operations swapped
+    assert_eq!(encode("DM=M-1"), encode("MD=M-1"));
+    assert_eq!(encode("D=M+D"), encode("D=D+M"));
+    assert_eq!(encode("A=A&D"), encode("A=D&A"));
+    assert_eq!(encode("D=1+D"), encode("D=D+1"));
+
+    let jumps = ["JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+    for (bits, jump) in jumps.iter().enumerate() {
+        assert_eq!(encode(&format!("0;{}", jump)) & 0b111, bits as u16 + 1);
+    }
+    let dests = ["M", "D", "MD", "A", "AM", "AD", "AMD"];
+    for (bits, dest) in dests.iter().enumerate() {
+        assert_eq!(encode(&format!("{}=0", dest)) >> 3 & 0b111, bits as u16 + 1);
+    }
+}
+
+#[test]
+fn test_computations() {
+    // Every computation is run with D = 11, A = 100 and M = RAM[100] = -7
+    let (d, a, m) = (11i16, 100i16, -7i16);
+    let computations = [
+        ("0", 0),
+        ("1", 1),
+        ("-1", -1),
+        ("D", d),
+        ("A", a),
+        ("M", m),
+        ("!D", !d),
+        ("!A", !a),
+        ("!M", !m),
+        ("-D", -d),
+        ("-A", -a),
+        ("-M", -m),
+        ("D+1", d + 1),
+        ("A+1", a + 1),
+        ("M+1", m + 1),
+        ("D-1", d - 1),
+        ("A-1", a - 1),
+        ("M-1", m - 1),
+        ("D+A", d + a),
+        ("D+M", d + m),
+        ("D-A", d - a),
+        ("D-M", d - m),
+        ("A-D", a - d),
+        ("M-D", m - d),
+        ("D&A", d & a),
+        ("D&M", d & m),
+        ("D|A", d | a),
+        ("D|M", d | m),
+    ];
+    for (comp, expected) in computations {
+        let source = format!("@{}\nD=A\n@{}\nD={}\n@200\nM=D", d, a, comp);
+        let mut cpu = Cpu::with_code(assemble(&source).unwrap());
+        cpu.ram[a as usize] = m;
+        assert!(cpu.run(100));
+        assert_eq!(cpu.ram[200], expected, "{}", comp);
+    }
+}
+
+#[test]
+fn test_symbols() {
+    let source = "\
+@R2
+D=M
+@i
+M=D
+(LOOP)
+@i
+D=M
+@END
+D;JLE
+@sum
+M=M+D
+@i
+M=M-1
+@LOOP
+0;JMP
+(END)
+@sum
+D=M
+@R0
+M=D
+@SCREEN
+D=A
+@KBD
+D=D-A
+@THAT
+M=D
+(HALT)
+@HALT
+0;JMP
+";
+    let code = assemble(source).unwrap();
+    // Variables are allocated from 16, and labels name the next instruction
+    assert_eq!(code[0], 2);
+    assert_eq!(code[2], 16);
+    assert_eq!(code[6], 14);
+    assert_eq!(code[8], 17);
+    assert_eq!(code[12], 4);
+    assert_eq!(code[24], 24);
+
+    let mut cpu = Cpu::with_code(code);
+    cpu.ram[2] = 10;
+    assert!(cpu.run(1000));
+    assert_eq!(cpu.ram[0], 55);
+    assert_eq!(cpu.ram[4], 16384 - 24576);
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(error("@1\nD=X"), "line 2: invalid computation `X`");
+    assert_eq!(error("D=A+M"), "line 1: invalid computation `A+M`");
+    assert_eq!(error("DD=A"), "line 1: invalid destination `DD`");
+    assert_eq!(error("X=A"), "line 1: invalid destination `X`");
+    assert_eq!(error("\n\n0;JUMP"), "line 3: invalid jump `JUMP`");
+    assert_eq!(
+        error("@32768"),
+        "line 1: constant 32768 is out of range, the maximum is 32767"
+    );
+    assert_eq!(error("@-1"), "line 1: invalid symbol `-1`");
+    assert_eq!(error("@1abc"), "line 1: invalid symbol `1abc`");
+    assert_eq!(error("(A)\n(A)"), "line 2: label `A` is declared twice");
+    assert_eq!(error("(R15)"), "line 1: `R15` is a predefined symbol");
+    assert_eq!(error("(LOOP"), "line 1: expected `)` after label `LOOP`");
+    // Not predefined, so a label
+    assert!(assemble("(R16)\n(R01)").is_ok());
+
+    let variables: String = (0..16369).map(|i| format!("@v{}\n", i)).collect();
+    assert_eq!(
+        error(&variables),
+        "line 16369: no memory left for variable `v16368`, variables go up to 16383"
+    );
+    assert_eq!(
+        error(&"D=0\n".repeat(32769)),
+        "line 32769: the program doesn't fit in the 32768 words of the ROM"
+    );
+}
+
+#[test]
+fn test_cli() {
+    let dir = std::env::temp_dir().join(format!("jack-assemble-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("Add.asm");
+    let output = dir.join("Add.hack");
+    let run = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_jack-compiler"))
+            .arg("assemble")
+            .arg("-o")
+            .arg(&output)
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+    fs::write(&input, "@2\nD=A\n@3\nD=D+A\n@0\nM=D\n").unwrap();
+    assert!(run().status.success());
+    let hack = fs::read_to_string(&output).unwrap();
+    assert_eq!(hack.lines().count(), 6);
+    assert_eq!(hack.lines().nth(1), Some("1110110000010000"));
+
+    fs::write(&input, "@2\nD=Q\n").unwrap();
+    let result = run();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("Add.asm: line 2: invalid computation `Q`"),
+        "{}",
+        stderr
+    );
+}
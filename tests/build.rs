@@ -0,0 +1,214 @@
+mod cpu;
+mod mini_os;
+mod utils;
+
+use cpu::Cpu;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::vm::{self, LinkError, VmFile, link};
+use mini_os::{OS, RESULT};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use utils::parse_class;
+
+fn vm_file(name: &str, source: &str) -> VmFile {
+    VmFile {
+        name: name.to_string(),
+        instructions: vm::parse(source).unwrap(),
+    }
+}
+
+fn functions(files: &[VmFile]) -> Vec<String> {
+    files
+        .iter()
+        .flat_map(|file| &file.instructions)
+        .filter_map(|instruction| match instruction {
+            vm::Instruction::Function { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_link() {
+    let sys = vm_file(
+        "Sys",
+        "function Sys.init 0\ncall Main.main 0\ncall Sys.halt 0\nfunction Sys.halt 0\nlabel END\ngoto END\nfunction Sys.wait 0\npush constant 0\nreturn\n",
+    );
+    let main = vm_file(
+        "Main",
+        "push constant 1\nfunction Main.main 0\ncall Main.f 0\nreturn\nfunction Main.unused 0\ncall Main.g 0\nreturn\nfunction Main.f 0\ncall Main.f 0\nreturn\n",
+    );
+    let math = vm_file(
+        "Math",
+        "function Math.multiply 0\npush constant 0\nreturn\n",
+    );
+    let files = [main, math, sys];
+
+    // Only the reachable functions are kept, in the order of the files, and `Math` is left out
+    let linked = link(&files, "Sys.init").unwrap();
+    assert_eq!(
+        functions(&linked),
+        ["Main.main", "Main.f", "Sys.init", "Sys.halt"]
+    );
+    assert_eq!(linked[0].name, "Main");
+    assert_eq!(linked[0].instructions.len(), 6);
+    assert_eq!(linked[1].name, "Sys");
+
+    assert_eq!(
+        link(&files, "Main.start").unwrap_err().to_string(),
+        "no function `Main.start` to start from"
+    );
+    assert_eq!(
+        link(&files, "Main.unused").unwrap_err(),
+        LinkError::Undefined {
+            function: "Main.g".to_string(),
+            caller: "Main.unused".to_string(),
+        }
+    );
+    let other = vm_file(
+        "Other",
+        "function Math.multiply 0\npush constant 1\nreturn\n",
+    );
+    assert_eq!(
+        link(&[files[1].clone(), other], "Math.multiply")
+            .unwrap_err()
+            .to_string(),
+        "function `Math.multiply` is defined in both Math and Other"
+    );
+}
+
+#[test]
+fn test_cli() {
+    let dir = std::env::temp_dir().join(format!("jack-build-{}", std::process::id()));
+    let program = dir.join("Program");
+    let os = dir.join("os");
+    fs::create_dir_all(&program).unwrap();
+    fs::create_dir_all(&os).unwrap();
+    let classes: Vec<_> = OS.iter().map(|source| parse_class(source)).collect();
+    for (class, result) in classes
+        .iter()
+        .zip(codegen::compile_program(&classes, &Options::default()))
+    {
+        let vm_code: String = result
+            .unwrap()
+            .instructions
+            .iter()
+            .map(|instruction| format!("{}\n", instruction))
+            .collect();
+        fs::write(os.join(format!("{}.vm", class.name.name)), vm_code).unwrap();
+    }
+    fs::write(
+        program.join("Main.jack"),
+        "class Main {
+    function int main() {
+        var String s;
+        let s = \"ab\";
+        return (6 * 7) + s.length();
+    }
+}",
+    )
+    .unwrap();
+    let run = |args: &[&str]| -> Output {
+        Command::new(env!("CARGO_BIN_EXE_jack-compiler"))
+            .arg("build")
+            .args(args)
+            .arg(&program)
+            .output()
+            .unwrap()
+    };
+    let run_hack = |path: &Path| {
+        let code = fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| u16::from_str_radix(line, 2).unwrap())
+            .collect();
+        let mut cpu = Cpu::with_code(code);
+        assert!(cpu.run(1_000_000));
+        cpu.ram[RESULT]
+    };
+
+    // Only the machine code is written by default, in the `build` directory of the input
+    let result = run(&["--os", os.to_str().unwrap()]);
+    assert!(result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.starts_with("Program: "), "{}", stderr);
+    let build = program.join("build");
+    assert_eq!(fs::read_dir(&build).unwrap().count(), 1);
+    assert_eq!(run_hack(&build.join("Program.hack")), 44);
+
+    let output = dir.join("out");
+    let result = run(&[
+        "--os",
+        os.to_str().unwrap(),
+        "--emit",
+        "vm,asm,hack",
+        "-O",
+        "-o",
+        output.to_str().unwrap(),
+    ]);
+    assert!(result.status.success());
+    let main_vm = fs::read_to_string(output.join("Main.vm")).unwrap();
+    assert!(main_vm.starts_with("function Main.main 1\n"), "{}", main_vm);
+    // The OS files aren't written, and the functions that aren't called are left out
+    assert!(!output.join("Math.vm").exists());
+    let asm = fs::read_to_string(output.join("Program.asm")).unwrap();
+    assert!(asm.contains("(String.appendChar)\n"));
+    assert!(!asm.contains("(String.charAt)\n"));
+    assert!(!asm.contains("(Math.divide)\n"));
+    assert_eq!(run_hack(&output.join("Program.hack")), 44);
+
+    // The VM code alone doesn't need the OS
+    let vm_output = dir.join("vm");
+    let result = run(&["--emit", "vm", "-o", vm_output.to_str().unwrap()]);
+    assert!(result.status.success());
+    assert_eq!(fs::read_dir(&vm_output).unwrap().count(), 1);
+    assert!(vm_output.join("Main.vm").exists());
+
+    // Without the OS, nothing calls `Main.main`
+    let result = run(&[]);
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(!result.status.success());
+    assert!(
+        stderr.contains(
+            "no function `Sys.init` to start from, the Jack OS can be linked with `--os`"
+        ),
+        "{}",
+        stderr
+    );
+
+    // A program too big for the ROM is reported with the line of the assembly
+    let calls = "do Main.main();\n".repeat(1000);
+    fs::write(
+        program.join("Main.jack"),
+        format!(
+            "class Main {{ function int main() {{ {} return 0; }} }}",
+            calls
+        ),
+    )
+    .unwrap();
+    let result = run(&["--os", os.to_str().unwrap(), "-o", output.to_str().unwrap()]);
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(!result.status.success());
+    assert!(
+        stderr.contains("Program.asm: line ")
+            && stderr.contains(": the program doesn't fit in the 32768 words of the ROM"),
+        "{}",
+        stderr
+    );
+
+    fs::write(
+        program.join("Main.jack"),
+        "class Main {
+    function int main() {
+        return x;
+    }
+}",
+    )
+    .unwrap();
+    let result = run(&["--os", os.to_str().unwrap(), "-o", output.to_str().unwrap()]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(result.status.code(), Some(1));
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains("`x`"), "{}", stderr);
+}
@@ -0,0 +1,593 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::codegen::{self, Compat, Options};
+use jack_compiler::vm::Instruction;
+use std::fs;
+use utils::parse_class;
+
+fn compile(source: &str, options: &Options) -> String {
+    let class = parse_class(source);
+    let code = codegen::compile_class(&class, options)
+        .unwrap_or_else(|e| panic!("error occurs while compiling: {:?}", e));
+    let mut out = Vec::new();
+    code.write(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Compiles every class of a program in `tests/programs`.
+fn compile_program(program_name: &str) -> Vec<Vec<Instruction>> {
+    let mut paths: Vec<_> = fs::read_dir(format!("tests/programs/{}", program_name))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let source = fs::read_to_string(path).unwrap();
+            codegen::compile_class(&parse_class(&source), &Options::default())
+                .unwrap_or_else(|e| panic!("error occurs while compiling {:?}: {:?}", path, e))
+                .instructions
+        })
+        .collect()
+}
+
+fn compile_classes(sources: &[&str]) -> Vec<Vec<Instruction>> {
+    sources
+        .iter()
+        .map(|source| {
+            let class = parse_class(source);
+            codegen::compile_class(&class, &Options::default())
+                .unwrap()
+                .instructions
+        })
+        .collect()
+}
+
+fn official() -> Options {
+    Options {
+        compat: Some(Compat::Official),
+        ..Options::default()
+    }
+}
+
+#[test]
+fn test_statements() {
+    let source = "class Main {
+    function int f(int n) {
+        var int i;
+        let i = 0;
+        while (i < n) {
+            if (i = 2) { return -i; } else { let i = i + 1; }
+        }
+        return ~(i * 2);
+    }
+}";
+    let expected = "\
+function Main.f 1
+push constant 0
+pop local 0
+label b1
+push local 0
+push argument 0
+lt
+not
+if-goto b6
+push local 0
+push constant 2
+eq
+not
+if-goto b4
+push local 0
+neg
+return
+label b4
+push local 0
+push constant 1
+add
+pop local 0
+goto b1
+label b6
+push local 0
+push constant 2
+call Math.multiply 2
+not
+return
+";
+    assert_eq!(compile(source, &Options::default()), expected);
+}
+
+#[test]
+fn test_constants() {
+    let source = "class Main {
+    function void f() {
+        do Main.g(true, false, null, 32767, 40000, 32768);
+        return;
+    }
+}";
+    let expected = "\
+function Main.f 0
+push constant 0
+not
+push constant 0
+push constant 0
+push constant 32767
+push constant 25536
+neg
+push constant 32767
+not
+call Main.g 6
+pop temp 0
+push constant 0
+return
+";
+    assert_eq!(compile(source, &Options::default()), expected);
+}
+
+#[test]
+fn test_string_constants() {
+    let source = r#"class Main {
+    function void f() {
+        do Output.printString("A b~");
+        do Output.printString("");
+        return;
+    }
+}"#;
+    let expected = "\
+function Main.f 0
+push constant 4
+call String.new 1
+push constant 65
+call String.appendChar 2
+push constant 32
+call String.appendChar 2
+push constant 98
+call String.appendChar 2
+push constant 126
+call String.appendChar 2
+call Output.printString 1
+pop temp 0
+push constant 0
+call String.new 1
+call Output.printString 1
+pop temp 0
+push constant 0
+return
+";
+    assert_eq!(compile(source, &Options::default()), expected);
+}
+
+#[test]
+fn test_pooled_strings() {
+    let source = r#"class Main {
+    static int count;
+
+    function void f() {
+        do Output.printString("ab");
+        do Output.printString("c");
+        do Output.printString("ab");
+        return;
+    }
+}"#;
+    let options = Options {
+        pool_strings: true,
+        ..Options::default()
+    };
+    let code = compile(source, &options);
+    // Each distinct constant gets a static variable after `count`
+    let pooled_ab = "\
+push static 1
+if-goto b2
+push constant 2
+call String.new 1
+push constant 97
+call String.appendChar 2
+push constant 98
+call String.appendChar 2
+pop static 1
+label b2
+push static 1
+call Output.printString 1
+";
+    assert!(code.contains(pooled_ab), "{}", code);
+    assert!(code.contains("push static 2\nif-goto b4\n"));
+    assert!(code.contains("push static 1\nif-goto b6\n"));
+    assert_eq!(code.matches("call String.new 1").count(), 3);
+}
+
+#[test]
+fn test_errors() {
+    let source = "class Main {
+    function void f() {
+        let y = \"caf\u{e9}\";
+        return;
+    }
+}";
+    let class = parse_class(source);
+    let errors = codegen::compile_class(&class, &Options::default()).unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0].message,
+        "character 'é' isn't in the Hack character set"
+    );
+    assert_eq!(errors[0].span.start, source.find('é').unwrap());
+    assert_eq!(errors[1].message, "cannot find variable `y` in this scope");
+
+    let source = "class Main {
+    field int x;
+
+    function int f() {
+        return x;
+    }
+}";
+    let class = parse_class(source);
+    let errors = codegen::compile_class(&class, &Options::default()).unwrap_err();
+    assert_eq!(errors[0].message, "cannot use field `x` in function `f`");
+}
+
+#[test]
+fn test_array_access() {
+    let source = "class Main {
+    function void f(Array a, Array b) {
+        let a[1] = b[2];
+        return;
+    }
+}";
+    let expected = "\
+function Main.f 0
+push argument 0
+push constant 1
+add
+push argument 1
+push constant 2
+add
+pop pointer 1
+push that 0
+pop temp 0
+pop pointer 1
+push temp 0
+pop that 0
+push constant 0
+return
+";
+    assert_eq!(compile(source, &Options::default()), expected);
+}
+
+#[test]
+fn test_nested_array_access() {
+    let source = "class Main {
+    function int main() {
+        var Array a, b;
+        var int i;
+        let a = Array.new(4);
+        let b = Array.new(4);
+        let i = 0;
+        while (i < 4) {
+            let b[i] = i * 10;
+            let i = i + 1;
+        }
+        let a[b[1] / 10] = b[b[3] / 10];
+        let a[a[1] / 10] = 7;
+        return a[1] + a[3];
+    }
+}";
+    let mut emulator = Emulator::new(&compile_classes(&[source]));
+    assert_eq!(emulator.run("Main.main", 10_000), 37);
+}
+
+#[test]
+fn test_array_test_program() {
+    let mut emulator = Emulator::new(&compile_program("ArrayTest"));
+    emulator.input.extend([4, 10, 20, 33, -3]);
+    emulator.run("Main.main", 100_000);
+    assert_eq!(
+        emulator.output,
+        "\
+HOW MANY NUMBERS? 4
+ENTER THE NEXT NUMBER: 10
+ENTER THE NEXT NUMBER: 20
+ENTER THE NEXT NUMBER: 33
+ENTER THE NEXT NUMBER: -3
+THE AVERAGE IS: 15
+"
+    );
+}
+
+#[test]
+fn test_square_program() {
+    let mut emulator = Emulator::new(&compile_program("Square"));
+    emulator.keys.extend([132, 0, 0, 0, 81]);
+    emulator.run("Main.main", 1_000_000);
+
+    // The square is drawn, then moves right by 2 pixels on every `moveSquare` call after the
+    // right arrow is pressed
+    let mut expected = vec![
+        "setColor(-1)".to_string(),
+        "drawRectangle(0, 0, 30, 30)".to_string(),
+    ];
+    for x in (0..10).step_by(2) {
+        expected.push("setColor(0)".to_string());
+        expected.push(format!("drawRectangle({}, 0, {}, 30)", x, x + 1));
+        expected.push("setColor(-1)".to_string());
+        expected.push(format!("drawRectangle({}, 0, {}, 30)", x + 31, x + 32));
+    }
+    assert_eq!(emulator.screen, expected);
+}
+
+#[test]
+fn test_objects() {
+    let source = "class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax) {
+        let x = ax;
+        let count = count + 1;
+        return this;
+    }
+
+    method int getX() {
+        return x;
+    }
+
+    method int sum(Point other) {
+        return getX() + other.getX() + Point.zero();
+    }
+
+    function int zero() {
+        return 0;
+    }
+}";
+    let expected = "\
+function Point.new 0
+push constant 2
+call Memory.alloc 1
+pop pointer 0
+push argument 0
+pop this 0
+push static 0
+push constant 1
+add
+pop static 0
+push pointer 0
+return
+function Point.getX 0
+push argument 0
+pop pointer 0
+push this 0
+return
+function Point.sum 0
+push argument 0
+pop pointer 0
+push pointer 0
+call Point.getX 1
+push argument 1
+call Point.getX 1
+add
+call Point.zero 0
+add
+return
+function Point.zero 0
+push constant 0
+return
+";
+    assert_eq!(compile(source, &Options::default()), expected);
+
+    let main = "class Main {
+    function int main() {
+        var Point a, b;
+        let a = Point.new(3);
+        let b = Point.new(4);
+        return a.sum(b);
+    }
+}";
+    let mut emulator = Emulator::new(&compile_classes(&[main, source]));
+    assert_eq!(emulator.run("Main.main", 10_000), 7);
+}
+
+#[test]
+fn test_method_call_errors() {
+    let source = "class Main {
+    function void main() {
+        var int n;
+        do n.foo();
+        do run();
+        return;
+    }
+
+    method void run() {
+        return;
+    }
+}";
+    let class = parse_class(source);
+    let errors = codegen::compile_class(&class, &Options::default()).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "`n` is of type `int`, which has no methods",
+            "cannot use method `run` in function `main`",
+        ]
+    );
+}
+
+#[test]
+fn test_official_compat() {
+    let source = "class Main {
+    function void f(Array a, int n) {
+        while (n > 0) {
+            if (a[n] = 0) {
+                let n = n - 1;
+            } else {
+                if (n < 2) { return; }
+                let a[n] = n;
+            }
+        }
+        while (false) {}
+        if (true) {}
+        return;
+    }
+
+    function void g() {
+        if (false) {}
+        return;
+    }
+}";
+    let expected = "\
+function Main.f 0
+label WHILE_EXP0
+push argument 1
+push constant 0
+gt
+not
+if-goto WHILE_END0
+push argument 1
+push argument 0
+add
+pop pointer 1
+push that 0
+push constant 0
+eq
+if-goto IF_TRUE0
+goto IF_FALSE0
+label IF_TRUE0
+push argument 1
+push constant 1
+sub
+pop argument 1
+goto IF_END0
+label IF_FALSE0
+push argument 1
+push constant 2
+lt
+if-goto IF_TRUE1
+goto IF_FALSE1
+label IF_TRUE1
+push constant 0
+return
+label IF_FALSE1
+push argument 1
+push argument 0
+add
+push argument 1
+pop temp 0
+pop pointer 1
+push temp 0
+pop that 0
+label IF_END0
+goto WHILE_EXP0
+label WHILE_END0
+label WHILE_EXP1
+push constant 0
+not
+if-goto WHILE_END1
+goto WHILE_EXP1
+label WHILE_END1
+push constant 0
+not
+if-goto IF_TRUE2
+goto IF_FALSE2
+label IF_TRUE2
+label IF_FALSE2
+push constant 0
+return
+function Main.g 0
+push constant 0
+if-goto IF_TRUE0
+goto IF_FALSE0
+label IF_TRUE0
+label IF_FALSE0
+push constant 0
+return
+";
+    assert_eq!(compile(source, &official()), expected);
+}
+
+#[test]
+fn test_official_compat_runs() {
+    let classes: Vec<Vec<Instruction>> = ["Main", "Square", "SquareGame"]
+        .iter()
+        .map(|class| {
+            let path = format!("tests/programs/Square/{}.jack", class);
+            let source = fs::read_to_string(path).unwrap();
+            codegen::compile_class(&parse_class(&source), &official())
+                .unwrap()
+                .instructions
+        })
+        .collect();
+    let mut emulator = Emulator::new(&classes);
+    emulator.keys.extend([132, 0, 0, 0, 81]);
+    emulator.run("Main.main", 1_000_000);
+    assert_eq!(
+        emulator.screen.last().unwrap(),
+        "drawRectangle(39, 0, 40, 30)"
+    );
+}
+
+#[test]
+fn test_annotations() {
+    let source = "class Main {
+    function void main() {
+        var int x;
+        let x = 1;
+        while (x < 3) {
+            let x = x
+                + 1;
+        }
+        return;
+    }
+}";
+    let class = parse_class(source);
+    let code = codegen::compile_class(&class, &Options::default()).unwrap();
+    let mut out = Vec::new();
+    code.write_annotated(&mut out, "Main.jack", source).unwrap();
+    let expected = "\
+function Main.main 1
+// Main.jack:4: let x = 1;
+push constant 1
+pop local 0
+// Main.jack:5: while (x < 3) {
+label b1
+push local 0
+push constant 3
+lt
+not
+if-goto b3
+// Main.jack:6: let x = x
+push local 0
+push constant 1
+add
+pop local 0
+goto b1
+// Main.jack:9: return;
+label b3
+push constant 0
+return
+";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    // Optimised code is annotated too, and only differs from the plain code by the comments
+    let options = Options {
+        optimize: true,
+        ..Options::default()
+    };
+    let code = codegen::compile_class(&class, &options).unwrap();
+    let mut out = Vec::new();
+    code.write_annotated(&mut out, "Main.jack", source).unwrap();
+    let annotated = String::from_utf8(out).unwrap();
+    assert_eq!(
+        annotated.matches("// Main.jack:").count(),
+        4,
+        "{}",
+        annotated
+    );
+    let uncommented: String = annotated
+        .lines()
+        .filter(|line| !line.starts_with("//"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    assert_eq!(uncommented, compile(source, &options));
+}
@@ -0,0 +1,96 @@
+mod utils;
+
+use jack_compiler::analysis::constant;
+use jack_compiler::const_eval::{self, Evaluator, IssueKind};
+use jack_compiler::diagnostics::Diagnostic;
+use utils::parse_class;
+
+/// Parses `expr` as the return value of a function and evaluates it.
+fn eval(expr: &str) -> (Option<i16>, Vec<IssueKind>) {
+    let source = format!("class Main {{ function int f() {{ return {}; }} }}", expr);
+    let class = parse_class(&source);
+    let jack_compiler::ast::Stmt::Return(ref stmt) = class.subroutines[0].body.stmts.0[0] else {
+        unreachable!()
+    };
+    let mut evaluator = Evaluator::new();
+    let value = evaluator.expression(stmt.return_val.as_ref().unwrap());
+    let issues = evaluator
+        .issues
+        .into_iter()
+        .map(|issue| issue.kind)
+        .collect();
+    (value, issues)
+}
+
+fn check(body: &str) -> Vec<Diagnostic> {
+    let source = format!("class Main {{ function void f() {{ {} return; }} }}", body);
+    let class = parse_class(&source);
+    let mut diagnostics = Vec::new();
+    constant::check_class(&class, &mut diagnostics);
+    diagnostics
+}
+
+#[test]
+fn test_left_to_right() {
+    assert_eq!(eval("1 + 2 * 3"), (Some(9), vec![]));
+    assert_eq!(eval("1 + (2 * 3)"), (Some(7), vec![]));
+}
+
+#[test]
+fn test_values() {
+    assert_eq!(eval("~0").0, Some(-1));
+    assert_eq!(eval("-7 / 2").0, Some(-3));
+    assert_eq!(eval("6 & 3 | 8").0, Some(10));
+    assert_eq!(eval("1 < 2").0, Some(const_eval::TRUE));
+    assert_eq!(eval("2 = 3").0, Some(const_eval::FALSE));
+    assert_eq!(eval("true & ~false").0, Some(-1));
+    assert_eq!(eval("x + 1").0, None);
+    assert_eq!(eval("this").0, None);
+}
+
+#[test]
+fn test_overflow() {
+    let (value, issues) = eval("32767 + 1");
+    assert_eq!(value, Some(-32768));
+    assert_eq!(
+        issues,
+        [IssueKind::Overflow {
+            expr: "32767 + 1".to_string(),
+            exact: 32768,
+            wrapped: -32768
+        }]
+    );
+    assert_eq!(eval("200 * 200").1.len(), 1);
+    assert_eq!(eval("-(-32767 - 1)").1.len(), 1);
+    assert_eq!(eval("40000").1, [IssueKind::LiteralOutOfRange(40000)]);
+}
+
+#[test]
+fn test_issues_in_non_constant_expressions() {
+    assert_eq!(eval("x / 0").1, [IssueKind::DivisionByZero]);
+    assert_eq!(eval("g(32767 + 1) + a[1 / 0]").1.len(), 2);
+}
+
+#[test]
+fn test_diagnostics() {
+    let diagnostics = check("let x = 1 / 0; let y = x + (30000 + 30000);");
+    let codes: Vec<_> = diagnostics.iter().map(|d| d.code.unwrap()).collect();
+    assert_eq!(
+        codes,
+        [constant::DIVISION_BY_ZERO, constant::CONSTANT_OVERFLOW]
+    );
+}
+
+#[test]
+fn test_constant_conditions() {
+    let diagnostics =
+        check("if (1 < 2) { } while ((2 + 2) = 5) { } while (true) { } if (x = 1) { }");
+    let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "this `if` condition is always true",
+            "this `while` condition is always false"
+        ]
+    );
+}
@@ -0,0 +1,72 @@
+mod utils;
+
+use jack_compiler::analysis::control_flow;
+use jack_compiler::diagnostics::{Diagnostic, Severity};
+use std::fs;
+use utils::parse_class;
+
+fn check(source: &str) -> Vec<Diagnostic> {
+    let class = parse_class(source);
+    let mut diagnostics = Vec::new();
+    control_flow::check_class(&class, &mut diagnostics);
+    diagnostics
+}
+
+#[test]
+fn test_missing_return() {
+    let diagnostics = check("class Main { function int f() { let x = 1; } }");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert!(diagnostics[0].message.contains("`f`"));
+}
+
+#[test]
+fn test_if_without_else_does_not_return() {
+    let diagnostics = check("class Main { function int f() { if (x) { return 1; } } }");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+}
+
+#[test]
+fn test_if_else_returns() {
+    let source = "class Main { function int f() { if (x) { return 1; } else { return 2; } } }";
+    assert!(check(source).is_empty());
+}
+
+#[test]
+fn test_while_is_conservative() {
+    let diagnostics = check("class Main { function int f() { while (true) { return 1; } } }");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+}
+
+#[test]
+fn test_unreachable_statements() {
+    let source = "class Main { function void f() { return; let x = 1; do g(); } }";
+    let diagnostics = check(source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    let span = diagnostics[0].span;
+    assert_eq!(&source[span.start..span.end], "let x = 1; do g();");
+}
+
+#[test]
+fn test_unreachable_after_if_else() {
+    let source = "class Main { function void f() { if (x) { return; } else { return; } return; } }";
+    let diagnostics = check(source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+}
+
+#[test]
+fn test_programs() {
+    for program in ["ArrayTest", "ExpressionLessSquare", "Square"] {
+        for entry in fs::read_dir(format!("tests/programs/{}", program)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "jack") {
+                let source = fs::read_to_string(&path).unwrap();
+                assert!(check(&source).is_empty(), "{}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+#![allow(dead_code)]
+
+//! A Hack CPU emulator running machine code in tests
+//!
+//! A program halts when it runs past its last instruction, or when it loops forever, which is
+//! detected when a jump is taken again with the same registers and memory. The memory is compared
+//! through a fingerprint updated on every write.
+
+use jack_compiler::hack;
+use std::collections::HashMap;
+
+const RAM_SIZE: usize = 32768;
+
+pub struct Cpu {
+    pub ram: Vec<i16>,
+    pub rom: Vec<u16>,
+    pub pc: usize,
+    pub a: i16,
+    pub d: i16,
+    /// The number of instructions executed so far.
+    pub steps: usize,
+    /// The sum of a hash of every address and its value, 0 when the memory is cleared.
+    fingerprint: u64,
+    /// The registers and memory fingerprint at the last jump taken from every instruction.
+    jumps: HashMap<usize, (i16, i16, u64)>,
+}
+
+impl Cpu {
+    /// Assembles a program, given as lines of assembly, and loads it.
+    pub fn new(lines: &[String]) -> Self {
+        let code = hack::assemble(&lines.join("\n")).unwrap_or_else(|e| panic!("{}", e));
+        Self::with_code(code)
+    }
+
+    /// Loads machine code.
+    pub fn with_code(rom: Vec<u16>) -> Self {
+        Self {
+            ram: vec![0; RAM_SIZE],
+            rom,
+            pc: 0,
+            a: 0,
+            d: 0,
+            steps: 0,
+            fingerprint: 0,
+            jumps: HashMap::new(),
+        }
+    }
+
+    /// Runs the program until it halts, and returns whether it did within `max_steps`.
+    pub fn run(&mut self, max_steps: usize) -> bool {
+        while self.steps < max_steps {
+            if !self.step() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Executes an instruction, and returns false if the program halted.
+    fn step(&mut self) -> bool {
+        if self.pc >= self.rom.len() {
+            return false;
+        }
+        self.steps += 1;
+        let pc = self.pc;
+        let instruction = self.rom[pc];
+        self.pc += 1;
+        if instruction & 0x8000 == 0 {
+            self.a = instruction as i16;
+            return true;
+        }
+
+        let address = self.a as u16 as usize;
+        let y = if instruction & 0x1000 != 0 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let value = alu(self.d, y, (instruction >> 6) & 0b111111);
+        if instruction & 0b001_000 != 0 {
+            self.fingerprint = self
+                .fingerprint
+                .wrapping_sub(hash(address, self.ram[address]))
+                .wrapping_add(hash(address, value));
+            self.ram[address] = value;
+        }
+        if instruction & 0b010_000 != 0 {
+            self.d = value;
+        }
+        if instruction & 0b100_000 != 0 {
+            self.a = value;
+        }
+        let jump = instruction & 0b111;
+        let jumps = (jump & 0b100 != 0 && value < 0)
+            || (jump & 0b010 != 0 && value == 0)
+            || (jump & 0b001 != 0 && value > 0);
+        if jumps {
+            let state = (self.a, self.d, self.fingerprint);
+            if self.jumps.insert(pc, state) == Some(state) {
+                return false;
+            }
+            self.pc = address;
+        }
+        true
+    }
+}
+
+/// Computes the output of the ALU for the `c` bits of an instruction: `zx`, `nx`, `zy`, `ny`, `f`
+/// and `no`, from the highest to the lowest.
+fn alu(x: i16, y: i16, control: u16) -> i16 {
+    let bit = |i: u16| control & (1 << (5 - i)) != 0;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) { !out } else { out }
+}
+
+fn hash(address: usize, value: i16) -> u64 {
+    (((address as u64) << 16) | value as u16 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
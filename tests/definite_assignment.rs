@@ -0,0 +1,75 @@
+mod utils;
+
+use jack_compiler::analysis::definite_assignment;
+use jack_compiler::diagnostics::Diagnostic;
+use utils::parse_class;
+
+fn check(body: &str) -> (String, Vec<Diagnostic>) {
+    let source = format!(
+        "class Main {{ function void f(int p) {{ var int x, y; {} return; }} }}",
+        body
+    );
+    let class = parse_class(&source);
+    let mut diagnostics = Vec::new();
+    definite_assignment::check_class(&class, &mut diagnostics);
+    (source, diagnostics)
+}
+
+#[test]
+fn test_read_before_assignment() {
+    let (source, diagnostics) = check("let y = x + p; let x = 1;");
+    assert_eq!(diagnostics.len(), 1);
+    let use_span = diagnostics[0].span;
+    let decl_span = diagnostics[0].labels[0].span;
+    assert_eq!(&source[use_span.start..use_span.end], "x");
+    assert!(decl_span.start < use_span.start);
+    assert_eq!(&source[decl_span.start..decl_span.end], "x");
+}
+
+#[test]
+fn test_assigned_before_read() {
+    let (_, diagnostics) = check("let x = p; let y = x;");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_if_without_else() {
+    let (_, diagnostics) = check("if (p) { let x = 1; } let y = x;");
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn test_if_with_else() {
+    let (_, diagnostics) = check("if (p) { let x = 1; } else { let x = 2; } let y = x;");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_returning_branch() {
+    let (_, diagnostics) = check("if (p) { let x = 1; } else { return; } let y = x;");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_while_body_may_not_run() {
+    let (_, diagnostics) = check("while (p) { let x = 1; } let y = x;");
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn test_while_condition() {
+    let (_, diagnostics) = check("while (x = 0) { let x = 1; }");
+    assert_eq!(diagnostics.len(), 1);
+}
+
+#[test]
+fn test_array_and_call_prefix() {
+    let (_, diagnostics) = check("let x[0] = 1; do y.run();");
+    assert_eq!(diagnostics.len(), 2);
+}
+
+#[test]
+fn test_reported_once() {
+    let (_, diagnostics) = check("let y = x; let y = x;");
+    assert_eq!(diagnostics.len(), 1);
+}
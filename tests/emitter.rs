@@ -0,0 +1,194 @@
+mod utils;
+
+use jack_compiler::diagnostics::Diagnostic;
+use jack_compiler::emitter::{Emitter, HumanEmitter, JsonEmitter, SarifEmitter, SourceFile};
+use jack_compiler::lint::Linter;
+use jack_compiler::{lexer, parser};
+use serde_json::Value;
+use std::path::Path;
+use utils::parse_class;
+
+const SOURCE: &str = "class Main {
+    function int f(int unused) {
+        return 1 + 2 * 3;
+    }
+}";
+
+fn lint(source: &str) -> Vec<Diagnostic> {
+    let class = parse_class(source);
+    Linter::new()
+        .check_program(std::slice::from_ref(&class), &[source])
+        .remove(0)
+}
+
+fn emit(emitter: &mut dyn Emitter, source: &str, diagnostics: &[Diagnostic]) {
+    let file = SourceFile {
+        path: Path::new("Main.jack"),
+        source,
+    };
+    for diagnostic in diagnostics {
+        emitter.emit(&file, diagnostic).unwrap();
+    }
+    emitter.finish().unwrap();
+}
+
+fn parse_error(source: &str) -> Diagnostic {
+    let lex = lexer::Lexer::new(source);
+    let error = parser::ClassParser::new().parse(source, lex).unwrap_err();
+    parser::error_diagnostic(source, &error)
+}
+
+fn render(source: &str, diagnostics: &[Diagnostic], color: bool) -> String {
+    let mut out = Vec::new();
+    emit(
+        &mut HumanEmitter::new(&mut out).with_color(color),
+        source,
+        diagnostics,
+    );
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_human_snippets() {
+    let expected = "\
+warning[J0102]: unused parameter `unused`
+ --> Main.jack:2:24
+  |
+2 |     function int f(int unused) {
+  |                        ^^^^^^
+  |
+  = note: if this is intentional, rename it to `_unused`
+
+warning[J0404]: operators of different precedence are evaluated strictly left to right
+ --> Main.jack:3:16
+  |
+3 |         return 1 + 2 * 3;
+  |                ^^^^^^^^^
+  |                ----- this part is evaluated first
+  |
+  = note: Jack has no operator precedence, so `1 + 2 * 3` means `(1 + 2) * 3`
+  = help: to make the left-to-right evaluation explicit, write `(1 + 2) * 3`
+  = help: to apply conventional precedence instead, which changes the result, write `1 + (2 * 3)`
+
+";
+    assert_eq!(render(SOURCE, &lint(SOURCE), false), expected);
+}
+
+#[test]
+fn test_human_multiline_label() {
+    let source = "class Main {
+    function int f() {
+        var int x;
+        let x = 1;
+        let x = 2;
+    }
+}";
+    let diagnostics = lint(source);
+    let expected = "\
+error[J0001]: subroutine `f` may reach its end without returning
+ --> Main.jack:6:5
+  |
+2 |       function int f() {
+  |  _____-
+...
+6 | |     }
+  | |     ^
+  | |_____- in this subroutine
+  |
+";
+    let out = render(source, &diagnostics, false);
+    assert!(out.starts_with(expected), "{}", out);
+}
+
+#[test]
+fn test_human_color() {
+    let diagnostics = lint(SOURCE);
+    assert!(!render(SOURCE, &diagnostics, false).contains('\x1b'));
+    let out = render(SOURCE, &diagnostics, true);
+    assert!(
+        out.starts_with("\x1b[1;33mwarning[J0102]\x1b[0m: \x1b[1munused parameter `unused`\x1b[0m")
+    );
+    assert!(out.contains("\x1b[1;34m2 |\x1b[0m     function int f(int unused) {"));
+}
+
+#[test]
+fn test_json_lines() {
+    let mut out = Vec::new();
+    emit(&mut JsonEmitter::new(&mut out), SOURCE, &lint(SOURCE));
+    let out = String::from_utf8(out).unwrap();
+    let objects: Vec<Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(objects.len(), 2);
+
+    let unused = &objects[0];
+    assert_eq!(unused["file"], "Main.jack");
+    assert_eq!(unused["severity"], "warning");
+    assert_eq!(unused["code"], "J0102");
+    assert_eq!(unused["message"], "unused parameter `unused`");
+    assert_eq!(unused["location"]["line_start"], 2);
+    assert_eq!(unused["location"]["column_start"], 24);
+    let start = unused["location"]["byte_start"].as_u64().unwrap() as usize;
+    let end = unused["location"]["byte_end"].as_u64().unwrap() as usize;
+    assert_eq!(&SOURCE[start..end], "unused");
+
+    let precedence = &objects[1];
+    assert_eq!(precedence["code"], "J0404");
+    let suggestion = &precedence["suggestions"][0];
+    assert_eq!(suggestion["applicability"], "machine-applicable");
+    assert_eq!(suggestion["edits"][0]["replacement"], "(");
+    assert_eq!(suggestion["edits"][1]["replacement"], ")");
+}
+
+#[test]
+fn test_sarif() {
+    let mut out = Vec::new();
+    let linter = Linter::new();
+    emit(
+        &mut SarifEmitter::new(&mut out, &linter),
+        SOURCE,
+        &lint(SOURCE),
+    );
+    let log: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+
+    let run = &log["runs"][0];
+    let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+    assert!(rules.iter().any(|rule| rule["id"] == "J0404"));
+
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1]["ruleId"], "J0404");
+    assert_eq!(results[1]["level"], "warning");
+    let region = &results[1]["locations"][0]["physicalLocation"]["region"];
+    assert_eq!(region["startLine"], 3);
+    // Only the machine-applicable suggestion becomes a fix
+    assert_eq!(results[1]["fixes"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_parse_errors() {
+    let source = "class Main {\n  function void f() { let x = 3 }\n}";
+    let diagnostic = parse_error(source);
+    assert!(diagnostic.is_error());
+    assert_eq!(diagnostic.message, "unexpected token `}`");
+    assert_eq!(diagnostic.span.start, source.rfind("}\n").unwrap());
+    assert!(diagnostic.notes[0].starts_with("expected one of `;`"));
+
+    let diagnostic = parse_error("class Main { function void f() { return; }");
+    assert_eq!(diagnostic.message, "unexpected end of file");
+}
+
+#[test]
+fn test_lexical_errors() {
+    let source = "class Main { field int x; # }";
+    let diagnostic = parse_error(source);
+    assert_eq!(diagnostic.message, "invalid token");
+    assert_eq!(&source[diagnostic.span.start..diagnostic.span.end], "#");
+
+    let source = "class Main { function int f() { return 99999; } }";
+    let diagnostic = parse_error(source);
+    assert!(diagnostic.message.starts_with("invalid integer constant"));
+    assert_eq!(&source[diagnostic.span.start..diagnostic.span.end], "99999");
+}
@@ -0,0 +1,367 @@
+#![allow(dead_code)]
+
+//! A VM emulator running compiled programs in tests
+//!
+//! The memory layout follows the VM specification: the stack starts at 256, static variables live
+//! in 16-255 and the heap starts at 2048. The Jack OS is replaced by native stubs: the screen is a
+//! log of drawing calls, the keyboard replays scripted input, and the output is collected into a
+//! string.
+
+use jack_compiler::vm::{ArithmeticOp, Instruction, Segment};
+use std::collections::{HashMap, VecDeque};
+
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+const TEMP: usize = 5;
+const STATIC: usize = 16;
+const STACK: usize = 256;
+const HEAP: usize = 2048;
+const HEAP_END: usize = 16384;
+
+struct Frame {
+    return_pc: usize,
+    function: String,
+    lcl: i16,
+    arg: i16,
+    this: i16,
+    that: i16,
+}
+
+pub struct Emulator {
+    pub ram: Vec<i16>,
+    program: Vec<Instruction>,
+    functions: HashMap<String, usize>,
+    /// Label positions, keyed by function and label.
+    labels: HashMap<(String, String), usize>,
+    /// The static variables of each class, keyed by class and index.
+    statics: HashMap<(String, u16), usize>,
+    frames: Vec<Frame>,
+    pc: usize,
+    heap_top: usize,
+    pub output: String,
+    /// The numbers read by `Keyboard.readInt`.
+    pub input: VecDeque<i16>,
+    /// The keys returned by successive `Keyboard.keyPressed` calls, 0 once exhausted.
+    pub keys: VecDeque<i16>,
+    /// The Screen calls made so far, e.g. `drawRectangle(0, 0, 30, 30)`.
+    pub screen: Vec<String>,
+    /// The number of VM instructions executed so far.
+    pub steps: usize,
+    halted: bool,
+}
+
+impl Emulator {
+    /// Loads the code of every class of a program.
+    pub fn new(classes: &[Vec<Instruction>]) -> Self {
+        let program: Vec<Instruction> = classes.iter().flatten().cloned().collect();
+        let mut functions = HashMap::new();
+        let mut labels = HashMap::new();
+        let mut current = String::new();
+        for (pc, instruction) in program.iter().enumerate() {
+            match instruction {
+                Instruction::Function { name, .. } => {
+                    functions.insert(name.clone(), pc);
+                    current = name.clone();
+                }
+                Instruction::Label(label) => {
+                    labels.insert((current.clone(), label.clone()), pc);
+                }
+                _ => {}
+            }
+        }
+        let mut ram = vec![0; 32768];
+        ram[SP] = STACK as i16;
+        Self {
+            ram,
+            program,
+            functions,
+            labels,
+            statics: HashMap::new(),
+            frames: Vec::new(),
+            pc: 0,
+            heap_top: HEAP,
+            output: String::new(),
+            input: VecDeque::new(),
+            keys: VecDeque::new(),
+            screen: Vec::new(),
+            steps: 0,
+            halted: false,
+        }
+    }
+
+    /// Calls `function` with no arguments and runs until it returns or the program halts,
+    /// returning the value of the call. Panics if more than `max_steps` instructions run.
+    pub fn run(&mut self, function: &str, max_steps: usize) -> i16 {
+        self.call(function, 0, usize::MAX);
+        while !self.halted && !self.frames.is_empty() {
+            assert!(self.steps < max_steps, "step limit exceeded");
+            self.step();
+        }
+        self.ram[self.sp() - 1]
+    }
+
+    /// Returns the characters of a `String` object.
+    pub fn string(&self, address: i16) -> String {
+        let address = address as usize;
+        let len = self.ram[address + 1] as usize;
+        self.ram[address + 2..address + 2 + len]
+            .iter()
+            .map(|&c| char::from(c as u8))
+            .collect()
+    }
+
+    fn sp(&self) -> usize {
+        self.ram[SP] as usize
+    }
+
+    fn push(&mut self, value: i16) {
+        let sp = self.sp();
+        self.ram[sp] = value;
+        self.ram[SP] += 1;
+    }
+
+    fn pop(&mut self) -> i16 {
+        self.ram[SP] -= 1;
+        self.ram[self.sp()]
+    }
+
+    fn current_class(&self) -> String {
+        let function = &self.frames.last().unwrap().function;
+        function.split('.').next().unwrap().to_string()
+    }
+
+    fn address(&mut self, segment: Segment, index: u16) -> usize {
+        let index = index as usize;
+        match segment {
+            Segment::Argument => self.ram[ARG] as usize + index,
+            Segment::Local => self.ram[LCL] as usize + index,
+            Segment::This => self.ram[THIS] as u16 as usize + index,
+            Segment::That => self.ram[THAT] as u16 as usize + index,
+            Segment::Pointer => {
+                assert!(index < 2, "invalid pointer index {}", index);
+                THIS + index
+            }
+            Segment::Temp => {
+                assert!(index < 8, "invalid temp index {}", index);
+                TEMP + index
+            }
+            Segment::Static => {
+                let next = STATIC + self.statics.len();
+                let key = (self.current_class(), index as u16);
+                let address = *self.statics.entry(key).or_insert(next);
+                assert!(address < STACK, "too many static variables");
+                address
+            }
+            Segment::Constant => unreachable!("the constant segment has no address"),
+        }
+    }
+
+    fn step(&mut self) {
+        self.steps += 1;
+        let instruction = self.program[self.pc].clone();
+        self.pc += 1;
+        match instruction {
+            Instruction::Push(Segment::Constant, value) => {
+                assert!(value <= 32767, "constant {} out of range", value);
+                self.push(value as i16);
+            }
+            Instruction::Push(segment, index) => {
+                let address = self.address(segment, index);
+                self.push(self.ram[address]);
+            }
+            Instruction::Pop(segment, index) => {
+                let address = self.address(segment, index);
+                let value = self.pop();
+                self.ram[address] = value;
+            }
+            Instruction::Arithmetic(op) => self.arithmetic(op),
+            Instruction::Label(_) => {}
+            Instruction::Goto(label) => self.pc = self.label(&label),
+            Instruction::IfGoto(label) => {
+                if self.pop() != 0 {
+                    self.pc = self.label(&label);
+                }
+            }
+            Instruction::Function { locals, .. } => {
+                for _ in 0..locals {
+                    self.push(0);
+                }
+            }
+            Instruction::Call { name, args } => self.call(&name, args, self.pc),
+            Instruction::Return => {
+                let frame = self.frames.pop().unwrap();
+                let value = self.pop();
+                let arg = self.ram[ARG];
+                self.ram[arg as usize] = value;
+                self.ram[SP] = arg + 1;
+                self.ram[LCL] = frame.lcl;
+                self.ram[ARG] = frame.arg;
+                self.ram[THIS] = frame.this;
+                self.ram[THAT] = frame.that;
+                self.pc = frame.return_pc;
+            }
+        }
+    }
+
+    fn label(&self, label: &str) -> usize {
+        let function = self.frames.last().unwrap().function.clone();
+        *self
+            .labels
+            .get(&(function.clone(), label.to_string()))
+            .unwrap_or_else(|| panic!("unknown label {} in {}", label, function))
+    }
+
+    fn arithmetic(&mut self, op: ArithmeticOp) {
+        let bool_value = |b: bool| if b { -1 } else { 0 };
+        if let ArithmeticOp::Neg | ArithmeticOp::Not = op {
+            let x = self.pop();
+            self.push(if op == ArithmeticOp::Neg {
+                x.wrapping_neg()
+            } else {
+                !x
+            });
+            return;
+        }
+        let y = self.pop();
+        let x = self.pop();
+        self.push(match op {
+            ArithmeticOp::Add => x.wrapping_add(y),
+            ArithmeticOp::Sub => x.wrapping_sub(y),
+            ArithmeticOp::Eq => bool_value(x == y),
+            ArithmeticOp::Gt => bool_value(x > y),
+            ArithmeticOp::Lt => bool_value(x < y),
+            ArithmeticOp::And => x & y,
+            ArithmeticOp::Or => x | y,
+            ArithmeticOp::Neg | ArithmeticOp::Not => unreachable!(),
+        });
+    }
+
+    fn call(&mut self, name: &str, args: u16, return_pc: usize) {
+        let Some(&pc) = self.functions.get(name) else {
+            let args: Vec<i16> = (0..args).map(|_| self.pop()).collect();
+            let args: Vec<i16> = args.into_iter().rev().collect();
+            let value = self.os_call(name, &args);
+            self.push(value);
+            return;
+        };
+        let arg = self.ram[SP] - args as i16;
+        self.frames.push(Frame {
+            return_pc,
+            function: name.to_string(),
+            lcl: self.ram[LCL],
+            arg: self.ram[ARG],
+            this: self.ram[THIS],
+            that: self.ram[THAT],
+        });
+        // The saved frame takes 5 words of the stack, like in the real VM
+        for _ in 0..5 {
+            self.push(0);
+        }
+        self.ram[ARG] = arg;
+        self.ram[LCL] = self.ram[SP];
+        self.pc = pc;
+    }
+
+    fn alloc(&mut self, size: i16) -> i16 {
+        assert!(size >= 0, "allocating a negative size");
+        let address = self.heap_top;
+        self.heap_top += (size as usize).max(1);
+        assert!(self.heap_top <= HEAP_END, "heap overflow");
+        address as i16
+    }
+
+    fn print(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn os_call(&mut self, name: &str, args: &[i16]) -> i16 {
+        match (name, args) {
+            ("Math.multiply", &[x, y]) => x.wrapping_mul(y),
+            ("Math.divide", &[x, y]) => {
+                assert!(y != 0, "division by zero");
+                x.wrapping_div(y)
+            }
+            ("Math.abs", &[x]) => x.wrapping_abs(),
+            ("Math.min", &[x, y]) => x.min(y),
+            ("Math.max", &[x, y]) => x.max(y),
+            ("Memory.alloc", &[size]) | ("Array.new", &[size]) => self.alloc(size),
+            ("Memory.deAlloc", &[_]) | ("Array.dispose", &[_]) => 0,
+            ("Memory.peek", &[address]) => self.ram[address as u16 as usize],
+            ("Memory.poke", &[address, value]) => {
+                self.ram[address as u16 as usize] = value;
+                0
+            }
+            // Strings are laid out as [max length, length, characters...]
+            ("String.new", &[max_len]) => {
+                let address = self.alloc(max_len + 2);
+                self.ram[address as usize] = max_len;
+                self.ram[address as usize + 1] = 0;
+                address
+            }
+            ("String.appendChar", &[string, c]) => {
+                let address = string as usize;
+                let len = self.ram[address + 1];
+                assert!(len < self.ram[address], "string is full");
+                self.ram[address + 2 + len as usize] = c;
+                self.ram[address + 1] = len + 1;
+                string
+            }
+            ("String.length", &[string]) => self.ram[string as usize + 1],
+            ("String.charAt", &[string, i]) => self.ram[string as usize + 2 + i as usize],
+            ("String.dispose", &[_]) => 0,
+            ("String.newLine", &[]) => 128,
+            ("String.backSpace", &[]) => 129,
+            ("String.doubleQuote", &[]) => 34,
+            ("Output.printString", &[string]) => {
+                let text = self.string(string);
+                self.print(&text);
+                0
+            }
+            ("Output.printInt", &[i]) => {
+                self.print(&i.to_string());
+                0
+            }
+            ("Output.printChar", &[c]) => {
+                let text = if c == 128 {
+                    "\n".to_string()
+                } else {
+                    char::from(c as u8).to_string()
+                };
+                self.print(&text);
+                0
+            }
+            ("Output.println", &[]) => {
+                self.print("\n");
+                0
+            }
+            ("Output.moveCursor", &[_, _]) | ("Output.backSpace", &[]) => 0,
+            ("Keyboard.readInt", &[message]) => {
+                let text = self.string(message);
+                let value = self.input.pop_front().expect("no input left");
+                self.print(&format!("{}{}\n", text, value));
+                value
+            }
+            ("Keyboard.keyPressed", &[]) => self.keys.pop_front().unwrap_or(0),
+            ("Screen.clearScreen", &[]) => {
+                self.screen.push("clearScreen()".to_string());
+                0
+            }
+            (call, args) if call.starts_with("Screen.") => {
+                let args: Vec<String> = args.iter().map(i16::to_string).collect();
+                self.screen
+                    .push(format!("{}({})", &call["Screen.".len()..], args.join(", ")));
+                0
+            }
+            ("Sys.wait", &[_]) => 0,
+            ("Sys.halt", &[]) => {
+                self.halted = true;
+                0
+            }
+            ("Sys.error", &[code]) => panic!("Sys.error({})", code),
+            _ => panic!("unknown function {} with {} arguments", name, args.len()),
+        }
+    }
+}
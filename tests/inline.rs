@@ -0,0 +1,204 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::ir::{self, Function};
+use jack_compiler::vm::Instruction;
+use utils::parse_class;
+
+const POINT: &str = "class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        let count = count + 1;
+        return this;
+    }
+
+    method int getX() {
+        return x;
+    }
+
+    method int getY() {
+        return y;
+    }
+
+    method int dot(Point other) {
+        return (getX() * other.getX()) + (y * other.getY());
+    }
+
+    function int count() {
+        return count;
+    }
+
+    function int twice(int n) {
+        return n + n;
+    }
+
+    function int forever(int n) {
+        return Point.forever(n);
+    }
+
+    function int zero() {
+        var int unset;
+        return unset;
+    }
+}";
+
+const MAIN: &str = "class Main {
+    function int main() {
+        var Point p, q;
+        let p = Point.new(2, 3);
+        let q = Point.new(4, 5);
+        return p.dot(q) + Point.twice(q.getY()) + Point.count() + Point.zero();
+    }
+}";
+
+fn options() -> Options {
+    Options {
+        optimize: true,
+        inline: true,
+        ..Options::default()
+    }
+}
+
+fn lower_program(sources: &[&str]) -> Vec<Vec<Function>> {
+    let classes: Vec<_> = sources.iter().map(|source| parse_class(source)).collect();
+    ir::lower_program(&classes, &options())
+        .into_iter()
+        .map(|result| {
+            let mut functions = result.unwrap();
+            for function in &mut functions {
+                assert_eq!(ir::verify(function), Ok(()), "{}", function);
+                ir::optimize(function);
+            }
+            functions
+        })
+        .collect()
+}
+
+fn find<'a>(program: &'a [Vec<Function>], name: &str) -> &'a Function {
+    program.iter().flatten().find(|f| f.name == name).unwrap()
+}
+
+#[test]
+fn test_inline() {
+    let program = lower_program(&[POINT, MAIN]);
+    // Fields of the current object are read directly, the ones of other objects from memory
+    let expected = "\
+function Point.dot kind=method args=2 locals=0 temps=6
+b0:
+    this = arg0
+    t0 = mem[arg1]
+    t1 = field0 * t0
+    t2 = arg1 + 1
+    t3 = mem[t2]
+    t4 = field1 * t3
+    t5 = t1 + t4
+    return t5
+";
+    assert_eq!(find(&program, "Point.dot").to_string(), expected);
+
+    // `Point.count` reads a static of `Point`, `Point.forever` calls itself and `Point.zero`
+    // reads a local
+    let main = find(&program, "Main.main").to_string();
+    assert!(!main.contains("Point.dot"), "{}", main);
+    assert!(!main.contains("Point.twice"), "{}", main);
+    assert!(!main.contains("Point.getY"), "{}", main);
+    assert!(main.contains("call Point.count()"), "{}", main);
+    assert!(main.contains("call Point.zero()"), "{}", main);
+
+    let point = parse_class(POINT);
+    let forever = parse_class(
+        "class Main {
+    function int main() {
+        return Point.forever(1);
+    }
+}",
+    );
+    let program = ir::lower_program(&[point, forever], &options());
+    let main = program[1].as_ref().unwrap()[0].to_string();
+    assert!(main.contains("call Point.forever(1)"), "{}", main);
+}
+
+#[test]
+fn test_inlined_program_runs() {
+    let classes: Vec<_> = [POINT, MAIN]
+        .iter()
+        .map(|source| parse_class(source))
+        .collect();
+    let compile = |options: &Options| -> Vec<Vec<Instruction>> {
+        codegen::compile_program(&classes, options)
+            .into_iter()
+            .map(|result| result.unwrap().instructions)
+            .collect()
+    };
+    // 2 * 4 + 3 * 5, then 5 + 5, and 2 points created
+    for options in [Options::default(), options()] {
+        let mut emulator = Emulator::new(&compile(&options));
+        assert_eq!(emulator.run("Main.main", 10_000), 23 + 10 + 2);
+    }
+}
+
+#[test]
+fn test_arguments_copied() {
+    // `Main.get` reads its argument after a call that changes the static passed to it
+    let source = "class Main {
+    static int s;
+
+    function int main() {
+        let s = 1;
+        return Main.get(s);
+    }
+
+    function int get(int n) {
+        return Main.bump() + n;
+    }
+
+    function int bump() {
+        let s = 10;
+        return 0;
+    }
+}";
+    let classes = [parse_class(source)];
+    let code: Vec<_> = codegen::compile_program(&classes, &options())
+        .into_iter()
+        .map(|result| result.unwrap().instructions)
+        .collect();
+    assert!(
+        !code[0]
+            .iter()
+            .any(|instruction| instruction.to_string() == "call Main.get 1")
+    );
+    let mut emulator = Emulator::new(&code);
+    assert_eq!(emulator.run("Main.main", 10_000), 1);
+}
+
+#[test]
+fn test_argument_count_mismatch() {
+    // The compiler doesn't check the number of arguments, so calls passing too few are compiled,
+    // and left as calls
+    let source = "class Main {
+    field int x;
+
+    function int main() {
+        var Main m;
+        return m.get() + Main.get2();
+    }
+
+    method int get(int a) {
+        return a;
+    }
+
+    method int get2() {
+        return x;
+    }
+}";
+    let program = lower_program(&[source]);
+    let main = find(&program, "Main.main").to_string();
+    assert!(main.contains("call Main.get(local0)"), "{}", main);
+    assert!(main.contains("call Main.get2()"), "{}", main);
+}
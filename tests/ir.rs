@@ -0,0 +1,456 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::ast::SubroutineKind;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::ir::{self, Block, Function, Inst, Operand, Terminator, Var};
+use jack_compiler::span::Span;
+use jack_compiler::vm::Instruction;
+use std::fs;
+use utils::parse_class;
+
+fn lower(source: &str, options: &Options) -> Vec<Function> {
+    let functions = ir::lower_class(&parse_class(source), options)
+        .unwrap_or_else(|e| panic!("error occurs while lowering: {:?}", e));
+    for function in &functions {
+        if let Err(errors) = ir::verify(function) {
+            panic!(
+                "invalid IR for {}: {:?}\n{}",
+                function.name, errors, function
+            );
+        }
+    }
+    functions
+}
+
+fn dump(functions: &[Function]) -> String {
+    functions.iter().map(Function::to_string).collect()
+}
+
+fn to_vm(functions: &[Function]) -> Vec<Instruction> {
+    functions.iter().flat_map(ir::to_vm).collect()
+}
+
+fn vm_text(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+/// Compiles every class of a program in `tests/programs` through the IR.
+fn compile_program(program_name: &str) -> Vec<Vec<Instruction>> {
+    let mut paths: Vec<_> = fs::read_dir(format!("tests/programs/{}", program_name))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            to_vm(&lower(
+                &fs::read_to_string(path).unwrap(),
+                &Options::default(),
+            ))
+        })
+        .collect()
+}
+
+#[test]
+fn test_dump() {
+    let source = "class Main {
+    static Array table;
+
+    function int sum(int n) {
+        var int i, total;
+        while (i < n) {
+            if (table[i] > 0) {
+                let total = total + table[i];
+            } else {
+                let table[i] = -table[i];
+            }
+            let i = i + 1;
+        }
+        do Output.printString(\"ok\");
+        return total * 2;
+    }
+}";
+    let expected = "\
+function Main.sum kind=function args=1 locals=2 temps=14
+b0:
+    goto b1
+b1:
+    t0 = local0 < arg0
+    if t0 goto b2 else b6
+b2:
+    t1 = static0 + local0
+    t2 = mem[t1]
+    t3 = t2 > 0
+    if t3 goto b3 else b4
+b3:
+    t4 = static0 + local0
+    t5 = mem[t4]
+    local1 = local1 + t5
+    goto b5
+b4:
+    t6 = static0 + local0
+    t7 = static0 + local0
+    t8 = mem[t7]
+    t9 = -t8
+    mem[t6] = t9
+    goto b5
+b5:
+    local0 = local0 + 1
+    goto b1
+b6:
+    t10 = call String.new(2)
+    t11 = call String.appendChar(t10, 111)
+    t12 = call String.appendChar(t11, 107)
+    call Output.printString(t12)
+    t13 = local1 * 2
+    return t13
+";
+    let functions = lower(source, &Options::default());
+    assert_eq!(dump(&functions), expected);
+}
+
+#[test]
+fn test_pooled_strings() {
+    let source = "class Main {
+    function void main() {
+        do Output.printString(\"a\");
+        return;
+    }
+}";
+    let options = Options {
+        pool_strings: true,
+        ..Options::default()
+    };
+    let expected = "\
+function Main.main kind=function args=0 locals=0 temps=1
+b0:
+    if static0 goto b2 else b1
+b1:
+    t0 = call String.new(1)
+    static0 = call String.appendChar(t0, 97)
+    goto b2
+b2:
+    call Output.printString(static0)
+    return 0
+";
+    assert_eq!(dump(&lower(source, &options)), expected);
+}
+
+#[test]
+fn test_evaluation_order() {
+    // `count` is read before `bump` changes it, and `a[i]` before `set` changes it
+    let source = "class Main {
+    static int count;
+
+    function int main() {
+        var int x, i;
+        var Array a;
+        let a = Array.new(2);
+        let a[1] = 10;
+        let x = count - Main.bump();
+        let x = x + Main.add(count, Main.bump());
+        let i = 1;
+        return x + a[i] - Main.read(a, Main.set(a));
+    }
+
+    function int bump() {
+        let count = count + 1;
+        return count;
+    }
+
+    function int add(int a, int b) {
+        return a + b;
+    }
+
+    function int set(Array a) {
+        let a[1] = 3;
+        return 1;
+    }
+
+    function int read(Array a, int i) {
+        return a[i];
+    }
+}";
+    let functions = lower(source, &Options::default());
+    assert!(dump(&functions).contains(
+        "\
+    t2 = static0
+    t1 = call Main.bump()
+    local0 = t2 - t1
+"
+    ));
+    // x = 0 - 1, then x + (1 + 2) = 2, and 2 + 10 - 3
+    let mut emulator = Emulator::new(&[to_vm(&functions)]);
+    assert_eq!(emulator.run("Main.main", 10_000), 9);
+}
+
+#[test]
+fn test_to_vm() {
+    // Intermediate results stay on the stack, giving the same code as the direct code generator
+    let source = "class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax) {
+        let x = ax;
+        let count = count + 1;
+        return this;
+    }
+
+    method int getX() {
+        return x;
+    }
+
+    method int sum(Point other) {
+        return getX() + other.getX() + Point.zero();
+    }
+
+    function int zero() {
+        return 0;
+    }
+
+    method void draw(int size) {
+        do Screen.drawRectangle(x, y, x + size, 7 - (y * size));
+        return;
+    }
+
+    function int loop(int n) {
+        var int i;
+        while (i < n) {
+            let i = i + 1;
+        }
+        if (~(i = n)) {
+            return -1;
+        }
+        return 32767 - n;
+    }
+}";
+    let class = parse_class(source);
+    let mut direct = Vec::new();
+    codegen::compile_class(&class, &Options::default())
+        .unwrap()
+        .write(&mut direct)
+        .unwrap();
+    let direct = String::from_utf8(direct).unwrap();
+    let vm = vm_text(&to_vm(&lower(source, &Options::default())));
+    let loop_start = direct.find("function Point.loop").unwrap();
+    assert_eq!(vm[..loop_start], direct[..loop_start]);
+    assert_eq!(
+        &vm[loop_start..],
+        "\
+function Point.loop 1
+label b1
+push local 0
+push argument 0
+lt
+not
+if-goto b3
+push local 0
+push constant 1
+add
+pop local 0
+goto b1
+label b3
+push local 0
+push argument 0
+eq
+not
+not
+if-goto b5
+push constant 1
+neg
+return
+label b5
+push constant 32767
+push argument 0
+sub
+return
+"
+    );
+}
+
+#[test]
+fn test_stored_temporaries() {
+    // `t0` is read after the call is pushed, so it's kept in a local variable
+    let function = Function {
+        name: "Main.f".to_string(),
+        kind: SubroutineKind::Function,
+        args: 1,
+        locals: 1,
+        temps: 1,
+        blocks: vec![
+            Block {
+                insts: vec![Inst::Binary {
+                    dst: Var::Temp(0),
+                    op: ir::BinaryOp::Mul,
+                    lhs: Operand::Var(Var::Argument(0)),
+                    rhs: Operand::Const(3),
+                }],
+                spans: vec![Span::default(); 1],
+                terminator: Terminator::Jump(1),
+                terminator_span: Span::default(),
+            },
+            Block {
+                insts: vec![Inst::Call {
+                    dst: Some(Var::Local(0)),
+                    function: "Main.g".to_string(),
+                    args: vec![Operand::Var(Var::Local(0)), Operand::Var(Var::Temp(0))],
+                }],
+                spans: vec![Span::default(); 1],
+                terminator: Terminator::Return(Operand::Var(Var::Local(0))),
+                terminator_span: Span::default(),
+            },
+        ],
+        span: Span::default(),
+    };
+    assert_eq!(ir::verify(&function), Ok(()));
+    assert_eq!(
+        vm_text(&ir::to_vm(&function)),
+        "\
+function Main.f 2
+push argument 0
+push constant 3
+call Math.multiply 2
+pop local 1
+push local 0
+push local 1
+call Main.g 2
+pop local 0
+push local 0
+return
+"
+    );
+}
+
+#[test]
+fn test_programs() {
+    let mut emulator = Emulator::new(&compile_program("ArrayTest"));
+    emulator.input.extend([4, 10, 20, 33, -3]);
+    emulator.run("Main.main", 100_000);
+    assert!(emulator.output.ends_with("THE AVERAGE IS: 15\n"));
+
+    let mut emulator = Emulator::new(&compile_program("Square"));
+    emulator.keys.extend([132, 0, 0, 0, 81]);
+    emulator.run("Main.main", 1_000_000);
+    assert_eq!(emulator.screen.len(), 22);
+    assert_eq!(emulator.screen[21], "drawRectangle(39, 0, 40, 30)");
+}
+
+#[test]
+fn test_errors() {
+    let source = "class Main {
+    field int x;
+
+    function void main() {
+        var int n;
+        do n.foo();
+        let x = y;
+        return;
+    }
+}";
+    let errors = ir::lower_class(&parse_class(source), &Options::default()).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "`n` is of type `int`, which has no methods",
+            "cannot find variable `y` in this scope",
+            "cannot use field `x` in function `main`",
+        ]
+    );
+}
+
+#[test]
+fn test_verify() {
+    let function = Function {
+        name: "Main.f".to_string(),
+        kind: SubroutineKind::Function,
+        args: 0,
+        locals: 1,
+        temps: 2,
+        blocks: vec![
+            Block {
+                insts: vec![
+                    Inst::Copy {
+                        dst: Var::Local(1),
+                        src: Operand::Var(Var::Field(0)),
+                    },
+                    Inst::Copy {
+                        dst: Var::Temp(0),
+                        src: Operand::Const(1),
+                    },
+                ],
+                spans: vec![Span::default(); 2],
+                terminator: Terminator::Branch {
+                    cond: Operand::Var(Var::Temp(0)),
+                    then: 1,
+                    otherwise: 2,
+                },
+                terminator_span: Span::default(),
+            },
+            Block {
+                insts: vec![Inst::Copy {
+                    dst: Var::Temp(1),
+                    src: Operand::Const(2),
+                }],
+                spans: vec![Span::default(); 1],
+                terminator: Terminator::Jump(2),
+                terminator_span: Span::default(),
+            },
+            Block {
+                insts: Vec::new(),
+                spans: Vec::new(),
+                terminator: Terminator::Return(Operand::Var(Var::Temp(1))),
+                terminator_span: Span::default(),
+            },
+        ],
+        span: Span::default(),
+    };
+    let messages: Vec<String> = ir::verify(&function)
+        .unwrap_err()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "Main.f: b0: field0 is used in a function, which has no current object",
+            "Main.f: b0: local1 is out of range, the function has 1 locals",
+        ]
+    );
+
+    let mut function = function;
+    function.blocks[0].insts.remove(0);
+    let messages: Vec<String> = ir::verify(&function)
+        .unwrap_err()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(messages, ["Main.f: b0: 2 spans for 1 instructions"]);
+
+    function.blocks[0].spans.remove(0);
+    let messages: Vec<String> = ir::verify(&function)
+        .unwrap_err()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        messages,
+        ["Main.f: b2: t1 may be used before it's assigned"]
+    );
+
+    function.blocks[1].terminator = Terminator::Jump(3);
+    let messages: Vec<String> = ir::verify(&function)
+        .unwrap_err()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(messages, ["Main.f: b1: jump to missing block b3"]);
+}
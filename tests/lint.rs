@@ -0,0 +1,135 @@
+mod utils;
+
+use jack_compiler::diagnostics::{Diagnostic, Severity};
+use jack_compiler::lint::{Context, Level, Linter, Rule};
+use utils::parse_class;
+
+fn check(linter: &Linter, source: &str) -> Vec<Diagnostic> {
+    let class = parse_class(source);
+    linter
+        .check_program(std::slice::from_ref(&class), &[source])
+        .remove(0)
+}
+
+const SOURCE: &str = "class Main {
+    function void f(int a) {
+        var int b;
+        return;
+    }
+}";
+
+#[test]
+fn test_default_levels() {
+    let diagnostics = check(&Linter::new(), SOURCE);
+    let codes: Vec<_> = diagnostics.iter().map(|d| d.code.unwrap()).collect();
+    assert_eq!(codes, ["J0102", "J0101"]);
+    assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+}
+
+#[test]
+fn test_set_level() {
+    let mut linter = Linter::new();
+    linter.set_level("unused-parameter", Level::Allow).unwrap();
+    linter.set_level("J0101", Level::Deny).unwrap();
+    let diagnostics = check(&linter, SOURCE);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Some("J0101"));
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+
+    assert!(linter.set_level("no-such-rule", Level::Allow).is_err());
+}
+
+#[test]
+fn test_deny_warnings() {
+    let mut linter = Linter::new();
+    linter.set_level("warnings", Level::Deny).unwrap();
+    let diagnostics = check(&linter, SOURCE);
+    assert_eq!(diagnostics.len(), 2);
+    assert!(diagnostics.iter().all(Diagnostic::is_error));
+}
+
+#[test]
+fn test_allow_comment() {
+    let source = "class Main {
+    // jack-allow(J0102)
+    function void f(int a) {
+        // jack-allow(unused-local, unassigned-read)
+        var int b;
+        var int c;
+        return;
+    }
+}";
+    let diagnostics = check(&Linter::new(), source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Some("J0101"));
+    assert!(diagnostics[0].message.contains("`c`"));
+}
+
+#[test]
+fn test_allow_comment_in_string() {
+    // A `//` in a string constant doesn't start a comment
+    let source = "class Main {
+    function int f() {
+        do Output.printString(\"// jack-allow(division-by-zero)\");
+        return 1 / 0;
+    }
+}";
+    let diagnostics = check(&Linter::new(), source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Some("J0402"));
+
+    let source = "class Main {
+    function int f() {
+        do Output.printString(\"a\"); // jack-allow(division-by-zero)
+        return 1 / 0;
+    }
+}";
+    assert!(check(&Linter::new(), source).is_empty());
+}
+
+/// A house rule forbidding subroutines named `main` outside of `Main`
+struct MainOnlyInMain;
+
+impl Rule for MainOnlyInMain {
+    fn code(&self) -> &'static str {
+        "X0001"
+    }
+
+    fn name(&self) -> &'static str {
+        "main-only-in-main"
+    }
+
+    fn description(&self) -> &'static str {
+        "a subroutine named `main` outside of the `Main` class"
+    }
+
+    fn default_level(&self) -> Level {
+        Level::Warn
+    }
+
+    fn check(&self, cx: &Context, diagnostics: &mut Vec<Diagnostic>) {
+        if cx.class.name.name == "Main" {
+            return;
+        }
+        for subroutine in &cx.class.subroutines {
+            if subroutine.name.name == "main" {
+                diagnostics.push(Diagnostic::warning(
+                    "`main` outside of `Main`",
+                    subroutine.name.span,
+                ));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_custom_rule() {
+    let mut linter = Linter::new();
+    linter.register(Box::new(MainOnlyInMain));
+    linter.set_level("main-only-in-main", Level::Deny).unwrap();
+    let source = "class Game { function void main() { return; } }";
+    let diagnostics = check(&linter, source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Some("X0001"));
+    assert!(diagnostics[0].is_error());
+}
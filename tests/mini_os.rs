@@ -0,0 +1,92 @@
+#![allow(dead_code)]
+
+//! A minimal Jack OS for the programs run on the Hack CPU in tests
+
+/// `Sys.init` stores the value of `Main.main` at [`RESULT`] and halts, and the other classes only
+/// have what the generated code calls and the tests use.
+pub const OS: [&str; 5] = [
+    "class Sys {
+    function void init() {
+        var Array ram;
+        let ram = 0;
+        let ram[8000] = Main.main();
+        while (true) {}
+        return;
+    }
+
+    function void wait(int duration) {
+        return;
+    }
+}",
+    "class Math {
+    function int multiply(int x, int y) {
+        var int sum;
+        if (y < 0) {
+            let x = -x;
+            let y = -y;
+        }
+        while (y > 0) {
+            let sum = sum + x;
+            let y = y - 1;
+        }
+        return sum;
+    }
+
+    function int divide(int x, int y) {
+        var int quotient;
+        while (~(x < y)) {
+            let x = x - y;
+            let quotient = quotient + 1;
+        }
+        return quotient;
+    }
+}",
+    "class Memory {
+    static int free;
+
+    function int alloc(int size) {
+        var int block;
+        if (free = 0) {
+            let free = 2048;
+        }
+        let block = free;
+        let free = free + size;
+        return block;
+    }
+
+    function void deAlloc(Array block) {
+        return;
+    }
+}",
+    "class Array {
+    function Array new(int size) {
+        return Memory.alloc(size);
+    }
+}",
+    "class String {
+    field int length;
+    field Array chars;
+
+    constructor String new(int maxLength) {
+        let chars = Array.new(maxLength);
+        return this;
+    }
+
+    method String appendChar(char c) {
+        let chars[length] = c;
+        let length = length + 1;
+        return this;
+    }
+
+    method int length() {
+        return length;
+    }
+
+    method char charAt(int i) {
+        return chars[i];
+    }
+}",
+];
+
+/// The address `Sys.init` stores the value of `Main.main` at.
+pub const RESULT: usize = 8000;
@@ -0,0 +1,222 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::ast::SubroutineKind;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::ir::{self, BinaryOp, Block, Function, Inst, Operand, Terminator, Var};
+use jack_compiler::span::Span;
+use jack_compiler::vm::Instruction;
+use std::fs;
+use utils::parse_class;
+
+fn optimized() -> Options {
+    Options {
+        optimize: true,
+        ..Options::default()
+    }
+}
+
+fn optimize(source: &str) -> String {
+    let functions = ir::lower_class(&parse_class(source), &Options::default()).unwrap();
+    functions
+        .into_iter()
+        .map(|mut function| {
+            ir::optimize(&mut function);
+            assert_eq!(ir::verify(&function), Ok(()));
+            function.to_string()
+        })
+        .collect()
+}
+
+fn compile_classes(sources: &[&str], options: &Options) -> Vec<Vec<Instruction>> {
+    sources
+        .iter()
+        .map(|source| {
+            codegen::compile_class(&parse_class(source), options)
+                .unwrap()
+                .instructions
+        })
+        .collect()
+}
+
+fn compile_program(program_name: &str, options: &Options) -> Vec<Vec<Instruction>> {
+    let mut paths: Vec<_> = fs::read_dir(format!("tests/programs/{}", program_name))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    paths.sort();
+    let sources: Vec<String> = paths
+        .iter()
+        .map(|path| fs::read_to_string(path).unwrap())
+        .collect();
+    let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+    compile_classes(&sources, options)
+}
+
+fn instruction_count(classes: &[Vec<Instruction>]) -> usize {
+    classes.iter().map(Vec::len).sum()
+}
+
+#[test]
+fn test_constant_folding() {
+    let source = "class Main {
+    function int main(int x) {
+        var int y;
+        let y = 2 * 3 + 4 - (10 / 5);
+        let y = y + (x + 1 + 2 - 4);
+        if (~(1 = 2)) {
+            let y = y - (-32768);
+        }
+        while (false) {
+            let y = 0;
+        }
+        return y / 0;
+    }
+}";
+    // `y / 0` is left for Math.divide to report
+    let expected = "\
+function Main.main kind=function args=1 locals=1 temps=2
+b0:
+    local0 = 8
+    t0 = arg0 - 1
+    local0 = local0 + t0
+    goto b1
+b1:
+    local0 = local0 - -32768
+    goto b2
+b2:
+    goto b3
+b3:
+    goto b5
+b4:
+    local0 = 0
+    goto b3
+b5:
+    t1 = local0 / 0
+    return t1
+";
+    assert_eq!(optimize(source), expected);
+}
+
+#[test]
+fn test_simplification() {
+    let source = "class Main {
+    function int main(int x, int y) {
+        var int z;
+        let z = (x * 1) + (y + 0) - (x - 0);
+        let z = z | (~~x & y);
+        let z = z + (x * 0) + (0 - y);
+        return z / -1;
+    }
+}";
+    let expected = "\
+function Main.main kind=function args=2 locals=1 temps=4
+b0:
+    t0 = arg0 + arg1
+    local0 = t0 - arg0
+    t1 = arg0 & arg1
+    local0 = local0 | t1
+    t2 = -arg1
+    local0 = local0 + t2
+    t3 = -local0
+    return t3
+";
+    assert_eq!(optimize(source), expected);
+}
+
+#[test]
+fn test_strength_reduction() {
+    let source = "class Main {
+    function int main(int x) {
+        return (x * 8) + (4 * x) - (x * -2) + (x / 4);
+    }
+}";
+    let dump = optimize(source);
+    assert!(!dump.contains('*'), "{}", dump);
+    // Dividing by a power of two can't be reduced, it rounds toward 0
+    assert!(dump.contains("arg0 / 4"), "{}", dump);
+
+    for x in [0i16, 1, -1, 7, -7, 100, -100, 4095, -4096] {
+        let caller = format!(
+            "class Test {{ function int run() {{ return Main.main({}); }} }}",
+            x
+        );
+        let classes = compile_classes(&[source, &caller], &optimized());
+        assert!(
+            !classes[0]
+                .iter()
+                .any(|instruction| instruction.to_string() == "call Math.multiply 2")
+        );
+        let expected = x
+            .wrapping_mul(8)
+            .wrapping_add(x.wrapping_mul(4))
+            .wrapping_add(x.wrapping_mul(2))
+            .wrapping_add(x / 4);
+        let mut emulator = Emulator::new(&classes);
+        assert_eq!(emulator.run("Test.run", 10_000), expected, "x = {}", x);
+    }
+}
+
+#[test]
+fn test_aliasing_store() {
+    // The array may point to the current object, so `field0` isn't read again after the store
+    let mut function = Function {
+        name: "Main.f".to_string(),
+        kind: SubroutineKind::Method,
+        args: 2,
+        locals: 0,
+        temps: 2,
+        blocks: vec![Block {
+            insts: vec![
+                Inst::Copy {
+                    dst: Var::Temp(0),
+                    src: Operand::Var(Var::Field(0)),
+                },
+                Inst::Store {
+                    address: Operand::Var(Var::Argument(1)),
+                    src: Operand::Const(5),
+                },
+                Inst::Binary {
+                    dst: Var::Temp(1),
+                    op: BinaryOp::Add,
+                    lhs: Operand::Var(Var::Temp(0)),
+                    rhs: Operand::Const(1),
+                },
+            ],
+            spans: vec![Span::default(); 3],
+            terminator: Terminator::Return(Operand::Var(Var::Temp(1))),
+            terminator_span: Span::default(),
+        }],
+        span: Span::default(),
+    };
+    let unoptimized = function.clone();
+    ir::optimize(&mut function);
+    assert_eq!(function, unoptimized);
+}
+
+#[test]
+fn test_programs() {
+    for optimize in [false, true] {
+        let options = Options {
+            optimize,
+            ..Options::default()
+        };
+        let mut emulator = Emulator::new(&compile_program("ArrayTest", &options));
+        emulator.input.extend([4, 10, 20, 33, -3]);
+        emulator.run("Main.main", 100_000);
+        assert!(emulator.output.ends_with("THE AVERAGE IS: 15\n"));
+
+        let mut emulator = Emulator::new(&compile_program("Square", &options));
+        emulator.keys.extend([132, 0, 0, 0, 81]);
+        emulator.run("Main.main", 1_000_000);
+        assert_eq!(emulator.screen.len(), 22);
+        assert_eq!(emulator.screen[21], "drawRectangle(39, 0, 40, 30)");
+    }
+
+    // Square's `Main.more` has an `if (false)`
+    let unoptimized = instruction_count(&compile_program("Square", &Options::default()));
+    let optimized = instruction_count(&compile_program("Square", &optimized()));
+    assert!(optimized < unoptimized, "{} >= {}", optimized, unoptimized);
+}
@@ -0,0 +1,268 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::codegen::{self, Compat, Options};
+use jack_compiler::vm::{self, Instruction};
+use std::fs;
+use utils::parse_class;
+
+fn vm_text(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+fn optimize(source: &str) -> (String, Vec<(String, usize)>) {
+    let optimized = vm::peephole(&vm::parse(source).unwrap());
+    (vm_text(&optimized.instructions), optimized.removed)
+}
+
+#[test]
+fn test_rewrites() {
+    let source = "\
+// Hand-written code
+function Main.main 1
+push local 0
+pop local 0
+push argument 0
+push constant 0
+add
+not
+not
+push constant 1
+eq
+if-goto TRUE
+goto FALSE
+label TRUE
+push constant 3
+goto END   // to the next label but one
+label FALSE
+label END
+return
+
+function Main.negated 0
+push argument 0
+push constant 1
+gt
+not
+if-goto TRUE
+goto FALSE
+label TRUE
+push constant 1
+return
+label FALSE
+push constant 0
+return
+";
+    let expected = "\
+function Main.main 1
+push argument 0
+push constant 1
+eq
+not
+if-goto FALSE
+label TRUE
+push constant 3
+label FALSE
+label END
+return
+function Main.negated 0
+push argument 0
+push constant 1
+gt
+if-goto FALSE
+label TRUE
+push constant 1
+return
+label FALSE
+push constant 0
+return
+";
+    let (code, removed) = optimize(source);
+    assert_eq!(code, expected);
+    assert_eq!(
+        removed,
+        [
+            ("Main.main".to_string(), 7),
+            ("Main.negated".to_string(), 2)
+        ]
+    );
+}
+
+#[test]
+fn test_kept_code() {
+    // Any value but 0 is true, so the condition can't be negated with `not`
+    let source = "\
+function Main.main 0
+push argument 0
+if-goto TRUE
+goto FALSE
+label TRUE
+push local 0
+pop local 1
+push constant 1
+add
+goto END
+label FALSE
+push constant 0
+label END
+return
+function Main.other 0
+label END
+push constant 0
+return
+";
+    let (code, removed) = optimize(source);
+    assert_eq!(code, source);
+    assert_eq!(
+        removed,
+        [("Main.main".to_string(), 0), ("Main.other".to_string(), 0)]
+    );
+}
+
+#[test]
+fn test_parse() {
+    let source = fs::read_to_string("tests/programs/Square/Square.jack").unwrap();
+    let code = codegen::compile_class(&parse_class(&source), &Options::default()).unwrap();
+    let text = vm_text(&code.instructions);
+    assert_eq!(vm::parse(&text).unwrap(), code.instructions);
+
+    let errors = [
+        (
+            "function Main.main 0\npush local\n",
+            "line 2: `push` takes 2 operands, found 1",
+        ),
+        ("jump END\n", "line 1: unknown command `jump`"),
+        (
+            "\n\npop constant 1",
+            "line 3: can't pop to segment `constant`",
+        ),
+        ("push heap 0", "line 1: unknown segment `heap`"),
+        (
+            "push constant 32768",
+            "line 1: index 32768 is out of range for segment `constant`, which goes up to 32767",
+        ),
+        (
+            "push temp -1",
+            "line 1: expected a non-negative number, found `-1`",
+        ),
+        ("label 1ABC", "line 1: invalid symbol `1ABC`"),
+        ("return 0", "line 1: `return` takes 0 operands, found 1"),
+    ];
+    for (source, message) in errors {
+        assert_eq!(vm::parse(source).unwrap_err().to_string(), message);
+    }
+}
+
+#[test]
+fn test_compiled_programs() {
+    // The official compiler's `if` statements jump over a `goto`
+    let options = Options {
+        compat: Some(Compat::Official),
+        ..Options::default()
+    };
+    let mut before = 0;
+    let mut after = 0;
+    let classes: Vec<Vec<Instruction>> = ["Main", "Square", "SquareGame"]
+        .iter()
+        .map(|class| {
+            let path = format!("tests/programs/Square/{}.jack", class);
+            let source = fs::read_to_string(path).unwrap();
+            let mut code = codegen::compile_class(&parse_class(&source), &options).unwrap();
+            before += code.instructions.len();
+            let removed: usize = code.peephole().iter().map(|(_, count)| count).sum();
+            after += code.instructions.len();
+            assert_eq!(code.origins.len(), code.instructions.len());
+            assert_eq!(before - after, removed);
+            code.instructions
+        })
+        .collect();
+    assert!(after < before, "{} >= {}", after, before);
+
+    let mut emulator = Emulator::new(&classes);
+    emulator.keys.extend([132, 0, 0, 0, 81]);
+    emulator.run("Main.main", 1_000_000);
+    assert_eq!(
+        emulator.screen.last().unwrap(),
+        "drawRectangle(39, 0, 40, 30)"
+    );
+}
+
+#[test]
+fn test_annotations() {
+    let source = "class Main {
+    function int main(int x) {
+        var int y;
+        let y = ~~x;
+        let y = y + 0;
+        return y;
+    }
+}";
+    let mut code = codegen::compile_class(&parse_class(source), &Options::default()).unwrap();
+    assert_eq!(code.peephole(), [("Main.main".to_string(), 6)]);
+    let mut out = Vec::new();
+    code.write_annotated(&mut out, "Main.jack", source).unwrap();
+    // Nothing is left of the second statement
+    let expected = "\
+function Main.main 1
+// Main.jack:4: let y = ~~x;
+push argument 0
+pop local 0
+// Main.jack:5: let y = y + 0;
+// Main.jack:6: return y;
+push local 0
+return
+";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
+
+#[test]
+fn test_cli() {
+    let dir = std::env::temp_dir().join(format!("jack-peephole-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("Main.jack");
+    fs::write(
+        &input,
+        "class Main {
+    function int main(int x) {
+        var int y;
+        let y = ~~x;
+        return y;
+    }
+}",
+    )
+    .unwrap();
+    let run = |format: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_jack-compiler"))
+            .args(["--peephole", "-f", format, "-o"])
+            .arg(dir.join("Main.out"))
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+    let result = run("vm");
+    assert!(result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("Main.main: 2 instructions removed\n"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.ends_with("2 instructions removed in total\n"),
+        "{}",
+        stderr
+    );
+
+    let result = run("asm");
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(result.status.code(), Some(2));
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("`--peephole` rewrites VM code, and can't be combined with `-f asm`"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,93 @@
+mod utils;
+
+use jack_compiler::analysis::precedence;
+use jack_compiler::diagnostics::{Applicability, Diagnostic};
+use utils::parse_class;
+
+/// Checks `expr` as a return value, and returns the source along with the diagnostics.
+fn check(expr: &str) -> (String, Vec<Diagnostic>) {
+    let source = format!("class Main {{ function int f() {{ return {}; }} }}", expr);
+    let class = parse_class(&source);
+    let mut diagnostics = Vec::new();
+    precedence::check_class(&class, &mut diagnostics);
+    (source, diagnostics)
+}
+
+fn fixes(expr: &str) -> Vec<(String, Applicability)> {
+    let (source, diagnostics) = check(expr);
+    assert_eq!(diagnostics.len(), 1);
+    let diagnostic = &diagnostics[0];
+    diagnostic
+        .suggestions
+        .iter()
+        .map(|s| (s.apply(&source, diagnostic.span), s.applicability))
+        .collect()
+}
+
+#[test]
+fn test_mixed_arithmetic() {
+    assert_eq!(
+        fixes("1 + 2 * 3"),
+        [
+            ("(1 + 2) * 3".to_string(), Applicability::MachineApplicable),
+            ("1 + (2 * 3)".to_string(), Applicability::MaybeIncorrect),
+        ]
+    );
+}
+
+#[test]
+fn test_and_with_comparisons() {
+    assert_eq!(
+        fixes("x < 5 & y > 3"),
+        [
+            (
+                "(x < 5 & y) > 3".to_string(),
+                Applicability::MachineApplicable
+            ),
+            ("x < 5 & (y > 3)".to_string(), Applicability::MaybeIncorrect),
+        ]
+    );
+}
+
+#[test]
+fn test_several_ambiguities() {
+    assert_eq!(
+        fixes("a + b * c + d * e"),
+        [
+            (
+                "((a + b) * c + d) * e".to_string(),
+                Applicability::MachineApplicable
+            ),
+            (
+                "a + (b * c) + (d * e)".to_string(),
+                Applicability::MaybeIncorrect
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_label_points_at_prefix() {
+    let (source, diagnostics) = check("a - b / c");
+    let span = diagnostics[0].labels[0].span;
+    assert_eq!(&source[span.start..span.end], "a - b");
+}
+
+#[test]
+fn test_unambiguous() {
+    for expr in [
+        "a * b + c",
+        "a + b - c",
+        "(a + b) * c",
+        "a + (b * c)",
+        "x < 5 & (y > 3)",
+    ] {
+        assert!(check(expr).1.is_empty(), "{}", expr);
+    }
+}
+
+#[test]
+fn test_nested_expression() {
+    let (_, diagnostics) = check("f(1 + 2 * 3) + a[x | y = z]");
+    assert_eq!(diagnostics.len(), 2);
+}
@@ -0,0 +1,64 @@
+mod utils;
+
+use jack_compiler::analysis::{OS_CLASSES, shadowing};
+use jack_compiler::diagnostics::Diagnostic;
+use std::collections::HashSet;
+use utils::parse_class;
+
+fn check(source: &str) -> Vec<Diagnostic> {
+    let class = parse_class(source);
+    let class_names: HashSet<&str> = OS_CLASSES.into_iter().chain(["Main"]).collect();
+    let mut diagnostics = Vec::new();
+    shadowing::check_class(&class, &class_names, &mut diagnostics);
+    diagnostics
+}
+
+#[test]
+fn test_local_shadows_field() {
+    let source = "class Main { field int x; method void f() { var int x; let x = 1; return; } }";
+    let diagnostics = check(source);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "local variable `x` shadows field `x`"
+    );
+    // Points at both declarations
+    assert_eq!(
+        diagnostics[0].span.start,
+        source.find("var int x").unwrap() + 8
+    );
+    assert_eq!(
+        diagnostics[0].labels[0].span.start,
+        source.find("x;").unwrap()
+    );
+}
+
+#[test]
+fn test_parameter_shadows_static() {
+    let diagnostics =
+        check("class Main { static int n; function void f(int n) { let n = 1; return; } }");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "parameter `n` shadows static variable `n`"
+    );
+}
+
+#[test]
+fn test_local_shadows_class_in_call() {
+    let diagnostics = check(
+        "class Main { function void f() { var Foo Output; do Output.printInt(1); return; } }",
+    );
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "local variable `Output` shadows the class `Output` in this call"
+    );
+}
+
+#[test]
+fn test_method_call_through_variable() {
+    let diagnostics =
+        check("class Main { function void f() { var Game game; do game.run(); return; } }");
+    assert!(diagnostics.is_empty());
+}
@@ -0,0 +1,102 @@
+mod utils;
+
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::source_map::SourceMap;
+use jack_compiler::vm::{ArithmeticOp, Instruction, Segment};
+use utils::parse_class;
+
+const SOURCE: &str = "class Main {
+    function int f(int x) {
+        return x + 1;
+    }
+
+    method void g() {
+        do Main.f(2);
+        return;
+    }
+}";
+
+fn source_map() -> (Vec<Instruction>, SourceMap) {
+    let class = parse_class(SOURCE);
+    let code = codegen::compile_class(&class, &Options::default()).unwrap();
+    let source_map = SourceMap::new(&code, "Main.jack", SOURCE);
+    (code.instructions, source_map)
+}
+
+/// Returns the Jack code the instruction at `index` comes from.
+fn origin_text(source_map: &SourceMap, index: usize) -> &'static str {
+    let (origin, _) = source_map.lookup(index).unwrap();
+    &SOURCE[origin.span.start..origin.span.end]
+}
+
+#[test]
+fn test_origins() {
+    let (instructions, source_map) = source_map();
+    assert_eq!(source_map.instructions.len(), instructions.len());
+    assert_eq!(source_map.file, "Main.jack");
+
+    let names: Vec<&str> = source_map
+        .subroutines
+        .iter()
+        .map(|subroutine| subroutine.name.as_str())
+        .collect();
+    assert_eq!(names, ["Main.f", "Main.g"]);
+    assert_eq!(source_map.subroutines[1].line, 6);
+
+    // function Main.f 0
+    let (origin, subroutine) = source_map.lookup(0).unwrap();
+    assert_eq!(subroutine.name, "Main.f");
+    assert!(origin_text(&source_map, 0).starts_with("function int f(int x) {"));
+    assert_eq!((origin.line, origin.column), (2, 5));
+
+    // push argument 0; push constant 1; add; return. Variables and constants are pushed by the
+    // operation reading them.
+    assert_eq!(instructions[1], Instruction::Push(Segment::Argument, 0));
+    assert_eq!(origin_text(&source_map, 1), "x + 1");
+    assert_eq!(origin_text(&source_map, 2), "x + 1");
+    assert_eq!(instructions[3], Instruction::Arithmetic(ArithmeticOp::Add));
+    assert_eq!(origin_text(&source_map, 3), "x + 1");
+    assert_eq!(origin_text(&source_map, 4), "return x + 1;");
+    let (origin, _) = source_map.lookup(4).unwrap();
+    assert_eq!((origin.line, origin.column), (3, 9));
+
+    // The method prologue belongs to the method
+    assert!(matches!(instructions[5], Instruction::Function { .. }));
+    for index in 5..instructions.len() {
+        let (_, subroutine) = source_map.lookup(index).unwrap();
+        assert_eq!(subroutine.name, "Main.g");
+    }
+    assert!(origin_text(&source_map, 6).starts_with("method void g()"));
+    assert_eq!(origin_text(&source_map, 9), "do Main.f(2);");
+    assert!(source_map.lookup(instructions.len()).is_none());
+}
+
+#[test]
+fn test_json_round_trip() {
+    let (_, source_map) = source_map();
+    let json = source_map.to_json();
+    assert_eq!(SourceMap::from_json(&json).unwrap(), source_map);
+}
+
+#[test]
+fn test_invalid_json() {
+    let error = SourceMap::from_json(r#"{"version": 2}"#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid source map: unsupported version 2"
+    );
+
+    let json = r#"{"version": 1, "file": "Main.jack", "subroutines": [],
+        "instructions": [{"subroutine": 0, "start": 0, "end": 1, "line": 1, "column": 1}]}"#;
+    let error = SourceMap::from_json(json).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid source map: unknown subroutine 0"
+    );
+
+    let error = SourceMap::from_json(r#"{"version": 1}"#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid source map: missing or invalid `file`"
+    );
+}
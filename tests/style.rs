@@ -0,0 +1,98 @@
+mod utils;
+
+use jack_compiler::analysis::style;
+use jack_compiler::diagnostics::Diagnostic;
+use jack_compiler::lint::LintOptions;
+use utils::parse_class;
+
+fn notes(diagnostics: &[Diagnostic]) -> Vec<&str> {
+    diagnostics.iter().map(|d| d.notes[0].as_str()).collect()
+}
+
+#[test]
+fn test_naming() {
+    let class = parse_class(
+        "class square_game {
+            static int MAX_SIZE;
+            field int _size;
+            method void Draw(int Ax, int _unused) { var int my_var; return; }
+            method void drawAll() { return; }
+        }",
+    );
+    let mut diagnostics = Vec::new();
+    style::check_names(&class, &mut diagnostics);
+    assert_eq!(
+        notes(&diagnostics),
+        [
+            "rename it to `SquareGame`",
+            "rename it to `maxSize`",
+            "rename it to `draw`",
+            "rename it to `ax`",
+            "rename it to `myVar`",
+        ]
+    );
+    assert_eq!(diagnostics[0].code, Some(style::CLASS_NAME_CASE));
+    assert_eq!(diagnostics[2].code, Some(style::SUBROUTINE_NAME_CASE));
+    assert_eq!(diagnostics[3].code, Some(style::VARIABLE_NAME_CASE));
+}
+
+#[test]
+fn test_upper_case_class_name() {
+    let mut diagnostics = Vec::new();
+    style::check_names(&parse_class("class FOO { }"), &mut diagnostics);
+    assert_eq!(notes(&diagnostics), ["rename it to `Foo`"]);
+    assert_eq!(diagnostics[0].code, Some(style::CLASS_NAME_CASE));
+
+    // Single letters and names with a lowercase letter are fine
+    for source in ["class A { }", "class Io { }", "class ABTest { }"] {
+        let mut diagnostics = Vec::new();
+        style::check_names(&parse_class(source), &mut diagnostics);
+        assert!(diagnostics.is_empty(), "{}", source);
+    }
+}
+
+#[test]
+fn test_long_subroutine() {
+    let class = parse_class(
+        "class Main {
+            function void f() { if (x) { let a = 1; } else { let a = 2; } return; }
+            function void g() { return; }
+        }",
+    );
+    let mut diagnostics = Vec::new();
+    style::check_length(&class, 3, &mut diagnostics);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "subroutine `f` has 4 statements, more than the limit of 3"
+    );
+}
+
+#[test]
+fn test_deep_nesting() {
+    let source = "class Main {
+        function void f() {
+            while (x) { if (y) { while (z) { let a = 1; } } }
+            return;
+        }
+    }";
+    let class = parse_class(source);
+
+    let mut diagnostics = Vec::new();
+    style::check_nesting(&class, 3, &mut diagnostics);
+    assert!(diagnostics.is_empty());
+
+    style::check_nesting(&class, 2, &mut diagnostics);
+    assert_eq!(diagnostics.len(), 1);
+    let span = diagnostics[0].span;
+    assert!(source[span.start..span.end].starts_with("while (z)"));
+}
+
+#[test]
+fn test_options() {
+    let mut options = LintOptions::default();
+    options.set("max-nesting", "2").unwrap();
+    assert_eq!(options.max_nesting, 2);
+    assert!(options.set("max-nesting", "two").is_err());
+    assert!(options.set("max-width", "80").is_err());
+}
@@ -0,0 +1,287 @@
+mod cpu;
+mod mini_os;
+mod utils;
+
+use cpu::Cpu;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::hack::Asm;
+use jack_compiler::{ir, vm};
+use mini_os::{OS, RESULT};
+use std::fs;
+use utils::parse_class;
+
+/// Translates VM code without a bootstrap, and runs it with the given pointers, starting with
+/// `SP`.
+fn run_vm(source: &str, pointers: &[i16]) -> Cpu {
+    let mut asm = Asm::new();
+    vm::translate("Test", &vm::parse(source).unwrap(), &mut asm);
+    let mut cpu = Cpu::new(&asm.lines);
+    cpu.ram[..pointers.len()].copy_from_slice(pointers);
+    assert!(cpu.run(10_000));
+    cpu
+}
+
+#[test]
+fn test_arithmetic() {
+    let source = "\
+push constant 17
+push constant 17
+eq
+push constant 17
+push constant 16
+eq
+push constant 892
+push constant 891
+lt
+push constant 891
+push constant 892
+lt
+push constant 32767
+push constant 32766
+gt
+push constant 57
+push constant 31
+push constant 53
+add
+push constant 112
+sub
+neg
+and
+push constant 82
+or
+not
+";
+    let cpu = run_vm(source, &[256]);
+    assert_eq!(cpu.ram[0], 262);
+    assert_eq!(&cpu.ram[256..262], [-1, 0, 0, -1, -1, -91]);
+}
+
+#[test]
+fn test_segments() {
+    let source = "\
+push constant 10
+pop local 0
+push constant 21
+push constant 22
+pop argument 2
+pop argument 1
+push constant 36
+pop this 6
+push constant 42
+push constant 45
+pop that 5
+pop that 2
+push constant 510
+pop temp 6
+push local 0
+push that 5
+add
+push argument 1
+sub
+push this 6
+push this 6
+add
+sub
+push temp 6
+add
+push constant 3030
+pop pointer 0
+push constant 3040
+pop pointer 1
+push constant 7
+pop static 3
+push pointer 0
+push pointer 1
+add
+push static 3
+add
+";
+    // SP, LCL, ARG, THIS and THAT
+    let cpu = run_vm(source, &[256, 300, 400, 3000, 3010]);
+    assert_eq!(cpu.ram[0], 258);
+    assert_eq!(cpu.ram[256], 472);
+    assert_eq!(cpu.ram[257], 3030 + 3040 + 7);
+    assert_eq!(cpu.ram[300], 10);
+    assert_eq!(&cpu.ram[401..403], [21, 22]);
+    assert_eq!(cpu.ram[3006], 36);
+    assert_eq!(cpu.ram[3012], 42);
+    assert_eq!(cpu.ram[3015], 45);
+    assert_eq!(cpu.ram[11], 510);
+    assert_eq!(cpu.ram[16], 7);
+}
+
+#[test]
+fn test_functions() {
+    // `Sys.init` sums 1 to 10 through a loop and a recursive function, and stores the results
+    let sys = "\
+function Sys.init 1
+push constant 10
+call Sys.loop 1
+pop local 0
+push constant 10
+call Sys.sum 1
+push local 0
+add
+pop static 0
+label END
+goto END
+";
+    let sum = "\
+function Sys.loop 1
+label LOOP
+push argument 0
+if-goto BODY
+push local 0
+return
+label BODY
+push local 0
+push argument 0
+add
+pop local 0
+push argument 0
+push constant 1
+sub
+pop argument 0
+goto LOOP
+function Sys.sum 0
+push argument 0
+push constant 0
+eq
+not
+if-goto RECURSE
+push constant 0
+return
+label RECURSE
+push argument 0
+push argument 0
+push constant 1
+sub
+call Sys.sum 1
+add
+return
+";
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    vm::translate("Sys", &vm::parse(sys).unwrap(), &mut asm);
+    vm::translate("Sum", &vm::parse(sum).unwrap(), &mut asm);
+    assert!(asm.lines.contains(&"(Sys.loop$LOOP)".to_string()));
+    let mut cpu = Cpu::new(&asm.lines);
+    assert!(cpu.run(100_000));
+    assert_eq!(cpu.ram[16], 110);
+    // The bootstrap frame and the local of `Sys.init`
+    assert_eq!(cpu.ram[0], 262);
+}
+
+#[test]
+fn test_compiled_programs() {
+    let main = "class Main {
+    static int count;
+
+    function int main() {
+        var Array a;
+        var int i, sum;
+        var String s;
+        let a = Array.new(5);
+        while (i < 5) {
+            let a[i] = Main.square(i);
+            let i = i + 1;
+        }
+        let i = 0;
+        while (i < 5) {
+            let sum = sum + a[i];
+            let i = i + 1;
+        }
+        let s = \"abc\";
+        return sum + count + s.length();
+    }
+
+    function int square(int n) {
+        let count = count + 1;
+        return n * n;
+    }
+}";
+    let classes: Vec<_> = OS
+        .iter()
+        .chain([&main])
+        .map(|source| parse_class(source))
+        .collect();
+    let vm_code: Vec<_> = codegen::compile_program(&classes, &Options::default())
+        .into_iter()
+        .map(|result| result.unwrap().instructions)
+        .collect();
+    // 0 + 1 + 4 + 9 + 16, 5 calls and 3 characters
+    let expected = 30 + 5 + 3;
+
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    for (class, code) in classes.iter().zip(&vm_code) {
+        vm::translate(class.name.name, code, &mut asm);
+    }
+    let mut cpu = Cpu::new(&asm.lines);
+    assert!(cpu.run(1_000_000));
+    assert_eq!(cpu.ram[RESULT], expected);
+
+    // The OS translated from VM code is called from code generated from the IR, and the other
+    // way around
+    let mut asm = Asm::new();
+    asm.bootstrap();
+    for (index, class) in classes.iter().enumerate() {
+        if class.name.name == "Main" {
+            for function in ir::lower_class(class, &Options::default()).unwrap() {
+                ir::to_asm(&function, &mut asm);
+            }
+        } else {
+            vm::translate(class.name.name, &vm_code[index], &mut asm);
+        }
+    }
+    let mut cpu = Cpu::new(&asm.lines);
+    assert!(cpu.run(1_000_000));
+    assert_eq!(cpu.ram[RESULT], expected);
+}
+
+#[test]
+fn test_cli() {
+    let dir = std::env::temp_dir().join(format!("jack-translate-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("Sys.vm"),
+        "function Sys.init 0\npush constant 6\npush constant 7\nadd\npop static 0\nlabel END\ngoto END\n",
+    )
+    .unwrap();
+    let output = dir.join("out.asm");
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_jack-compiler"))
+            .arg("translate")
+            .args(args)
+            .arg("-o")
+            .arg(&output)
+            .arg(&dir)
+            .output()
+            .unwrap()
+    };
+    assert!(run(&[]).status.success());
+    let lines: Vec<String> = fs::read_to_string(&output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(&lines[..4], ["@256", "D=A", "@SP", "M=D"]);
+    let mut cpu = Cpu::new(&lines);
+    assert!(cpu.run(10_000));
+    assert_eq!(cpu.ram[16], 13);
+
+    assert!(run(&["--no-bootstrap"]).status.success());
+    let asm = fs::read_to_string(&output).unwrap();
+    assert!(asm.starts_with("(Sys.init)\n"), "{}", asm);
+
+    fs::write(dir.join("Bad.vm"), "push constant 1\npop constant 0\n").unwrap();
+    let result = run(&[]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!result.status.success());
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("Bad.vm: line 2: can't pop to segment `constant`"),
+        "{}",
+        stderr
+    );
+}
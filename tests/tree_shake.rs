@@ -0,0 +1,148 @@
+mod emulator;
+mod utils;
+
+use emulator::Emulator;
+use jack_compiler::codegen::{self, Options};
+use jack_compiler::tree_shake::{call_graph, tree_shake};
+use jack_compiler::vm::Instruction;
+use std::fs;
+use utils::parse_class;
+
+const PROGRAM: [&str; 3] = [
+    "class Main {
+    function void main() {
+        var Counter counter;
+        let counter = Counter.new();
+        do counter.add(2 * 3);
+        do Main.loop(1);
+        return;
+    }
+
+    function void loop(int n) {
+        if (n > 0) {
+            do Main.loop(n - 1);
+        }
+        return;
+    }
+
+    function void unused() {
+        do Main.alsoUnused();
+        return;
+    }
+
+    function void alsoUnused() {
+        do Main.unused();
+        return;
+    }
+}",
+    "class Counter {
+    field int count;
+
+    constructor Counter new() {
+        return this;
+    }
+
+    method void add(int n) {
+        let count = count + n;
+        do print();
+        return;
+    }
+
+    method void print() {
+        do Output.printString(\"count\");
+        return;
+    }
+
+    method int get() {
+        return count / 2;
+    }
+}",
+    "class Math {
+    function int multiply(int x, int y) {
+        return 0;
+    }
+
+    function int divide(int x, int y) {
+        return 0;
+    }
+
+    function int sqrt(int x) {
+        return 0;
+    }
+}",
+];
+
+#[test]
+fn test_call_graph() {
+    let program: Vec<_> = PROGRAM.iter().map(|source| parse_class(source)).collect();
+    let graph = call_graph(&program);
+    let callees = |name: &str| graph[name].join(", ");
+    assert_eq!(
+        callees("Main.main"),
+        "Counter.new, Math.multiply, Counter.add, Main.loop"
+    );
+    assert_eq!(callees("Main.loop"), "Main.loop");
+    assert_eq!(callees("Counter.new"), "Memory.alloc");
+    assert_eq!(callees("Counter.add"), "Counter.print");
+    assert_eq!(
+        callees("Counter.print"),
+        "String.new, String.appendChar, Output.printString"
+    );
+    assert_eq!(callees("Counter.get"), "Math.divide");
+    assert_eq!(callees("Math.sqrt"), "");
+    assert_eq!(graph.len(), 11);
+}
+
+#[test]
+fn test_tree_shake() {
+    let mut program: Vec<_> = PROGRAM.iter().map(|source| parse_class(source)).collect();
+    assert_eq!(
+        tree_shake(&mut program, false),
+        ["Main.unused", "Main.alsoUnused", "Counter.get"]
+    );
+    let sizes: Vec<_> = program
+        .iter()
+        .map(|class| class.subroutines.len())
+        .collect();
+    assert_eq!(sizes, [2, 3, 3]);
+
+    let mut program: Vec<_> = PROGRAM.iter().map(|source| parse_class(source)).collect();
+    assert_eq!(
+        tree_shake(&mut program, true),
+        [
+            "Main.unused",
+            "Main.alsoUnused",
+            "Counter.get",
+            "Math.divide",
+            "Math.sqrt"
+        ]
+    );
+}
+
+#[test]
+fn test_square_program() {
+    let sources: Vec<String> = ["Main", "Square", "SquareGame"]
+        .iter()
+        .map(|class| fs::read_to_string(format!("tests/programs/Square/{}.jack", class)).unwrap())
+        .collect();
+    let mut program: Vec<_> = sources.iter().map(|source| parse_class(source)).collect();
+    assert_eq!(tree_shake(&mut program, false), ["Main.more"]);
+
+    let classes: Vec<Vec<Instruction>> = program
+        .iter()
+        .map(|class| {
+            codegen::compile_class(class, &Options::default())
+                .unwrap()
+                .instructions
+        })
+        .collect();
+    assert!(
+        !classes[0]
+            .iter()
+            .any(|instruction| instruction.to_string().starts_with("function Main.more"))
+    );
+    let mut emulator = Emulator::new(&classes);
+    emulator.keys.extend([132, 0, 0, 0, 81]);
+    emulator.run("Main.main", 1_000_000);
+    assert_eq!(emulator.screen.len(), 22);
+}
@@ -0,0 +1,58 @@
+mod utils;
+
+use jack_compiler::analysis::unused;
+use jack_compiler::diagnostics::Diagnostic;
+use utils::parse_class;
+
+fn check(source: &str) -> Vec<Diagnostic> {
+    let class = parse_class(source);
+    let mut diagnostics = Vec::new();
+    unused::check_class(&class, &mut diagnostics);
+    diagnostics
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+    diagnostics.iter().map(|d| d.message.as_str()).collect()
+}
+
+#[test]
+fn test_unused_local_and_parameter() {
+    let diagnostics =
+        check("class Main { function int f(int a, int b) { var int x, y; let x = a; return x; } }");
+    assert_eq!(
+        messages(&diagnostics),
+        ["unused parameter `b`", "unused local variable `y`"]
+    );
+}
+
+#[test]
+fn test_underscore_parameter() {
+    let diagnostics = check("class Main { function void f(int _a) { var int _x; return; } }");
+    assert_eq!(messages(&diagnostics), ["unused local variable `_x`"]);
+}
+
+#[test]
+fn test_call_prefix_is_a_reference() {
+    let diagnostics = check("class Main { function void f(Game g) { do g.run(); return; } }");
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_unused_class_variables() {
+    let diagnostics = check(
+        "class Main {
+            static int count;
+            field int x, y;
+            method int getX() { return x; }
+            function void reset() { let count = 0; return; }
+        }",
+    );
+    assert_eq!(messages(&diagnostics), ["unused field `y`"]);
+}
+
+#[test]
+fn test_shadowed_field_is_unused() {
+    let diagnostics =
+        check("class Main { field int x; method int f() { var int x; let x = 1; return x; } }");
+    assert_eq!(messages(&diagnostics), ["unused field `x`"]);
+}
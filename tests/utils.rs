@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use jack_compiler::token::{Keyword, Symbol};
+use jack_compiler::{ast, lexer, parser};
 
 pub fn keyword_to_literal(kw: &Keyword) -> &'static str {
     use Keyword::*;
@@ -55,3 +56,11 @@ pub fn symbol_to_literal(sym: &Symbol) -> &'static str {
         Tilde => "~",
     }
 }
+
+pub fn parse_class(source: &str) -> ast::Class<'_> {
+    let lex = lexer::Lexer::new(source);
+    let parser = parser::ClassParser::new();
+    parser
+        .parse(source, lex)
+        .unwrap_or_else(|e| panic!("error occurs while parsing: {:?}", e))
+}